@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use mineral::storage::serve::Serve;
+use mineral::storage::wal::Wal;
+use mineral::{HashKv, KvConfig, StorageConfig};
+
+const BENCH_PATH: &str = "/tmp/wtfs/benches/write-amp";
+const WRITES: usize = 10000;
+const VALUE_SIZE: usize = 100;
+// 旧版本每 5 秒将 kv 层变更缓冲刷入存储层，这里按写入次数模拟一个刷盘周期
+const FLUSH_EVERY: usize = 1000;
+
+// 负载：槽位数及第 i 次写入的 key
+struct Workload {
+    name: &'static str,
+    slots: u32,
+    key: fn(usize) -> Vec<u8>,
+}
+
+const WORKLOADS: [Workload; 3] = [
+    // 每次写入不同的 key，几乎没有可合并的槽位
+    Workload { name: "unique", slots: 65536, key: unique_key },
+    // 1024 个 key 集中在 16 个槽位中，槽位较大
+    Workload { name: "hot slot", slots: 16, key: hot_slot_key },
+    // 80% 的写入落在 64 个热点 key 上，其余分散
+    Workload { name: "mixed", slots: 65536, key: mixed_key },
+];
+
+fn unique_key(i: usize) -> Vec<u8> {
+    format!("key-{}", i).into_bytes()
+}
+
+fn hot_slot_key(i: usize) -> Vec<u8> {
+    format!("key-{}", i % 1024).into_bytes()
+}
+
+fn mixed_key(i: usize) -> Vec<u8> {
+    // 线性同余生成器，保证两种写入路径的 key 序列一致
+    let r = (i as u64).wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407) >> 33;
+    if r % 10 < 8 {
+        format!("hot-{}", r % 64).into_bytes()
+    } else {
+        format!("key-{}", r % 65536).into_bytes()
+    }
+}
+
+fn get_conf(path: &str, slots: u32) -> KvConfig {
+    KvConfig {
+        storage: StorageConfig {
+            path: format!("{}/data", path),
+            block_size: 1024,
+            page_max_cap: 1024 * 1024 * 50,
+            ..Default::default()
+        },
+        cache_cap: 1024,
+        slot_qty: slots,
+        ..Default::default()
+    }
+}
+
+// 与 HashKv 相同的槽位计算方式
+fn slot_index(key: &[u8], slots: u32) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.to_vec().hash(&mut hasher);
+    let hash_code = hasher.finish();
+    ((hash_code >> 18) ^ (hash_code & 0x3FFFF)) as usize & (slots as usize - 1)
+}
+
+// 旧版本 kv 层预写日志条目格式：expires-at | keylen | key | val
+fn legacy_entry(key: &[u8], val: &[u8]) -> Vec<u8> {
+    let mut buf = 0u64.to_be_bytes().to_vec();
+    buf.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    buf.extend_from_slice(key);
+    buf.extend_from_slice(val);
+    buf
+}
+
+// 旧版本槽位格式：total-len | expires-at | keylen | key | val
+fn legacy_slot(entries: &BTreeMap<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+    let mut buf = vec![];
+    for (key, val) in entries {
+        buf.extend_from_slice(&((key.len() + val.len() + 20) as u64).to_be_bytes());
+        buf.extend_from_slice(&legacy_entry(key, val));
+    }
+    buf
+}
+
+// 旧版本写入路径：每次写入追加 kv 层预写日志并更新 kv 层变更缓冲中的槽位，
+// 刷盘周期到达时将缓冲中的槽位整体写入存储层，再由存储层写入自己的预写日志
+struct Legacy {
+    kv_wal: Wal,
+    store: Serve,
+    slots: u32,
+    data: HashMap<usize, BTreeMap<Vec<u8>, Vec<u8>>>,
+    dirty: HashSet<usize>,
+    writes: usize,
+}
+
+impl Legacy {
+    fn new(path: &str, slots: u32) -> Self {
+        Legacy {
+            kv_wal: Wal::new(&format!("{}/log", path)),
+            store: Serve::new(get_conf(path, slots).storage),
+            slots,
+            data: HashMap::new(),
+            dirty: HashSet::new(),
+            writes: 0,
+        }
+    }
+
+    fn set(&mut self, key: &[u8], val: &[u8]) {
+        self.kv_wal.append(&legacy_entry(key, val)).unwrap();
+
+        let slot_no = slot_index(key, self.slots);
+        self.data.entry(slot_no).or_default().insert(key.to_vec(), val.to_vec());
+        self.dirty.insert(slot_no);
+
+        self.writes += 1;
+        if self.writes.is_multiple_of(FLUSH_EVERY) {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        for slot_no in self.dirty.drain() {
+            self.store.set(slot_no, legacy_slot(&self.data[&slot_no])).unwrap();
+        }
+    }
+
+    fn wal_bytes(&mut self) -> u64 {
+        self.flush();
+        self.kv_wal.size() + self.store.stats().unwrap().wal_bytes
+    }
+}
+
+// 按负载写入 WRITES 次，返回 (写入的 key/value 字节数, 统一预写日志字节数, 旧版本预写日志字节数)
+fn run(workload: &Workload) -> (u64, u64, u64) {
+    let path = format!("{}/{}", BENCH_PATH, workload.name.replace(' ', "-"));
+    let _ = fs::remove_dir_all(&path);
+
+    let mut kv = HashKv::new(get_conf(&format!("{}/unified", path), workload.slots));
+    let mut legacy = Legacy::new(&format!("{}/legacy", path), workload.slots);
+
+    let val = vec![1u8; VALUE_SIZE];
+    let mut logical = 0;
+    for i in 0..WRITES {
+        let key = (workload.key)(i);
+        kv.set(&key, &val).unwrap();
+        legacy.set(&key, &val);
+        logical += (key.len() + val.len()) as u64;
+    }
+
+    let unified = kv.stats().unwrap().storage.wal_bytes;
+    kv.close().unwrap();
+
+    (logical, unified, legacy.wal_bytes())
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    println!("write amplification ({} writes, {} byte values):", WRITES, VALUE_SIZE);
+    for workload in WORKLOADS.iter() {
+        let (logical, unified, legacy) = run(workload);
+        println!("  {}:", workload.name);
+        println!("    unified wal:       {} bytes, {:.2}x", unified, unified as f64 / logical as f64);
+        println!("    double buffer wal: {} bytes, {:.2}x", legacy, legacy as f64 / logical as f64);
+    }
+
+    let val = vec![1u8; VALUE_SIZE];
+    for workload in WORKLOADS.iter() {
+        let path = format!("{}/bench-{}", BENCH_PATH, workload.name.replace(' ', "-"));
+        let _ = fs::remove_dir_all(&path);
+        let mut kv = HashKv::new(get_conf(&format!("{}/unified", path), workload.slots));
+        let mut legacy = Legacy::new(&format!("{}/legacy", path), workload.slots);
+
+        let mut i = 0;
+        c.bench_function(&format!("unified set {}: {}byte", workload.name, VALUE_SIZE), |b| b.iter(|| {
+            i += 1;
+            kv.set(&(workload.key)(i), &val).unwrap();
+        }));
+
+        let mut i = 0;
+        c.bench_function(&format!("double buffer set {}: {}byte", workload.name, VALUE_SIZE), |b| b.iter(|| {
+            i += 1;
+            legacy.set(&(workload.key)(i), &val);
+        }));
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,5 +1,8 @@
 use serde::Deserialize;
 
+use crate::error::Error;
+use crate::flate::Codec;
+use crate::storage::mainblock::HEADER_SIZE;
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct StorageConfig {
@@ -9,16 +12,128 @@ pub struct StorageConfig {
     pub block_size: usize,
     // 页最大容量
     pub page_max_cap: usize,
+    // 数据块空闲占比达到该值时触发后台整理，0 表示不自动整理
+    #[serde(default)]
+    pub compact_threshold: f64,
+    // 变更缓冲待刷盘字节数的高水位，超过后写入需等待刷盘，0 表示不限制
+    #[serde(default)]
+    pub cbf_high_water: usize,
+    // 超过高水位时写入最多等待的毫秒数，仍未回落则拒绝写入，0 表示直接拒绝
+    #[serde(default)]
+    pub cbf_stall_ms: u64,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct KvConfig {
     pub storage: StorageConfig,
+    // 旧版本 kv 层预写日志路径，打开时回放其中未刷盘的条目，新的写入只经过存储层预写日志
+    #[serde(default)]
     pub wal_path: String,
     // hash lru存储容量
     pub cache_cap: usize,
-    // 变更缓冲容量大小设置
-    pub cbf_cap: usize,
     // hash槽位数量
     pub slot_qty: u32,
-}
\ No newline at end of file
+    // 值压缩阈值，超过该大小的值才压缩，0 表示不压缩
+    #[serde(default)]
+    pub compress_threshold: usize,
+    // 值压缩算法
+    #[serde(default)]
+    pub compress_codec: Codec,
+    // 大值分离阈值，超过该大小的值写入值日志，槽位只保存指针，0 表示不分离
+    #[serde(default)]
+    pub vlog_threshold: usize,
+    // 值日志单个段文件大小上限，0 使用默认值
+    #[serde(default)]
+    pub vlog_segment_size: u64,
+    // 值日志段中失效数据占比达到该值时才回收，0 表示有失效数据即回收
+    #[serde(default)]
+    pub vlog_gc_ratio: f64,
+}
+
+impl StorageConfig {
+    // 检查配置取值，数据块需容纳块头，页容量不小于一个数据块
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.block_size <= HEADER_SIZE {
+            return Err(Error::InvalidConfig(format!(
+                "block_size must be greater than the block header size {}, got {}",
+                HEADER_SIZE, self.block_size
+            )));
+        }
+        if self.page_max_cap < self.block_size {
+            return Err(Error::InvalidConfig(format!(
+                "page_max_cap must be at least block_size {}, got {}",
+                self.block_size, self.page_max_cap
+            )));
+        }
+        if !(0.0..1.0).contains(&self.compact_threshold) {
+            return Err(Error::InvalidConfig(format!(
+                "compact_threshold must be in [0, 1), got {}",
+                self.compact_threshold
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl KvConfig {
+    // 检查配置取值，槽位按掩码定位，数量必须是 2 的幂
+    pub fn validate(&self) -> Result<(), Error> {
+        self.storage.validate()?;
+        if !self.slot_qty.is_power_of_two() {
+            return Err(Error::InvalidConfig(format!(
+                "slot_qty must be a power of two, got {}",
+                self.slot_qty
+            )));
+        }
+        if self.cache_cap == 0 {
+            return Err(Error::InvalidConfig("cache_cap must be greater than 0".to_string()));
+        }
+        if !(0.0..=1.0).contains(&self.vlog_gc_ratio) {
+            return Err(Error::InvalidConfig(format!(
+                "vlog_gc_ratio must be in [0, 1], got {}",
+                self.vlog_gc_ratio
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_conf() -> KvConfig {
+        KvConfig {
+            storage: StorageConfig {
+                path: "/tmp/wtfs/config".to_string(),
+                block_size: 1024,
+                page_max_cap: 1024 * 1024,
+                ..Default::default()
+            },
+            cache_cap: 1024,
+            slot_qty: 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(get_conf().validate().is_ok());
+
+        let mut conf = get_conf();
+        conf.slot_qty = 10000;
+        assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+
+        let mut conf = get_conf();
+        conf.storage.block_size = HEADER_SIZE;
+        assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+
+        let mut conf = get_conf();
+        conf.storage.page_max_cap = 512;
+        assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+
+        let mut conf = get_conf();
+        conf.cache_cap = 0;
+        assert!(matches!(conf.validate(), Err(Error::InvalidConfig(_))));
+    }
+}
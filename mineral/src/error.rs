@@ -11,6 +11,12 @@ pub enum Error {
     #[error("Failed to get block data: {0}")]
     BlockDataGetFailed(ioError),
 
+    #[error("Failed to open storage: {0}")]
+    StorageOpenFailed(ioError),
+
+    #[error("Failed to compact data block: {0}")]
+    CompactFailed(ioError),
+
     #[error("Failed to del main block data: {0}")]
     MainDataDelFailed(ioError),
 
@@ -25,4 +31,55 @@ pub enum Error {
 
     #[error("Failed to encode hash slot: {0}")]
     SlotEncodeFailed(String),
+
+    #[error("Failed to compress value: {0}")]
+    CompressFailed(String),
+
+    #[error("Failed to decompress value: {0}")]
+    DecompressFailed(String),
+
+    #[error("Unknown value codec: {0}")]
+    UnknownCodec(u8),
+
+    #[error("Failed to write value log: {0}")]
+    ValueLogWriteFailed(ioError),
+
+    #[error("Failed to read value log: {0}")]
+    ValueLogReadFailed(ioError),
+
+    #[error("Invalid value log data: {0}")]
+    InvalidValueLog(String),
+
+    #[error("Data directory is locked by another process: {0}")]
+    DirLocked(String),
+
+    #[error("Failed to lock data directory: {0}")]
+    LockFailed(ioError),
+
+    #[error("Data directory does not match config: {0}")]
+    ManifestMismatch(String),
+
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("Failed to write manifest: {0}")]
+    ManifestWriteFailed(ioError),
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("Value is not an integer or out of range")]
+    NotInteger,
+
+    #[error("Increment or decrement would overflow")]
+    IntegerOverflow,
+
+    #[error("Store is read-only after a write failure")]
+    ReadOnly,
+
+    #[error("Change buffer is full, flush is lagging behind")]
+    BufferFull,
+
+    #[error("Storage was opened without a merger")]
+    MergerMissing,
 }
@@ -1,22 +1,100 @@
 use flate2::bufread::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use serde::Deserialize;
 use std::io::prelude::*;
 
+use crate::error::Error;
 
-pub fn compress_data(data: &[u8]) -> Vec<u8> {
+// 压缩算法标识，tag 会随数据一起落盘
+// tag 为 0 表示未压缩，保证旧数据仍可读取
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    None,
+    Gzip,
+    Lz4,
+}
+
+const CODEC_NONE: u8 = 0;
+const CODEC_GZIP: u8 = 1;
+const CODEC_LZ4: u8 = 2;
+
+impl Codec {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Codec::None => CODEC_NONE,
+            Codec::Gzip => CODEC_GZIP,
+            Codec::Lz4 => CODEC_LZ4,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Result<Codec, Error> {
+        match tag {
+            CODEC_NONE => Ok(Codec::None),
+            CODEC_GZIP => Ok(Codec::Gzip),
+            CODEC_LZ4 => Ok(Codec::Lz4),
+            _ => Err(Error::UnknownCodec(tag)),
+        }
+    }
+}
+
+pub fn compress(codec: Codec, data: &[u8]) -> Result<Vec<u8>, Error> {
+    match codec {
+        Codec::None => Ok(data.to_vec()),
+        Codec::Gzip => compress_data(data),
+        Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+    }
+}
+
+pub fn decompress(codec: Codec, data: &[u8]) -> Result<Vec<u8>, Error> {
+    match codec {
+        Codec::None => Ok(data.to_vec()),
+        Codec::Gzip => decompress_data(data),
+        Codec::Lz4 => lz4_flex::decompress_size_prepended(data)
+            .map_err(|err| Error::DecompressFailed(err.to_string())),
+    }
+}
+
+pub fn compress_data(data: &[u8]) -> Result<Vec<u8>, Error> {
     let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(data).unwrap();
-    encoder.finish().unwrap()
+    encoder.write_all(data).map_err(|err| Error::CompressFailed(err.to_string()))?;
+    encoder.finish().map_err(|err| Error::CompressFailed(err.to_string()))
 }
 
-pub fn decompress_data(data: &[u8]) -> Vec<u8> {
+pub fn decompress_data(data: &[u8]) -> Result<Vec<u8>, Error> {
     let mut decoder = GzDecoder::new(data);
 
     let mut decompress_data = Vec::new();
 
-    decoder.read_to_end(&mut decompress_data).unwrap();
+    decoder.read_to_end(&mut decompress_data)
+        .map_err(|err| Error::DecompressFailed(err.to_string()))?;
 
-    decompress_data
+    Ok(decompress_data)
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let data = vec![7u8; 4096];
+        for codec in [Codec::None, Codec::Gzip, Codec::Lz4] {
+            let buf = compress(codec, &data).unwrap();
+            if codec != Codec::None {
+                assert!(buf.len() < data.len());
+            }
+            assert_eq!(decompress(codec, &buf).unwrap(), data);
+            assert_eq!(Codec::from_tag(codec.tag()).unwrap(), codec);
+        }
+    }
+
+    #[test]
+    fn test_decompress_corrupt() {
+        assert!(decompress(Codec::Gzip, &[1, 2, 3]).is_err());
+        assert!(decompress(Codec::Lz4, &[10, 0, 0, 0, 0xF0]).is_err());
+        assert!(Codec::from_tag(9).is_err());
+    }
+}
@@ -1,209 +1,622 @@
 
+use std::cmp;
 use std::num::NonZeroUsize;
 use std::ops::Add;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use lru::LruCache;
+use tracing::{error, info};
 
-use crate::{config::KvConfig, storage::serve::Serve};
+use crate::{config::KvConfig, error::Error, flate::{self, Codec}, metrics::Metrics, storage::serve::{Serve, StorageStats}, worker::Worker};
 
-use super::cbf::Cbf;
-use super::slot::{SlotEntry, EXPIRE_DEL};
+use super::manifest::{DirLock, Manifest};
+use super::slot::{SlotEntry, EXPIRE_DEL, KEY_LEN_MASK};
+use super::vlog::{ValueLog, ValuePointer};
 use super::wal::{KvWal, KvWalEntry};
 use super::Slot;
 use super::Bytes;
 
+// 值日志后台回收间隔
+const VLOG_GC_INTERVAL: Duration = Duration::from_secs(60);
+
+// 槽位变更直接写入存储层，由存储层的预写日志和变更缓冲统一保证持久化
+// 预写日志只记录单个 key 的变更，缓冲中保存合并后的槽位
+// 槽位的读取、修改和写回在同一次存储层加锁内完成，与值日志回收线程互斥，加锁顺序为存储层、值日志
 #[derive(Debug)]
 pub struct HashKv {
     store: Arc<Mutex<Serve>>,
-    wal: Arc<Mutex<KvWal>>,
-    cbf: Arc<Mutex<Cbf>>,
     slots: u32,
     lru: LruCache<Bytes, SlotEntry>,
+    compress_threshold: usize,
+    compress_codec: Codec,
+    vlog: Arc<Mutex<ValueLog>>,
+    vlog_threshold: usize,
+    vlog_gc: Arc<Mutex<VlogGc>>,
+    gc_worker: Option<Worker>, // 使用值日志时才启动
+    metrics: Arc<Metrics>, // 与存储层共享
+    _lock: DirLock, // 最后释放，保证存储层先完成关闭
+}
+
+// 条件写入的条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SetCond {
+    #[default]
+    Always,
+    IfAbsent,       // key 不存在时写入
+    IfPresent,      // key 存在时写入
+    IfVersion(u64), // 当前版本号等于给定值时写入，0 匹配不存在的 key 及无版本的旧数据
+}
+
+// 条件写入参数
+#[derive(Debug, Clone, Default)]
+pub struct SetOptions {
+    pub expire: Option<Duration>,
+    pub cond: SetCond,
+    pub get: bool, // 返回写入前的旧值
+}
+
+// 条件写入结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetOutcome {
+    pub written: bool,
+    pub old: Option<Bytes>, // 写入前的旧值，仅 get 为 true 时返回
+    pub version: u64,       // 写入时为新版本号，否则为当前版本号，key 不存在时为 0
+}
+
+// 值日志单次回收结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VlogGcReport {
+    pub segment: Option<u64>, // 本次检查的段
+    pub collected: bool,      // 段是否已回收
+    pub live: usize,          // 重写的存活条目数
+    pub dead: usize,          // 失效条目数
+    pub reclaimed: u64,       // 回收的字节数
+}
+
+// kv 层统计，storage 为槽位数据所在存储层的统计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KvStats {
+    pub keys: usize,          // 未过期的 key 数
+    pub cache_entries: usize, // lru 缓存条目数
+    pub vlog_segments: usize, // 值日志段数
+    pub vlog_bytes: u64,      // 值日志字节数
+    pub storage: StorageStats,
+}
+
+// expire 转换为过期时间戳，0 表示不过期
+fn expires_at(expire: Option<Duration>) -> u64 {
+    match expire {
+        Some(dur) => SystemTime::now().duration_since(UNIX_EPOCH).unwrap().add(dur).as_secs(),
+        None => 0,
+    }
+}
+
+// 版本号单调递增且不小于当前微秒时间戳，key 删除后重新写入时版本号不会回退
+fn next_version(current: u64) -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+    cmp::max(current + 1, now)
+}
+
+fn slot_index<K: Hash>(key: &K, slots: u32) -> usize {
+    // 使用Rust的标准库中的Hash来计算key的哈希值
+    let mut hasher = DefaultHasher::new();
+
+    key.hash(&mut hasher);
+
+    let hash_code = hasher.finish(); // 获取64位的哈希值
+
+    // 将hashCode的高18位和低18位进行异或运算
+    let xor_hash = (hash_code >> 18) ^ (hash_code & 0x3FFFF);
+
+    // 与当前数组长度减一进行与运算，得到数组下标
+    let index = xor_hash & (slots as u64 - 1);
+
+    // 由于数组下标必须是usize类型，进行类型转换
+    index as usize
+}
+
+fn load_slot(store: &mut Serve, slot_no: usize) -> Result<Slot, Error> {
+    let data = store.get(slot_no)?;
+    Slot::new(data)
+}
+
+// 读取条目的原始值，指针条目从值日志中读取后再解压
+fn read_value(vlog: &Mutex<ValueLog>, key: &Bytes, entry: &SlotEntry) -> Result<Bytes, Error> {
+    match entry.value_pointer()? {
+        Some(ptr) => {
+            let (vlog_key, stored) = vlog.lock().unwrap().read(&ptr)?;
+            if &vlog_key != key {
+                return Err(Error::InvalidValueLog("key mismatch".to_string()));
+            }
+            flate::decompress(entry.codec, &stored)
+        },
+        None => entry.decode_value(),
+    }
 }
 
 impl HashKv {
+    // 打开失败时 panic，需要处理错误时使用 open
     pub fn new(conf: KvConfig) -> Self {
+        Self::open(conf).unwrap()
+    }
+
+    // 先锁定数据目录并校验元信息，再打开存储层文件
+    pub fn open(conf: KvConfig) -> Result<Self, Error> {
+        let lock = DirLock::acquire(&conf.storage.path)?;
+        Manifest::check(&conf.storage.path, &conf)?;
+
+        let store = Arc::new(Mutex::new(Serve::with_merger(conf.storage.clone(), Slot::merge)?));
+        let metrics = store.lock().unwrap().metrics();
+        let vlog = Arc::new(Mutex::new(ValueLog::new(&conf.storage.path, conf.vlog_segment_size)?));
+        let vlog_gc = Arc::new(Mutex::new(VlogGc {
+            store: store.clone(),
+            vlog: vlog.clone(),
+            slots: conf.slot_qty,
+            ratio: conf.vlog_gc_ratio,
+            next: 0,
+        }));
         let mut kv = HashKv {
-            store: Arc::new(Mutex::new(Serve::new(conf.storage.clone()))),
-            wal: Arc::new(Mutex::new(KvWal::new(&conf.wal_path))),
-            cbf: Arc::new(Mutex::new(Cbf::new(conf.cbf_cap))),
+            store,
             slots: conf.slot_qty,
             lru: LruCache::new(NonZeroUsize::new(conf.cache_cap).unwrap()),
+            compress_threshold: conf.compress_threshold,
+            compress_codec: conf.compress_codec,
+            vlog,
+            vlog_threshold: conf.vlog_threshold,
+            vlog_gc,
+            gc_worker: None,
+            metrics,
+            _lock: lock,
         };
-        
-        kv.init_wal_logs();
 
-        kv.run();
-        kv
+        kv.replay_legacy_wal(&conf.wal_path);
+
+        // 关闭值日志后仍需回收已有的段
+        if conf.vlog_threshold > 0 || !kv.vlog.lock().unwrap().sealed_segments().is_empty() {
+            kv.gc_worker = Some(Self::spawn_gc(kv.vlog_gc.clone()));
+        }
 
+        Ok(kv)
+    }
+
+    fn spawn_gc(vlog_gc: Arc<Mutex<VlogGc>>) -> Worker {
+        Worker::spawn(VLOG_GC_INTERVAL, move || {
+            match vlog_gc.lock().unwrap().collect() {
+                Ok(report) if report.collected => info!(
+                    segment = report.segment,
+                    live = report.live,
+                    dead = report.dead,
+                    reclaimed = report.reclaimed,
+                    "collected value log segment",
+                ),
+                Ok(_) | Err(Error::ReadOnly) => {},
+                Err(err) => error!(cause = %err, "failed to collect value log"),
+            }
+        })
     }
 
     fn calculate_index<K: Hash>(&self, key: &K) -> usize {
-        // 使用Rust的标准库中的Hash来计算key的哈希值
-        let mut hasher = DefaultHasher::new();
-    
-        key.hash(&mut hasher);
-        
-        let hash_code = hasher.finish(); // 获取64位的哈希值
-    
-        // 将hashCode的高18位和低18位进行异或运算
-        let xor_hash = (hash_code >> 18) ^ (hash_code & 0x3FFFF);
-    
-        // 与当前数组长度减一进行与运算，得到数组下标
-        let index = xor_hash & (self.slots as u64 - 1);
-    
-        // 由于数组下标必须是usize类型，进行类型转换
-        index as usize
+        slot_index(key, self.slots)
+    }
+
+    pub fn set(&mut self, key: &Bytes, val: &Bytes) -> Result<(), Error> {
+        self.setex(key, val, None)
     }
 
-    pub fn set(&mut self, key: &Bytes, val: &Bytes) {
-        self.setnx(key, val, None);
+    // 写入并设置过期时间，expire 为 None 时不过期
+    pub fn setex(&mut self, key: &Bytes, val: &Bytes, expire: Option<Duration>) -> Result<(), Error> {
+        self.check_writable()?;
+        self.put_value(key, val, expires_at(expire))?;
+        Ok(())
     }
 
-    pub fn setnx(&mut self, key: &Bytes, val: &Bytes, expire: Option<Duration>) {
-        let expires_at = if let Some(dur) = expire {
-            SystemTime::now().duration_since(UNIX_EPOCH).unwrap().add(dur).as_secs()
+    // 条件写入，条件判断和写入在同一次调用中完成，持有 HashKv 的可变引用即保证原子性
+    pub fn set_with(&mut self, key: &Bytes, val: &Bytes, opts: &SetOptions) -> Result<SetOutcome, Error> {
+        self.check_writable()?;
+
+        let current = if opts.cond != SetCond::Always || opts.get {
+            self.get_entry(key)?
         } else {
-            0
+            None
         };
-        
-        // 优先写日志
-        let version = self.wal.lock().unwrap().set(key, val, expires_at).unwrap();
+        let version = current.as_ref().map_or(0, |entry| entry.version);
+
+        let written = match opts.cond {
+            SetCond::Always => true,
+            SetCond::IfAbsent => current.is_none(),
+            SetCond::IfPresent => current.is_some(),
+            SetCond::IfVersion(expected) => version == expected,
+        };
+
+        let mut outcome = SetOutcome {
+            written,
+            old: if opts.get { current.map(|entry| entry.value) } else { None },
+            version,
+        };
+        if written {
+            outcome.version = self.put_value(key, val, expires_at(opts.expire))?;
+        }
+        Ok(outcome)
+    }
 
-        self._set_to_cbf(version, key, val, expires_at);
+    // 整数加减，读取、计算和写入在同一次调用中完成，持有 HashKv 的可变引用即保证原子性
+    // key 不存在或已过期时按 0 计算，已有的过期时间保持不变
+    pub fn incr_by(&mut self, key: &Bytes, delta: i64) -> Result<i64, Error> {
+        self.check_writable()?;
+
+        let (current, expires_at) = match self.get_entry(key)? {
+            Some(entry) => {
+                let current = std::str::from_utf8(&entry.value)
+                    .ok()
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .ok_or(Error::NotInteger)?;
+                (current, entry.expires_at())
+            },
+            None => (0, 0),
+        };
+        let value = current.checked_add(delta).ok_or(Error::IntegerOverflow)?;
 
+        self.put_value(key, &value.to_string().into_bytes(), expires_at)?;
+        Ok(value)
     }
 
-    fn _set_to_cbf(&mut self, version: u64, key: &Bytes, val: &Bytes, expires_at: u64) {
+    // 按需压缩或分离到值日志后写入槽位，expires_at 为 0 表示不过期，返回写入的版本号
+    fn put_value(&mut self, key: &Bytes, val: &Bytes, expires_at: u64) -> Result<u64, Error> {
+        if key.len() > KEY_LEN_MASK as usize {
+            return Err(Error::SlotEncodeFailed(format!("key too long: {}", key.len())));
+        }
+
+        // 超过阈值的值先压缩，压缩失败时按原值写入
+        let mut entry = SlotEntry::compress(val, self.compress_codec, self.compress_threshold, expires_at)
+            .unwrap_or_else(|err| {
+                error!(cause = %err, "failed to compress value");
+                SlotEntry::new(val, expires_at)
+            });
+
         let slot_no = self.calculate_index(&key);
+        let version = {
+            let mut store = self.store.lock().unwrap();
+
+            // 大值写入值日志，槽位只保存指针，值日志写入失败时不再写入槽位并切换为只读
+            if self.vlog_threshold > 0 && entry.value.len() >= self.vlog_threshold {
+                let ptr = self.vlog.lock().unwrap().append(key, &entry.value)
+                    .map_err(|err| store.degrade_with(err))?;
+                entry = SlotEntry::pointer(&ptr, entry.codec, expires_at);
+            }
 
-        let mut slot = if let Some(st) = self.cbf.lock().unwrap().get(slot_no) {
-                st
-            } else if let Ok(data) = self.store.lock().unwrap().get(slot_no) {
-                Slot::new(slot_no, data).unwrap()
-            } else {
-                Slot::new(slot_no, vec![]).unwrap()
-            };
+            let mut slot = load_slot(&mut store, slot_no)?;
+            let version = next_version(slot.get(key).map_or(0, |old| old.version));
+            let entry = entry.with_version(version);
+            let delta = Slot::encode_put(key, &entry)?;
+            slot.put(key, entry);
+            store.merge(slot_no, delta, slot.encode()?)?;
+            version
+        };
 
-        // 更新数据    
-        slot.set(key, val, expires_at);
+        // 更新lru，lru 中缓存未压缩的值
+        self.lru.put(key.clone(), SlotEntry::new(val, expires_at).with_version(version));
+        Ok(version)
+    }
 
-        // 刷新到cbf 写入缓冲中
-        self.cbf.lock().unwrap().insert(version as usize, &slot).unwrap();
+    // 更新槽位并整体写入存储层，条目的版本号保持不变
+    fn put_entry(&mut self, key: &Bytes, entry: SlotEntry) -> Result<(), Error> {
+        let slot_no = self.calculate_index(&key);
+        let mut store = self.store.lock().unwrap();
+        let mut slot = load_slot(&mut store, slot_no)?;
 
-        // 更新lru
-        self.lru.put(key.clone(), SlotEntry::new(val, expires_at));
+        // 更新数据
+        let delta = Slot::encode_put(key, &entry)?;
+        slot.put(key, entry);
+
+        store.merge(slot_no, delta, slot.encode()?)
     }
 
-    pub fn get(&mut self, key: &Bytes) -> Option<Bytes> {
+    pub fn get(&mut self, key: &Bytes) -> Result<Option<Bytes>, Error> {
+        Ok(self.get_entry(key)?.map(|entry| entry.value))
+    }
+
+    // 只判断 key 是否存在且未过期，不读取值日志
+    pub fn exists(&mut self, key: &Bytes) -> Result<bool, Error> {
+        let cached = self.lru.get(key).map(|entry| !entry.has_expired());
+        self.metrics.cache_hit(cached.is_some());
+        if let Some(live) = cached {
+            return Ok(live);
+        }
+        Ok(self.lookup_entry(key)?.is_some_and(|entry| !entry.has_expired()))
+    }
+
+    // 返回值及其版本号，0 表示无版本的旧数据
+    pub fn get_versioned(&mut self, key: &Bytes) -> Result<Option<(Bytes, u64)>, Error> {
+        Ok(self.get_entry(key)?.map(|entry| (entry.value, entry.version)))
+    }
+
+    // 返回未过期的条目，条目中为解压后的值
+    fn get_entry(&mut self, key: &Bytes) -> Result<Option<SlotEntry>, Error> {
 
-        let slot_no = self.calculate_index(&key);
         // 从lru中获取
-        if let Some(entry) = self.lru.get(key) {
+        let cached = self.lru.get(key).cloned();
+        self.metrics.cache_hit(cached.is_some());
+        if let Some(entry) = cached {
             if entry.has_expired() {
-                return None;
+                return Ok(None);
             }
-            return Some(entry.value.clone());
+            return Ok(Some(entry));
         }
 
-        // 从cbf中获取
-        if let Some(slot) = self.cbf.lock().unwrap().get(slot_no) {
-            if let Some(entry) = slot.get(key) {
-                if entry.has_expired() {
-                    return None;
-                }
-                return Some(entry.value);
+        // 向store获取数据，未刷盘的变更由存储层缓冲返回
+        // 持有存储层锁读取值日志，避免指针所在的段被回收
+        let value = {
+            let mut store = self.store.lock().unwrap();
+            let slot = load_slot(&mut store, self.calculate_index(&key))?;
+            match slot.get(key) {
+                Some(entry) if entry.has_expired() => return Ok(None),
+                Some(entry) => SlotEntry::new(&read_value(&self.vlog, key, &entry)?, entry.expires_at()).with_version(entry.version),
+                None => return Ok(None),
             }
+        };
+
+        // 将entry更新至lru
+        self.lru.put(key.clone(), value.clone());
+        Ok(Some(value))
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.store.lock().unwrap().is_read_only()
+    }
+
+    // 运行指标，读取时无需持有 kv 的锁
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly);
         }
+        Ok(())
+    }
 
-        // 向store获取磁盘中数据
-        if let Ok(res) = self.store.lock().unwrap().get(slot_no) {
-            let slot = Slot::new(slot_no, res).unwrap();
-            if let Some(entry) = slot.get(key) {
-                if entry.has_expired() {
-                    return None;
-                }
-                // 将entry更新至lru
-                self.lru.put(key.clone(), entry.clone());
-                return Some(entry.value);
-            }
+    // 统计需要遍历全部槽位，耗时与数据量相关
+    pub fn stats(&self) -> Result<KvStats, Error> {
+        let mut stats = self.usage()?;
+        stats.keys = self.key_counter().count()?;
+        Ok(stats)
+    }
+
+    // 不含 key 数的统计，keys 为 0，无需遍历槽位
+    pub fn usage(&self) -> Result<KvStats, Error> {
+        let (vlog_segments, vlog_bytes) = self.vlog.lock().unwrap().usage();
+
+        Ok(KvStats {
+            keys: 0,
+            cache_entries: self.lru.len(),
+            vlog_segments,
+            vlog_bytes,
+            storage: self.store.lock().unwrap().stats()?,
+        })
+    }
+
+    // 统计 key 数的句柄，可在不持有 HashKv 的情况下于其他线程中遍历槽位
+    pub fn key_counter(&self) -> KeyCounter {
+        KeyCounter {
+            store: self.store.clone(),
+            slots: self.slots,
         }
+    }
 
-        None
+    // 查找 key 当前在槽位中的条目，不经过lru
+    fn lookup_entry(&self, key: &Bytes) -> Result<Option<SlotEntry>, Error> {
+        Ok(load_slot(&mut self.store.lock().unwrap(), self.calculate_index(key))?.get(key))
     }
 
-    pub fn del(&mut self, key: &Bytes) -> Option<Bytes> {
+    // 立即回收一个已封存的值日志段，后台线程按 VLOG_GC_INTERVAL 定期执行相同的回收
+    pub fn gc_value_log(&mut self) -> Result<VlogGcReport, Error> {
+        self.check_writable()?;
+        self.vlog_gc.lock().unwrap().collect()
+    }
 
-        let version = self.wal.lock().unwrap().del(key).unwrap();
+    pub fn del(&mut self, key: &Bytes) -> Result<Option<Bytes>, Error> {
+        self.check_writable()?;
 
         let slot_no = self.calculate_index(&key);
+        let old_value = {
+            let mut store = self.store.lock().unwrap();
+            let mut slot = load_slot(&mut store, slot_no)?;
 
-        // 从lru中获取
-        let mut slot = if let Some(cache) = self.cbf.lock().unwrap().get(slot_no) {
-                cache.clone()
-            } else if let Ok(data) = self.store.lock().unwrap().get(slot_no) {
-                Slot::new(slot_no, data).unwrap()
-            } else {
-                Slot::new(slot_no, vec![]).unwrap()
-            };
-        
-        let old_slot_entry = slot.del(&key);
+            // 旧值在写回前读取，写回后指针所在的段可能被回收
+            let old_value = slot.del(key).map(|old_entry| read_value(&self.vlog, key, &old_entry));
 
-        // 更新cbf变更缓冲
-        self.cbf.lock().unwrap().insert(version as usize, &slot).unwrap();
+            store.merge(slot_no, Slot::encode_del(key), slot.encode()?)?;
+            old_value
+        };
 
         // 更新lru
         self.lru.put(key.clone(), SlotEntry::new(&vec![], EXPIRE_DEL));
-        
-        match old_slot_entry {
-            Some(old_entry) => Some(old_entry.value),
-            None => None
+
+        // 删除已完成，旧值读取失败时只记录日志
+        match old_value {
+            Some(Ok(value)) => Ok(Some(value)),
+            Some(Err(err)) => {
+                error!(cause = %err, "failed to decode value");
+                Ok(None)
+            },
+            None => Ok(None),
         }
 
     }
 
-    fn init_wal_logs(&mut self) {
-        let wal_reder = self.wal.lock().unwrap().reader(0, 0);
+    // 旧版本 kv 层单独维护预写日志，打开时将其中未刷入存储层的条目回放到存储层后检查
+    fn replay_legacy_wal(&mut self, path: &str) {
+        if path.is_empty() || !Path::new(path).exists() {
+            return;
+        }
+
+        let mut wal = KvWal::new(path);
+        let wal_reder = wal.reader(0, 0);
         if wal_reder.is_none() {
             return;
         }
 
+        let mut replayed = 0;
         for payload in wal_reder.unwrap() {
-            let payload = payload.unwrap();
-            let entry = KvWalEntry::decode(payload.data);
-            self._set_to_cbf(payload.version, &entry.key, &entry.val, entry.header.expires_at);
-        }
-    }
-
-    fn run(&self) {
-        let cbf = self.cbf.clone();
-        let store = self.store.clone();
-        let wal = self.wal.clone();
-        thread::spawn(move || {
-            loop {
-                if let Some((page_no, page)) = cbf.lock().unwrap().pop_first_page() {
-                    // println!("page no: {}", page_no);
-                    for (pos, buf) in page.entrys {
-                        store.lock().unwrap().set(pos, buf).unwrap();
-                    }
-
-                    // 此处主要用来处理预写日志检查点
-                    wal.lock().unwrap().checkpoint(page.max_version as u64);
+            let payload = match payload {
+                Ok(payload) => payload,
+                Err(err) => {
+                    error!(cause = %err, "failed to read legacy kv wal");
+                    return;
+                },
+            };
+            let entry = match KvWalEntry::decode(payload.data) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    error!(cause = %err, version = payload.version, "skip invalid kv wal entry");
+                    continue;
                 }
-                thread::sleep(Duration::from_millis(5000));
+            };
+            let res = entry.slot_entry()
+                .and_then(|slot_entry| self.put_entry(&entry.key, slot_entry));
+            match res {
+                Ok(()) => replayed += 1,
+                // 存储层不可写时保留日志，下次打开重试
+                Err(Error::ReadOnly) | Err(Error::BufferFull) => return,
+                Err(err) => error!(cause = %err, version = payload.version, "skip invalid kv wal entry"),
             }
-        });
+        }
+
+        let version = wal.version();
+        wal.checkpoint(version + 1);
+        if replayed > 0 {
+            info!(replayed, path, "replayed legacy kv wal");
+        }
+    }
+
+    // 将存储层缓冲中的变更全部落盘并停止后台线程，重复调用无副作用
+    pub fn close(&mut self) -> Result<(), Error> {
+        if let Some(mut worker) = self.gc_worker.take() {
+            worker.stop();
+        }
+        self.store.lock().unwrap().close()
+    }
+
+}
+
+// 统计未过期的 key 数，每个槽位单独加锁读取，遍历期间写入不会被长时间阻塞
+#[derive(Debug, Clone)]
+pub struct KeyCounter {
+    store: Arc<Mutex<Serve>>,
+    slots: u32,
+}
+
+impl KeyCounter {
+    pub fn count(&self) -> Result<usize, Error> {
+        let mut keys = 0;
+        for slot_no in 0..self.slots as usize {
+            let slot = load_slot(&mut self.store.lock().unwrap(), slot_no)?;
+            keys += slot.slot_kv.values().filter(|entry| !entry.has_expired()).count();
+        }
+        Ok(keys)
     }
+}
 
+// 值日志回收，依次检查已封存的段：存活值重写到当前段并更新槽位指针，随后删除该段
+// 失效数据占比低于 ratio 时跳过
+#[derive(Debug)]
+struct VlogGc {
+    store: Arc<Mutex<Serve>>,
+    vlog: Arc<Mutex<ValueLog>>,
+    slots: u32,
+    ratio: f64,
+    next: u64, // 下次从该段号开始检查，避免反复检查同一个段
+}
+
+impl VlogGc {
+    fn collect(&mut self) -> Result<VlogGcReport, Error> {
+        if self.store.lock().unwrap().is_read_only() {
+            return Err(Error::ReadOnly);
+        }
+        let mut report = VlogGcReport::default();
+
+        let sealed = self.vlog.lock().unwrap().sealed_segments();
+        let segment = match sealed.iter().find(|seq| **seq >= self.next).or(sealed.first()) {
+            Some(segment) => *segment,
+            None => return Ok(report),
+        };
+        self.next = segment + 1;
+        report.segment = Some(segment);
+
+        // 已封存的段不再写入，读取时无需持有值日志
+        let disk = self.vlog.lock().unwrap().open_segment(segment)?;
+        let entries = ValueLog::read_entries(disk, segment)?;
+
+        let mut total = 0u64;
+        let mut live = vec![];
+        for (ptr, key, val) in entries {
+            total += ptr.len as u64;
+            if self.lookup_live(&mut self.store.lock().unwrap(), &key, &ptr)?.is_some() {
+                live.push((ptr, key, val));
+            } else {
+                report.dead += 1;
+                report.reclaimed += ptr.len as u64;
+            }
+        }
+
+        if report.dead == 0 || (report.reclaimed as f64) < total as f64 * self.ratio {
+            report.reclaimed = 0;
+            return Ok(report);
+        }
+
+        for (ptr, key, val) in live {
+            // 检查后 key 可能已被改写或删除，重写前在存储层锁内再次确认
+            let mut store = self.store.lock().unwrap();
+            let (slot_no, mut slot, entry) = match self.lookup_live(&mut store, &key, &ptr)? {
+                Some(found) => found,
+                None => {
+                    report.dead += 1;
+                    report.reclaimed += ptr.len as u64;
+                    continue;
+                },
+            };
+
+            // 新指针先写入存储层预写日志，保证段删除后仍可恢复
+            let new_ptr = self.vlog.lock().unwrap().append(&key, &val)
+                .map_err(|err| store.degrade_with(err))?;
+            let entry = SlotEntry::pointer(&new_ptr, entry.codec, entry.expires_at()).with_version(entry.version);
+            let delta = Slot::encode_put(&key, &entry)?;
+            slot.put(&key, entry);
+            store.merge(slot_no, delta, slot.encode()?)?;
+            report.live += 1;
+        }
+
+        // 读取方在存储层锁内解析指针，删除时同样持有
+        let _store = self.store.lock().unwrap();
+        self.vlog.lock().unwrap().remove_segment(segment)?;
+        report.collected = true;
+
+        Ok(report)
+    }
+
+    // 槽位仍指向该位置且未过期的值才是存活的
+    fn lookup_live(&self, store: &mut Serve, key: &Bytes, ptr: &ValuePointer) -> Result<Option<(usize, Slot, SlotEntry)>, Error> {
+        let slot_no = slot_index(key, self.slots);
+        let slot = load_slot(store, slot_no)?;
+        match slot.get(key) {
+            Some(entry) if !entry.has_expired() && entry.value_pointer().ok().flatten().as_ref() == Some(ptr) => {
+                Ok(Some((slot_no, slot, entry)))
+            },
+            _ => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::thread;
+    use std::sync::atomic::Ordering;
     use crate::config::StorageConfig;
+    use crate::state::disk::Disk;
 
     use super::*;
 
@@ -213,11 +626,18 @@ mod tests {
                 path: "/tmp/terra/tests/kv-data2".to_string(),
                 block_size: 1024,
                 page_max_cap: 1024 * 1024 * 50,
+                compact_threshold: 0.0,
+                cbf_high_water: 0,
+                cbf_stall_ms: 0,
             },
             wal_path: "/tmp/terra/tests/kv-log2".to_string(),
             cache_cap: 1024 * 1024 * 50,
-            cbf_cap: 1024 * 1024 * 50,
             slot_qty: 10000,
+            compress_threshold: 0,
+            compress_codec: Codec::None,
+            vlog_threshold: 0,
+            vlog_segment_size: 0,
+            vlog_gc_ratio: 0.0,
         }
     }
 
@@ -226,18 +646,356 @@ mod tests {
         let mut kv = HashKv::new(get_conf());
         let key = "foo".as_bytes().to_vec();
         let val = "bar".as_bytes().to_vec();
-        kv.set(&key, &val);
-        assert_eq!(kv.get(&key).unwrap(), val);
+        kv.set(&key, &val).unwrap();
+        assert_eq!(kv.get(&key).unwrap().unwrap(), val);
 
-        let old_val = kv.del(&key);
+        let old_val = kv.del(&key).unwrap();
         assert_eq!(old_val.unwrap(), val);
-        let new_val = kv.get(&key);
+        let new_val = kv.get(&key).unwrap();
         assert!(new_val.is_none());
 
-        kv.setnx(&key, &val, Some(Duration::from_secs(4)));
-        assert_eq!(kv.get(&key).unwrap(), val);
+        kv.setex(&key, &val, Some(Duration::from_secs(4))).unwrap();
+        assert_eq!(kv.get(&key).unwrap().unwrap(), val);
         thread::sleep(Duration::from_secs(5));
-        assert_eq!(kv.get(&key), None);
+        assert_eq!(kv.get(&key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_compress() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data3".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log3".to_string();
+        conf.compress_threshold = 64;
+        conf.compress_codec = Codec::Lz4;
+
+        let mut kv = HashKv::new(conf);
+        let small = "small".as_bytes().to_vec();
+        let large = "large".as_bytes().to_vec();
+        let large_val = vec![9u8; 4096];
+        kv.set(&small, &small).unwrap();
+        kv.set(&large, &large_val).unwrap();
+
+        let entry = kv.lookup_entry(&large).unwrap().unwrap();
+        assert_eq!(entry.codec, Codec::Lz4);
+        assert!(entry.value.len() < large_val.len());
+
+        // 跳过lru，验证从落盘格式中解压
+        kv.lru.clear();
+        assert_eq!(kv.get(&small).unwrap().unwrap(), small);
+        assert_eq!(kv.get(&large).unwrap().unwrap(), large_val);
+    }
+
+    #[test]
+    fn test_vlog() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data4".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log4".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+        conf.vlog_threshold = 128;
+        conf.vlog_segment_size = 1024;
+
+        let mut kv = HashKv::new(conf);
+        assert!(kv.gc_worker.is_some());
+        let small = "small".as_bytes().to_vec();
+        let large = "large".as_bytes().to_vec();
+        let dead = "dead".as_bytes().to_vec();
+        kv.set(&small, &small).unwrap();
+        kv.set(&large, &vec![1u8; 600]).unwrap();
+        kv.set(&dead, &vec![2u8; 600]).unwrap();
+        kv.set(&dead, &vec![3u8; 600]).unwrap();
+
+        let entry = kv.lookup_entry(&large).unwrap().unwrap();
+        assert!(entry.pointer);
+        assert!(entry.value.len() < 600);
+
+        kv.lru.clear();
+        assert_eq!(kv.get(&small).unwrap().unwrap(), small);
+        assert_eq!(kv.get(&large).unwrap().unwrap(), vec![1u8; 600]);
+
+        // 段 0 中 large 存活，dead 的旧值已失效
+        let report = kv.gc_value_log().unwrap();
+        assert_eq!(report.segment, Some(0));
+        assert!(report.collected);
+        assert_eq!(report.live, 1);
+        assert_eq!(report.dead, 1);
+
+        kv.lru.clear();
+        assert_eq!(kv.get(&large).unwrap().unwrap(), vec![1u8; 600]);
+        assert_eq!(kv.get(&dead).unwrap().unwrap(), vec![3u8; 600]);
+        assert!(!kv.vlog.lock().unwrap().sealed_segments().contains(&0));
+
+        let stats = kv.stats().unwrap();
+        assert_eq!(stats.keys, 3);
+        assert!(stats.vlog_bytes > 0);
+        assert!(stats.storage.wal_bytes > 0);
+    }
+
+    #[test]
+    fn test_vlog_torn_tail() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data12".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log12".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+        conf.vlog_threshold = 128;
+        conf.vlog_segment_size = 1024;
+
+        let large = "large".as_bytes().to_vec();
+        let mut kv = HashKv::new(conf.clone());
+        kv.set(&large, &vec![1u8; 600]).unwrap();
+        drop(kv);
+
+        // 模拟写入值日志时崩溃，段 0 尾部留下残缺条目
+        let mut vlog = ValueLog::new(&conf.storage.path, conf.vlog_segment_size).unwrap();
+        let torn = vlog.append(&large, &vec![2u8; 300]).unwrap();
+        drop(vlog);
+        let name = ValueLog::build_segment_name(&conf.storage.path, 0);
+        let file = std::fs::OpenOptions::new().write(true).open(&name).unwrap();
+        file.set_len(torn.offset + torn.len as u64 - 10).unwrap();
+        drop(file);
+
+        // 重新打开后继续追加，段 0 写满后封存
+        let mut kv = HashKv::new(conf);
+        let other = "other".as_bytes().to_vec();
+        kv.set(&other, &vec![3u8; 300]).unwrap();
+        kv.set(&other, &vec![4u8; 600]).unwrap();
+        kv.set(&other, &vec![5u8; 600]).unwrap();
+        assert_eq!(kv.vlog.lock().unwrap().sealed_segments(), vec![0]);
+
+        let report = kv.gc_value_log().unwrap();
+        assert_eq!(report.segment, Some(0));
+        assert!(report.collected);
+        assert_eq!(report.live, 1);
+        assert_eq!(report.dead, 2);
+
+        kv.lru.clear();
+        assert_eq!(kv.get(&large).unwrap().unwrap(), vec![1u8; 600]);
+        assert_eq!(kv.get(&other).unwrap().unwrap(), vec![5u8; 600]);
+    }
+
+    #[test]
+    fn test_close() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data5".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log5".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf.clone());
+        let key = "foo".as_bytes().to_vec();
+        kv.set(&key, &vec![7u8; 2048]).unwrap();
+        kv.close().unwrap();
+        assert_eq!(kv.stats().unwrap().storage.cbf_pages, 0);
+        drop(kv);
+
+        // 预写日志已检查，数据从存储层读取
+        let mut kv = HashKv::new(conf);
+        assert_eq!(kv.stats().unwrap().storage.cbf_pages, 0);
+        assert_eq!(kv.get(&key).unwrap().unwrap(), vec![7u8; 2048]);
+    }
+
+    #[test]
+    fn test_key_counter() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data11".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log11".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf);
+        for i in 0..10 {
+            kv.set(&format!("key-{}", i).into_bytes(), &vec![1u8; 10]).unwrap();
+        }
+        kv.del(&"key-0".as_bytes().to_vec()).unwrap();
+
+        // 统计在其他线程中进行，期间仍可写入
+        let counter = kv.key_counter();
+        let counting = std::thread::spawn(move || counter.count().unwrap());
+        kv.set(&"key-1".as_bytes().to_vec(), &vec![2u8; 10]).unwrap();
+        assert_eq!(counting.join().unwrap(), 9);
+
+        assert_eq!(kv.usage().unwrap().keys, 0);
+        assert_eq!(kv.stats().unwrap().keys, 9);
+    }
+
+    #[test]
+    fn test_incr() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data8".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log8".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf.clone());
+        let key = "counter".as_bytes().to_vec();
+        assert_eq!(kv.incr_by(&key, 1).unwrap(), 1);
+        assert_eq!(kv.incr_by(&key, 10).unwrap(), 11);
+        assert_eq!(kv.incr_by(&key, -20).unwrap(), -9);
+        assert_eq!(kv.get(&key).unwrap().unwrap(), b"-9".to_vec());
+
+        // 过期时间保持不变
+        let ttl_key = "ttl".as_bytes().to_vec();
+        kv.setex(&ttl_key, &b"5".to_vec(), Some(Duration::from_secs(100))).unwrap();
+        assert_eq!(kv.incr_by(&ttl_key, 1).unwrap(), 6);
+        assert!(kv.lookup_entry(&ttl_key).unwrap().unwrap().expires_at() > 0);
+
+        let text = "text".as_bytes().to_vec();
+        kv.set(&text, &b"abc".to_vec()).unwrap();
+        assert!(matches!(kv.incr_by(&text, 1), Err(Error::NotInteger)));
+
+        kv.set(&key, &i64::MAX.to_string().into_bytes()).unwrap();
+        assert!(matches!(kv.incr_by(&key, 1), Err(Error::IntegerOverflow)));
+        drop(kv);
+
+        // 重新打开后从存储层读取
+        let mut kv = HashKv::new(conf);
+        assert_eq!(kv.incr_by(&"counter".as_bytes().to_vec(), -1).unwrap(), i64::MAX - 1);
+    }
+
+    #[test]
+    fn test_set_with() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data9".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log9".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf.clone());
+        let key = "foo".as_bytes().to_vec();
+        let nx = SetOptions { cond: SetCond::IfAbsent, ..Default::default() };
+        let xx = SetOptions { cond: SetCond::IfPresent, get: true, ..Default::default() };
+
+        assert!(!kv.set_with(&key, &b"1".to_vec(), &xx).unwrap().written);
+        let first = kv.set_with(&key, &b"1".to_vec(), &nx).unwrap();
+        assert!(first.written && first.version > 0);
+        assert!(!kv.set_with(&key, &b"2".to_vec(), &nx).unwrap().written);
+
+        let second = kv.set_with(&key, &b"2".to_vec(), &xx).unwrap();
+        assert!(second.written);
+        assert_eq!(second.old, Some(b"1".to_vec()));
+        assert!(second.version > first.version);
+
+        // 版本号不匹配时不写入
+        let cas = |version| SetOptions { cond: SetCond::IfVersion(version), ..Default::default() };
+        let stale = kv.set_with(&key, &b"3".to_vec(), &cas(first.version)).unwrap();
+        assert!(!stale.written);
+        assert_eq!(stale.version, second.version);
+        assert!(kv.set_with(&key, &b"3".to_vec(), &cas(second.version)).unwrap().written);
+        assert!(kv.set_with(&"bar".as_bytes().to_vec(), &b"1".to_vec(), &cas(0)).unwrap().written);
+        drop(kv);
+
+        // 版本号随数据落盘
+        let mut kv = HashKv::new(conf);
+        let (value, version) = kv.get_versioned(&key).unwrap().unwrap();
+        assert_eq!(value, b"3".to_vec());
+        assert!(version > second.version);
+        assert!(kv.exists(&key).unwrap());
+        kv.del(&key).unwrap();
+        assert!(!kv.exists(&key).unwrap());
+        assert!(kv.get_versioned(&key).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data6".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log6".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf);
+        let key = "foo".as_bytes().to_vec();
+        kv.set(&key, &key).unwrap();
+        assert!(!kv.is_read_only());
+
+        let long_key = vec![1u8; KEY_LEN_MASK as usize + 1];
+        assert!(matches!(kv.set(&long_key, &key), Err(Error::SlotEncodeFailed(_))));
+        assert!(!kv.is_read_only());
+
+        // 模拟存储层写入失败，只读后拒绝写入，读取不受影响
+        kv.store.lock().unwrap().degrade_with(Error::AppendWalDataFailed);
+        assert!(kv.is_read_only());
+        assert!(matches!(kv.set(&key, &key), Err(Error::ReadOnly)));
+        assert!(matches!(kv.del(&key), Err(Error::ReadOnly)));
+        kv.lru.clear();
+        assert_eq!(kv.get(&key).unwrap().unwrap(), key);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_vlog_read_only() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data13".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log13".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+        conf.vlog_threshold = 128;
+
+        let mut kv = HashKv::new(conf);
+        let key = "foo".as_bytes().to_vec();
+        kv.set(&key, &vec![1u8; 600]).unwrap();
+
+        // 写入 /dev/full 总是失败，值日志写入失败与预写日志失败一样切换为只读
+        let full = Disk::open("/dev/full").unwrap();
+        let active = kv.vlog.lock().unwrap().replace_active_for_test(full);
+        assert!(matches!(kv.set(&key, &vec![2u8; 600]), Err(Error::ValueLogWriteFailed(_))));
+        assert!(kv.is_read_only());
+        kv.vlog.lock().unwrap().replace_active_for_test(active);
+        assert!(matches!(kv.set(&key, &key), Err(Error::ReadOnly)));
+        kv.lru.clear();
+        assert_eq!(kv.get(&key).unwrap().unwrap(), vec![1u8; 600]);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data10".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log10".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        let mut kv = HashKv::new(conf);
+        let metrics = kv.metrics();
+        let key = "foo".as_bytes().to_vec();
+        kv.set(&key, &key).unwrap();
+        assert_eq!(metrics.wal_append.snapshot().count, 1);
+        assert!(metrics.cbf_bytes.load(Ordering::Relaxed) > 0);
+
+        // 写入后 lru 命中，清空后未命中并重新载入
+        kv.get(&key).unwrap();
+        kv.lru.clear();
+        kv.get(&key).unwrap();
+        assert!(kv.exists(&key).unwrap());
+        assert_eq!(metrics.cache_hits.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.cache_misses.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_legacy_wal() {
+        let mut conf = get_conf();
+        conf.storage.path = "/tmp/terra/tests/kv-data7".to_string();
+        conf.wal_path = "/tmp/terra/tests/kv-log7".to_string();
+        let _ = std::fs::remove_dir_all(&conf.storage.path);
+        let _ = std::fs::remove_dir_all(&conf.wal_path);
+
+        // 旧版本写入只落在 kv 层预写日志中
+        let key = "foo".as_bytes().to_vec();
+        let mut wal = KvWal::new(&conf.wal_path);
+        wal.set(&key, &SlotEntry::new(&vec![5u8; 100], 0)).unwrap();
+        drop(wal);
+
+        let mut kv = HashKv::new(conf.clone());
+        assert_eq!(kv.get(&key).unwrap().unwrap(), vec![5u8; 100]);
+        kv.close().unwrap();
+        drop(kv);
+
+        // 回放后日志已检查，不会再次回放
+        let mut wal = KvWal::new(&conf.wal_path);
+        assert!(wal.reader(0, 0).is_none_or(|mut reader| reader.next().is_none()));
+        drop(wal);
+        let mut kv = HashKv::new(conf);
+        assert_eq!(kv.get(&key).unwrap().unwrap(), vec![5u8; 100]);
     }
 
     #[test]
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use tracing::{info, warn};
+
+use crate::config::KvConfig;
+use crate::error::Error;
+use crate::state;
+
+const LOCK_FILE_NAME: &str = "@lock";
+const MANIFEST_FILE_NAME: &str = "@manifest";
+const MANIFEST_TMP_FILE_NAME: &str = "@manifest.tmp";
+
+// 落盘格式版本，格式变更时递增，旧版本数据可被新版本读取
+// 2: 槽位条目可带版本号
+pub const FORMAT_VERSION: u32 = 2;
+// 槽位定位算法：标准库 SipHash 的高低 18 位异或后与槽位数掩码
+pub const HASH_ALGORITHM: &str = "siphash13-xor18";
+
+// 数据目录独占锁，持有期间其他进程无法打开同一目录，释放文件时自动解锁
+#[derive(Debug)]
+pub struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    pub fn acquire(path: &str) -> Result<Self, Error> {
+        let lock_path = state::build_path(path, LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+            .map_err(Error::LockFailed)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut owner = String::new();
+                let _ = file.read_to_string(&mut owner);
+                return Err(Error::DirLocked(format!("{} (pid {})", path, owner.trim())));
+            }
+            Err(TryLockError::Error(err)) => return Err(Error::LockFailed(err)),
+        }
+
+        // 记录持有锁的进程号，便于排查
+        file.set_len(0).map_err(Error::LockFailed)?;
+        file.write_all(std::process::id().to_string().as_bytes()).map_err(Error::LockFailed)?;
+
+        Ok(DirLock { _file: file })
+    }
+}
+
+// 数据目录元信息，记录影响数据解析的配置，打开时与当前配置不一致则拒绝打开
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub block_size: usize,
+    pub slot_qty: u32,
+    pub hash: String,
+}
+
+impl Manifest {
+    pub fn from_config(conf: &KvConfig) -> Self {
+        Manifest {
+            format_version: FORMAT_VERSION,
+            block_size: conf.storage.block_size,
+            slot_qty: conf.slot_qty,
+            hash: HASH_ALGORITHM.to_string(),
+        }
+    }
+
+    // 每行一个 key=value
+    fn encode(&self) -> String {
+        format!(
+            "format_version={}\nblock_size={}\nslot_qty={}\nhash={}\n",
+            self.format_version, self.block_size, self.slot_qty, self.hash
+        )
+    }
+
+    fn decode(content: &str) -> Result<Self, Error> {
+        let fields: HashMap<&str, &str> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, val)| (key.trim(), val.trim()))
+            .collect();
+
+        let field = |name: &str| {
+            fields.get(name).copied().ok_or_else(|| Error::InvalidManifest(format!("missing {}", name)))
+        };
+        let number = |name: &str| {
+            field(name)?.parse::<u64>().map_err(|_| Error::InvalidManifest(format!("invalid {}", name)))
+        };
+
+        Ok(Manifest {
+            format_version: number("format_version")? as u32,
+            block_size: number("block_size")? as usize,
+            slot_qty: number("slot_qty")? as u32,
+            hash: field("hash")?.to_string(),
+        })
+    }
+
+    // 读取数据目录的元信息并与当前配置比对，目录中还没有元信息时写入当前配置
+    pub fn check(path: &str, conf: &KvConfig) -> Result<(), Error> {
+        let manifest_path = state::build_path(path, MANIFEST_FILE_NAME);
+        let expected = Self::from_config(conf);
+
+        if !Path::new(&manifest_path).exists() {
+            if fs::read_dir(path).map_err(Error::ManifestWriteFailed)?.count() > 1 {
+                // 旧版本创建的数据目录没有元信息，无法校验，按当前配置补写
+                warn!(path, "data directory has no manifest, recording current config");
+            }
+            return expected.write(path);
+        }
+
+        let content = fs::read_to_string(&manifest_path)
+            .map_err(|err| Error::InvalidManifest(err.to_string()))?;
+        let stored = Self::decode(&content)?;
+
+        if stored.format_version > expected.format_version {
+            return Err(Error::ManifestMismatch(format!(
+                "format version is {}, supported version is {}",
+                stored.format_version, expected.format_version
+            )));
+        }
+        if stored.block_size != expected.block_size {
+            return Err(Error::ManifestMismatch(format!(
+                "block_size is {}, config has {}",
+                stored.block_size, expected.block_size
+            )));
+        }
+        if stored.slot_qty != expected.slot_qty {
+            return Err(Error::ManifestMismatch(format!(
+                "slot_qty is {}, config has {}",
+                stored.slot_qty, expected.slot_qty
+            )));
+        }
+        if stored.hash != expected.hash {
+            return Err(Error::ManifestMismatch(format!(
+                "hash is {}, supported hash is {}",
+                stored.hash, expected.hash
+            )));
+        }
+
+        // 旧版本格式可直接读取，新写入的数据使用当前格式
+        if stored.format_version < expected.format_version {
+            info!(path, from = stored.format_version, to = expected.format_version, "upgrading data directory format");
+            return expected.write(path);
+        }
+        Ok(())
+    }
+
+    // 先写临时文件再改名，避免写入中断留下不完整的元信息
+    fn write(&self, path: &str) -> Result<(), Error> {
+        let tmp_path = state::build_path(path, MANIFEST_TMP_FILE_NAME);
+        let mut tmp = File::create(&tmp_path).map_err(Error::ManifestWriteFailed)?;
+        tmp.write_all(self.encode().as_bytes()).map_err(Error::ManifestWriteFailed)?;
+        tmp.sync_all().map_err(Error::ManifestWriteFailed)?;
+        fs::rename(&tmp_path, state::build_path(path, MANIFEST_FILE_NAME)).map_err(Error::ManifestWriteFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::StorageConfig;
+
+    use super::*;
+
+    fn get_conf(path: &str) -> KvConfig {
+        KvConfig {
+            storage: StorageConfig {
+                path: path.to_string(),
+                block_size: 1024,
+                page_max_cap: 1024 * 1024,
+                ..Default::default()
+            },
+            cache_cap: 1024,
+            slot_qty: 1024,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lock() {
+        let path = "/tmp/terra/tests/manifest-lock";
+        let _ = fs::remove_dir_all(path);
+
+        let lock = DirLock::acquire(path).unwrap();
+        assert!(matches!(DirLock::acquire(path), Err(Error::DirLocked(_))));
+
+        drop(lock);
+        assert!(DirLock::acquire(path).is_ok());
+    }
+
+    #[test]
+    fn test_check() {
+        let path = "/tmp/terra/tests/manifest-check";
+        let _ = fs::remove_dir_all(path);
+        let _ = fs::create_dir_all(path);
+
+        let conf = get_conf(path);
+        Manifest::check(path, &conf).unwrap();
+        Manifest::check(path, &conf).unwrap();
+
+        let mut other = conf.clone();
+        other.slot_qty = 2048;
+        assert!(matches!(Manifest::check(path, &other), Err(Error::ManifestMismatch(_))));
+
+        let mut other = conf.clone();
+        other.storage.block_size = 4096;
+        assert!(matches!(Manifest::check(path, &other), Err(Error::ManifestMismatch(_))));
+
+        let content = fs::read_to_string(state::build_path(path, MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(Manifest::decode(&content).unwrap(), Manifest::from_config(&conf));
+
+        // 旧格式升级，新格式拒绝打开
+        let mut old = Manifest::from_config(&conf);
+        old.format_version = 1;
+        old.write(path).unwrap();
+        Manifest::check(path, &conf).unwrap();
+        let content = fs::read_to_string(state::build_path(path, MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(Manifest::decode(&content).unwrap().format_version, FORMAT_VERSION);
+
+        let mut newer = Manifest::from_config(&conf);
+        newer.format_version = FORMAT_VERSION + 1;
+        newer.write(path).unwrap();
+        assert!(matches!(Manifest::check(path, &conf), Err(Error::ManifestMismatch(_))));
+
+        fs::write(state::build_path(path, MANIFEST_FILE_NAME), "format_version=1\n").unwrap();
+        assert!(matches!(Manifest::check(path, &conf), Err(Error::InvalidManifest(_))));
+    }
+}
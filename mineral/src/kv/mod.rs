@@ -4,6 +4,7 @@ type Bytes = Vec<u8>;
 mod slot;
 use slot::Slot;
 
-mod cbf;
 mod wal;
+pub mod vlog;
+pub mod manifest;
 pub mod hash;
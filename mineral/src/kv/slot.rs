@@ -1,25 +1,134 @@
 
 use std::{collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
-use crc32fast::Hasher;
 use crate::error::Error;
+use crate::flate::{self, Codec};
+use super::vlog::ValuePointer;
 use super::Bytes;
 
 pub const EXPIRE_DEL: u64 = 1;
 
+// key-len 高 8 位用于存放 tag，key 长度上限为 2^24 - 1
+// tag 最高位标识 value 为值日志指针，次高位标识 value 前 8 字节为版本号，其余位为压缩算法标识
+pub const KEY_LEN_MASK: u32 = 0x00FF_FFFF;
+pub const TAG_SHIFT: u32 = 24;
+const TAG_POINTER: u8 = 0x80;
+const TAG_VERSION: u8 = 0x40;
+const VERSION_LEN: usize = 8;
+
+// 槽位变更类型
+const DELTA_PUT: u8 = 1;
+const DELTA_DEL: u8 = 2;
+
 #[derive(Debug, Clone)]
 pub struct SlotEntry {
     expires_at: u64,    // timestamp
-    pub value: Vec<u8>,
+    pub codec: Codec,   // value 的压缩算法
+    pub pointer: bool,  // value 是否为值日志指针
+    pub version: u64,   // 写入版本号，0 表示无版本的旧数据
+    pub value: Vec<u8>, // 落盘形式的 value，可能已压缩或为值日志指针
 }
 
 impl SlotEntry {
     pub fn new(val: &Vec<u8>, exp: u64) -> Self {
+        Self::with_codec(val, Codec::None, exp)
+    }
+
+    pub fn with_codec(val: &Vec<u8>, codec: Codec, exp: u64) -> Self {
         SlotEntry {
             value: val.clone(),
+            codec,
+            pointer: false,
+            version: 0,
+            expires_at: exp,
+        }
+    }
+
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    // 值日志指针条目，codec 为值日志中所存 value 的压缩算法
+    pub fn pointer(ptr: &ValuePointer, codec: Codec, exp: u64) -> Self {
+        SlotEntry {
+            value: ptr.encode(),
+            codec,
+            pointer: true,
+            version: 0,
             expires_at: exp,
         }
     }
 
+    // val 为落盘数据，带版本号时前 8 字节为版本号
+    pub fn from_tag(val: &Vec<u8>, tag: u8, exp: u64) -> Result<Self, Error> {
+        let codec = Codec::from_tag(tag & !(TAG_POINTER | TAG_VERSION))?;
+        let mut entry = if tag & TAG_VERSION != 0 {
+            if val.len() < VERSION_LEN {
+                return Err(Error::SlotDecodeFailed("missing entry version".to_string()));
+            }
+            let version = u64::from_be_bytes(val[..VERSION_LEN].try_into().unwrap());
+            Self::with_codec(&val[VERSION_LEN..].to_vec(), codec, exp).with_version(version)
+        } else {
+            Self::with_codec(val, codec, exp)
+        };
+        entry.pointer = tag & TAG_POINTER != 0;
+        Ok(entry)
+    }
+
+    pub fn tag(&self) -> u8 {
+        let mut tag = self.codec.tag();
+        if self.pointer {
+            tag |= TAG_POINTER;
+        }
+        if self.version > 0 {
+            tag |= TAG_VERSION;
+        }
+        tag
+    }
+
+    // 落盘的 value，带版本号时在前面加上版本号
+    pub fn stored_value(&self) -> Vec<u8> {
+        if self.version == 0 {
+            return self.value.clone();
+        }
+        let mut buf = self.version.to_be_bytes().to_vec();
+        buf.extend_from_slice(&self.value);
+        buf
+    }
+
+    pub fn value_pointer(&self) -> Result<Option<ValuePointer>, Error> {
+        if !self.pointer {
+            return Ok(None);
+        }
+        ValuePointer::decode(&self.value).map(Some)
+    }
+
+    // 按阈值压缩 value，小于阈值或压缩无收益时保持原样
+    pub fn compress(val: &Vec<u8>, codec: Codec, threshold: usize, exp: u64) -> Result<Self, Error> {
+        if codec == Codec::None || threshold == 0 || val.len() < threshold {
+            return Ok(Self::new(val, exp));
+        }
+
+        let compressed = flate::compress(codec, val)?;
+        if compressed.len() >= val.len() {
+            return Ok(Self::new(val, exp));
+        }
+
+        Ok(Self::with_codec(&compressed, codec, exp))
+    }
+
+    // 返回解压后的 value，指针条目需先从值日志读取
+    pub fn decode_value(&self) -> Result<Vec<u8>, Error> {
+        if self.pointer {
+            return Err(Error::InvalidValueLog("value is stored in value log".to_string()));
+        }
+        flate::decompress(self.codec, &self.value)
+    }
+
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+
     pub fn has_expired(&self) -> bool {
         if self.expires_at > 0 {
             return SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() > self.expires_at;
@@ -30,27 +139,24 @@ impl SlotEntry {
 
 #[derive(Debug, Clone)]
 pub struct Slot {
-    pub slot_no: usize,
     pub slot_kv: HashMap<Bytes, SlotEntry>
 }
 
 impl Slot {
 
-    pub fn new(slot_no: usize, bytes: Bytes) -> Result<Slot, Error> {
+    pub fn new(bytes: Bytes) -> Result<Slot, Error> {
         if bytes.len() == 0 {
             return Ok(Slot {
-                slot_no,
                 slot_kv: HashMap::new(),
             });
         }
 
         let mut slot = Slot {
-            slot_no,
             slot_kv: HashMap::new()
         };
 
         if bytes.len() > 0 {
-            slot.decode_kv(&bytes);
+            slot.decode_kv(&bytes)?;
         }
 
         Ok(slot)
@@ -60,8 +166,7 @@ impl Slot {
         self.slot_kv.get(key).map(|bytes| bytes.clone())
     }
 
-    pub fn set(&mut self, key: &Bytes, val: &Bytes, expire: u64) -> Option<SlotEntry> {
-        let entry = SlotEntry::new(val, expire);
+    pub fn put(&mut self, key: &Bytes, entry: SlotEntry) -> Option<SlotEntry> {
         self.slot_kv.insert(key.to_vec(), entry)
     }
 
@@ -114,7 +219,8 @@ impl Slot {
     //     }
     // }
 
-    pub fn decode_kv(&mut self, buf: &Vec<u8>) {
+    // tag 无法识别时返回错误，跳过会让该 key 在下次编码时被永久丢弃
+    pub fn decode_kv(&mut self, buf: &Vec<u8>) -> Result<(), Error> {
         let mut buf = buf.clone();
         
         let mut buf_len = buf.len();
@@ -135,14 +241,17 @@ impl Slot {
             }
 
             let key_len = u32::from_be_bytes(new_buf[16..20].try_into().unwrap());
-            let key_end = (20 + key_len) as usize;
+            let key_end = (20 + (key_len & KEY_LEN_MASK)) as usize;
             let key = new_buf[20..key_end].to_vec();
-            let slot = SlotEntry::new(&new_buf[key_end..].to_vec(), expires_at);
+            let tag = (key_len >> TAG_SHIFT) as u8;
+            let slot = SlotEntry::from_tag(&new_buf[key_end..].to_vec(), tag, expires_at)
+                .map_err(|err| Error::SlotDecodeFailed(format!("entry {:?}: {}", String::from_utf8_lossy(&key), err)))?;
 
             self.slot_kv.insert(key, slot);
 
         }
 
+        Ok(())
     }
     
     // // |----- header -----|-------------- data --------------|
@@ -176,34 +285,124 @@ impl Slot {
     // }
 
 
-    // |-- header--|-------------- data --------------|
-    // +-----8-----+------8-----+----4----+--n--+--n--+
-    // | total-len | expires-at | key-len | key | val |
-    // +-----------+------------+---------+-----+-----+
+    // |-- header--|------------------ data ------------------|
+    // +-----8-----+------8-----+--1--+---3---+--n--+--n--+
+    // | total-len | expires-at | tag | key-len | key | val |
+    // +-----------+------------+-----+---------+-----+-----+
+    // tag 为 value 的压缩算法、值日志指针及版本号标识，旧数据 tag 恒为 0（未压缩）
     pub fn encode(&self) -> Result<Vec<u8>, Error> {
         let mut buf: Vec<u8> = vec![];
-        for (key, mut val) in self.slot_kv.clone() {
+        for (key, val) in self.slot_kv.iter() {
             if val.has_expired() {
                 continue;
             }
-            let key_len = key.len() as u32;
-            let total_len = (key_len as usize + val.value.len() + 20) as u64;
+            Self::encode_entry(key, val, &mut buf)?;
+        }
 
-            // 开始组装buf
-            buf.append(total_len.to_be_bytes().to_vec().as_mut());
-            buf.append(val.expires_at.to_be_bytes().to_vec().as_mut());
-            buf.append(key_len.to_be_bytes().to_vec().as_mut());
-            buf.append(key.to_vec().as_mut());
-            buf.append(val.value.as_mut());
+        Ok(buf)
+    }
+
+    fn encode_entry(key: &Bytes, val: &SlotEntry, buf: &mut Vec<u8>) -> Result<(), Error> {
+        if key.len() > KEY_LEN_MASK as usize {
+            return Err(Error::SlotEncodeFailed(format!("key too long: {}", key.len())));
         }
+        let key_len = key.len() as u32;
+        let mut value = val.stored_value();
+        let total_len = (key_len as usize + value.len() + 20) as u64;
+        let tag_key_len = ((val.tag() as u32) << TAG_SHIFT) | key_len;
+
+        // 开始组装buf
+        buf.append(total_len.to_be_bytes().to_vec().as_mut());
+        buf.append(val.expires_at.to_be_bytes().to_vec().as_mut());
+        buf.append(tag_key_len.to_be_bytes().to_vec().as_mut());
+        buf.append(key.to_vec().as_mut());
+        buf.append(&mut value);
+        Ok(())
+    }
 
+    // 单个 key 的变更，存储层预写日志只记录变更，重放时由 merge 合并到槽位
+    // +--1--+--------n--------+
+    // | op  | 槽位条目 / key  |
+    // +-----+-----------------+
+    pub fn encode_put(key: &Bytes, val: &SlotEntry) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![DELTA_PUT];
+        Self::encode_entry(key, val, &mut buf)?;
         Ok(buf)
     }
 
-    fn checksum(buf: &Vec<u8>) -> u32 {
-        let mut hasher = Hasher::new();
-        hasher.update(buf);
-        hasher.finalize()
+    pub fn encode_del(key: &Bytes) -> Vec<u8> {
+        let mut buf = vec![DELTA_DEL];
+        buf.extend_from_slice(key);
+        buf
     }
-    
-}
\ No newline at end of file
+
+    // 将变更合并到槽位数据 base，返回合并后的槽位数据
+    pub fn merge(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut slot = Slot::new(base.to_vec())?;
+        match delta.first() {
+            Some(&DELTA_PUT) if delta.len() >= 21 => {
+                let total_len = u64::from_be_bytes(delta[1..9].try_into().unwrap()) as usize;
+                let key_len = u32::from_be_bytes(delta[17..21].try_into().unwrap()) & KEY_LEN_MASK;
+                let key_end = 21 + key_len as usize;
+                if total_len != delta.len() - 1 || delta.len() < key_end {
+                    return Err(Error::SlotDecodeFailed("truncated slot delta".to_string()));
+                }
+                // 已过期的条目解码时跳过，先删除旧条目
+                slot.del(&delta[21..key_end].to_vec());
+                slot.decode_kv(&delta[1..].to_vec())?;
+            },
+            Some(&DELTA_DEL) => {
+                slot.del(&delta[1..].to_vec());
+            },
+            _ => return Err(Error::SlotDecodeFailed("invalid slot delta".to_string())),
+        }
+        slot.encode()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_unknown_codec() {
+        let mut slot = Slot::new(vec![]).unwrap();
+        slot.put(&b"a".to_vec(), SlotEntry::new(&b"1".to_vec(), 0));
+        let mut buf = slot.encode().unwrap();
+        assert_eq!(Slot::new(buf.clone()).unwrap().get(&b"a".to_vec()).unwrap().value, b"1".to_vec());
+
+        // tag 位于 expires-at 之后，改成未知的压缩算法
+        buf[16] = 0x3f;
+        assert!(matches!(Slot::new(buf), Err(Error::SlotDecodeFailed(_))));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut slot = Slot::new(vec![]).unwrap();
+        slot.put(&b"a".to_vec(), SlotEntry::new(&b"1".to_vec(), 0));
+        slot.put(&b"b".to_vec(), SlotEntry::new(&b"2".to_vec(), 0));
+        let base = slot.encode().unwrap();
+
+        let put = Slot::encode_put(&b"a".to_vec(), &SlotEntry::new(&b"3".to_vec(), 0).with_version(7)).unwrap();
+        let merged = Slot::new(Slot::merge(&base, &put).unwrap()).unwrap();
+        assert_eq!(merged.get(&b"a".to_vec()).unwrap().value, b"3".to_vec());
+        assert_eq!(merged.get(&b"a".to_vec()).unwrap().version, 7);
+        assert_eq!(merged.get(&b"b".to_vec()).unwrap().value, b"2".to_vec());
+
+        // 重复合并结果不变，重放时的基准可能已包含该变更
+        let del = Slot::encode_del(&b"b".to_vec());
+        let once = Slot::merge(&merged.encode().unwrap(), &del).unwrap();
+        let twice = Slot::new(Slot::merge(&once, &del).unwrap()).unwrap();
+        assert!(twice.get(&b"b".to_vec()).is_none());
+        assert_eq!(twice.slot_kv.len(), 1);
+
+        // 过期的写入等同于删除
+        let expired = Slot::encode_put(&b"a".to_vec(), &SlotEntry::new(&b"4".to_vec(), 2)).unwrap();
+        assert!(Slot::new(Slot::merge(&once, &expired).unwrap()).unwrap().get(&b"a".to_vec()).is_none());
+
+        assert!(matches!(Slot::merge(&once, &[9]), Err(Error::SlotDecodeFailed(_))));
+        assert!(matches!(Slot::merge(&once, &put[..10]), Err(Error::SlotDecodeFailed(_))));
+        assert!(matches!(Slot::merge(&once, &put[..put.len() - 1]), Err(Error::SlotDecodeFailed(_))));
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crc32fast::Hasher;
+use glob::glob;
+
+use crate::error::Error;
+use crate::state::{self, disk::Disk};
+
+use super::Bytes;
+
+const VLOG_NAME: &str = "@vlog";
+
+// 单个段文件默认上限 64MB
+const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+const ENTRY_HEADER_LEN: usize = 12;
+pub const POINTER_LEN: usize = 20;
+
+// 值日志中的位置，槽位中只保存该指针
+//     8         8        4
+// +---------+--------+-----+
+// | segment | offset | len |
+// +---------+--------+-----+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValuePointer {
+    pub segment: u64,
+    pub offset: u64,
+    pub len: u32, // 条目总长度，含头部
+}
+
+impl ValuePointer {
+    pub fn encode(&self) -> Bytes {
+        let mut buf = self.segment.to_be_bytes().to_vec();
+        buf.extend_from_slice(&self.offset.to_be_bytes());
+        buf.extend_from_slice(&self.len.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() != POINTER_LEN {
+            return Err(Error::InvalidValueLog(format!("invalid pointer length: {}", buf.len())));
+        }
+        Ok(ValuePointer {
+            segment: u64::from_be_bytes(buf[0..8].try_into().unwrap()),
+            offset: u64::from_be_bytes(buf[8..16].try_into().unwrap()),
+            len: u32::from_be_bytes(buf[16..20].try_into().unwrap()),
+        })
+    }
+}
+
+// 段内条目
+//    4        4         4       n     n
+// +-------+---------+---------+-----+-----+
+// | crc32 | key-len | val-len | key | val |
+// +-------+---------+---------+-----+-----+
+// 保存 key 用于 gc 时反查槽位判断值是否存活
+#[derive(Debug)]
+pub struct ValueLog {
+    path: String,
+    segment_max_size: u64,
+    segments: BTreeSet<u64>,
+    active: Disk,
+    active_seq: u64,
+    active_size: u64,
+}
+
+impl ValueLog {
+    pub fn new(path: &str, segment_max_size: u64) -> Result<Self, Error> {
+        let segments = Self::get_segments(path);
+        let active_seq = segments.last().copied().unwrap_or(0);
+        let mut active = Disk::open(&Self::build_segment_name(path, active_seq)).map_err(Error::ValueLogWriteFailed)?;
+
+        // 崩溃时写入了一部分的条目留在当前段尾部，截掉后新条目才能紧接最后一个完整条目写入
+        let size = active.meta().map_err(Error::ValueLogReadFailed)?.size as u64;
+        let active_size = Self::valid_len(&mut active, size)?;
+        if active_size < size {
+            active.set_len(active_size as usize)
+                .and_then(|_| active.sync())
+                .map_err(Error::ValueLogWriteFailed)?;
+        }
+
+        let mut segments = segments;
+        segments.insert(active_seq);
+
+        Ok(ValueLog {
+            path: path.to_string(),
+            segment_max_size: if segment_max_size == 0 { DEFAULT_SEGMENT_SIZE } else { segment_max_size },
+            segments,
+            active,
+            active_seq,
+            active_size,
+        })
+    }
+
+    // 返回前条目已落盘，调用方随后才能把指针写入预写日志
+    pub fn append(&mut self, key: &Bytes, val: &Bytes) -> Result<ValuePointer, Error> {
+        if self.active_size >= self.segment_max_size {
+            self.rotate()?;
+        }
+
+        let buf = Self::encode_entry(key, val);
+        let written = self.active.append(&buf).and_then(|_| self.active.sync());
+        if let Err(err) = written {
+            // 截掉写入了一部分的条目，保证后续指针的偏移正确
+            let _ = self.active.set_len(self.active_size as usize);
+            return Err(Error::ValueLogWriteFailed(err));
+        }
+
+        let ptr = ValuePointer {
+            segment: self.active_seq,
+            offset: self.active_size,
+            len: buf.len() as u32,
+        };
+        self.active_size += buf.len() as u64;
+
+        Ok(ptr)
+    }
+
+    // 读取指针指向的条目，返回 (key, val)
+    pub fn read(&mut self, ptr: &ValuePointer) -> Result<(Bytes, Bytes), Error> {
+        let mut buf = vec![0u8; ptr.len as usize];
+        let n = if ptr.segment == self.active_seq {
+            self.active.get(ptr.offset as usize, &mut buf)
+        } else {
+            self.open_segment(ptr.segment)?.get(ptr.offset as usize, &mut buf)
+        }.map_err(Error::ValueLogReadFailed)?;
+
+        if n != buf.len() {
+            return Err(Error::InvalidValueLog(format!(
+                "short read at segment {} offset {}", ptr.segment, ptr.offset)));
+        }
+
+        Self::decode_entry(&buf)
+    }
+
+    // (段数, 总字节数)
+    pub fn usage(&self) -> (usize, u64) {
+        let size = self.segments.iter()
+            .filter_map(|seq| fs::metadata(Self::build_segment_name(&self.path, *seq)).ok())
+            .map(|meta| meta.len())
+            .sum();
+        (self.segments.len(), size)
+    }
+
+    // 已封存的段，按从旧到新排序，不含当前写入段
+    pub fn sealed_segments(&self) -> Vec<u64> {
+        self.segments.iter()
+            .filter(|seq| **seq != self.active_seq)
+            .copied()
+            .collect()
+    }
+
+    // 顺序读取段内全部条目
+    pub fn segment_entries(&mut self, segment: u64) -> Result<Vec<(ValuePointer, Bytes, Bytes)>, Error> {
+        Self::read_entries(self.open_segment(segment)?, segment)
+    }
+
+    // 读取 open_segment 打开的段，已封存的段不再写入，读取时无需持有值日志
+    pub(crate) fn read_entries(mut disk: Disk, segment: u64) -> Result<Vec<(ValuePointer, Bytes, Bytes)>, Error> {
+        let size = disk.meta().map_err(Error::ValueLogReadFailed)?.size as u64;
+
+        let mut entries = vec![];
+        let mut offset = 0u64;
+        while offset + ENTRY_HEADER_LEN as u64 <= size {
+            let mut header = [0u8; ENTRY_HEADER_LEN];
+            disk.get(offset as usize, &mut header).map_err(Error::ValueLogReadFailed)?;
+            let key_len = u32::from_be_bytes(header[4..8].try_into().unwrap()) as u64;
+            let val_len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as u64;
+            let len = ENTRY_HEADER_LEN as u64 + key_len + val_len;
+            // 尾部写入不完整的条目直接丢弃
+            if offset + len > size {
+                break;
+            }
+
+            let ptr = ValuePointer { segment, offset, len: len as u32 };
+            let mut buf = vec![0u8; len as usize];
+            disk.get(offset as usize, &mut buf).map_err(Error::ValueLogReadFailed)?;
+            let (key, val) = Self::decode_entry(&buf)?;
+            entries.push((ptr, key, val));
+
+            offset += len;
+        }
+
+        Ok(entries)
+    }
+
+    // 替换当前段的文件句柄，用于模拟写入失败
+    #[cfg(test)]
+    pub(crate) fn replace_active_for_test(&mut self, active: Disk) -> Disk {
+        std::mem::replace(&mut self.active, active)
+    }
+
+    // 段内从头开始连续完整且校验通过的条目的总长度
+    fn valid_len(disk: &mut Disk, size: u64) -> Result<u64, Error> {
+        let mut offset = 0u64;
+        while offset + ENTRY_HEADER_LEN as u64 <= size {
+            let mut header = [0u8; ENTRY_HEADER_LEN];
+            disk.get(offset as usize, &mut header).map_err(Error::ValueLogReadFailed)?;
+            let key_len = u32::from_be_bytes(header[4..8].try_into().unwrap()) as u64;
+            let val_len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as u64;
+            let len = ENTRY_HEADER_LEN as u64 + key_len + val_len;
+            if offset + len > size {
+                break;
+            }
+
+            let mut buf = vec![0u8; len as usize];
+            disk.get(offset as usize, &mut buf).map_err(Error::ValueLogReadFailed)?;
+            if Self::decode_entry(&buf).is_err() {
+                break;
+            }
+
+            offset += len;
+        }
+        Ok(offset)
+    }
+
+    pub fn remove_segment(&mut self, segment: u64) -> Result<(), Error> {
+        if segment == self.active_seq {
+            return Err(Error::InvalidValueLog(format!("can not remove active segment {}", segment)));
+        }
+        fs::remove_file(Self::build_segment_name(&self.path, segment))
+            .map_err(Error::ValueLogWriteFailed)?;
+        self.segments.remove(&segment);
+        Ok(())
+    }
+
+    // 封存当前段，切换到新段写入
+    pub fn rotate(&mut self) -> Result<(), Error> {
+        let seq = self.active_seq + 1;
+        self.active = Disk::open(&Self::build_segment_name(&self.path, seq)).map_err(Error::ValueLogWriteFailed)?;
+        self.active_seq = seq;
+        self.active_size = 0;
+        self.segments.insert(seq);
+        Ok(())
+    }
+
+    pub(crate) fn open_segment(&self, segment: u64) -> Result<Disk, Error> {
+        let name = Self::build_segment_name(&self.path, segment);
+        if !self.segments.contains(&segment) || !Path::new(&name).exists() {
+            return Err(Error::InvalidValueLog(format!("segment {} not found", segment)));
+        }
+        Disk::open(&name).map_err(Error::ValueLogReadFailed)
+    }
+
+    fn encode_entry(key: &Bytes, val: &Bytes) -> Bytes {
+        let mut data = (key.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&(val.len() as u32).to_be_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(val);
+
+        let mut buf = Self::checksum(&data).to_be_bytes().to_vec();
+        buf.append(&mut data);
+        buf
+    }
+
+    fn decode_entry(buf: &[u8]) -> Result<(Bytes, Bytes), Error> {
+        if buf.len() < ENTRY_HEADER_LEN {
+            return Err(Error::InvalidValueLog("entry too short".to_string()));
+        }
+        let crc32 = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        if crc32 != Self::checksum(&buf[4..]) {
+            return Err(Error::InvalidValueLog("entry checksum mismatch".to_string()));
+        }
+        let key_len = u32::from_be_bytes(buf[4..8].try_into().unwrap()) as usize;
+        let val_len = u32::from_be_bytes(buf[8..12].try_into().unwrap()) as usize;
+        if ENTRY_HEADER_LEN + key_len + val_len != buf.len() {
+            return Err(Error::InvalidValueLog("entry length mismatch".to_string()));
+        }
+        let key_end = ENTRY_HEADER_LEN + key_len;
+        Ok((buf[ENTRY_HEADER_LEN..key_end].to_vec(), buf[key_end..].to_vec()))
+    }
+
+    fn checksum(buf: &[u8]) -> u32 {
+        let mut hasher = Hasher::new();
+        hasher.update(buf);
+        hasher.finalize()
+    }
+
+    pub(crate) fn build_segment_name(path: &str, seq: u64) -> String {
+        state::build_path(path, &format!("{}-{}", VLOG_NAME, seq))
+    }
+
+    fn get_segments(path: &str) -> BTreeSet<u64> {
+        let glob_path = state::build_path(path, &format!("{}-*", VLOG_NAME));
+
+        let globs = glob(&glob_path)
+                .expect("Failed to read glob pattern");
+
+        let mut list = BTreeSet::new();
+        for entry in globs.flatten() {
+            let seq = entry.file_name()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.rsplit('-').next())
+                .and_then(|last| last.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                list.insert(seq);
+            }
+        }
+
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_read() {
+        let path = "/tmp/terra/tests/vlog-append";
+        let _ = fs::remove_dir_all(path);
+
+        let mut vlog = ValueLog::new(path, 64).unwrap();
+        let key = "foo".as_bytes().to_vec();
+        let val = vec![3u8; 100];
+
+        let p1 = vlog.append(&key, &val).unwrap();
+        let p2 = vlog.append(&key, &vec![4u8; 10]).unwrap();
+        assert_eq!(p1.segment, 0);
+        assert_eq!(p2.segment, 1);
+        assert_eq!(ValuePointer::decode(&p1.encode()).unwrap(), p1);
+
+        assert_eq!(vlog.read(&p1).unwrap(), (key.clone(), val));
+        assert_eq!(vlog.read(&p2).unwrap().1, vec![4u8; 10]);
+        assert_eq!(vlog.sealed_segments(), vec![0]);
+        assert_eq!(vlog.segment_entries(0).unwrap().len(), 1);
+
+        // 重新打开后继续在最新段写入
+        let mut vlog = ValueLog::new(path, 64).unwrap();
+        let p3 = vlog.append(&key, &vec![5u8; 10]).unwrap();
+        assert_eq!(p3.segment, 1);
+        assert_eq!(p3.offset, p2.len as u64);
+
+        vlog.remove_segment(0).unwrap();
+        assert!(vlog.read(&p1).is_err());
+        assert!(vlog.remove_segment(1).is_err());
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let path = "/tmp/terra/tests/vlog-torn";
+        let _ = fs::remove_dir_all(path);
+
+        let mut vlog = ValueLog::new(path, 1024).unwrap();
+        let key = "foo".as_bytes().to_vec();
+        let p1 = vlog.append(&key, &vec![1u8; 100]).unwrap();
+        let p2 = vlog.append(&key, &vec![2u8; 100]).unwrap();
+        drop(vlog);
+
+        // 模拟写入 p2 时崩溃，条目只落盘了一部分
+        let name = ValueLog::build_segment_name(path, 0);
+        let file = fs::OpenOptions::new().write(true).open(&name).unwrap();
+        file.set_len(p2.offset + p2.len as u64 - 5).unwrap();
+        drop(file);
+
+        // 重新打开后截掉残缺条目，新条目紧接 p1 写入
+        let mut vlog = ValueLog::new(path, 1024).unwrap();
+        assert_eq!(fs::metadata(&name).unwrap().len(), p1.len as u64);
+        let p3 = vlog.append(&key, &vec![3u8; 100]).unwrap();
+        assert_eq!(p3.offset, p1.len as u64);
+        assert_eq!(vlog.read(&p3).unwrap().1, vec![3u8; 100]);
+
+        // 封存后 gc 能完整读取该段
+        vlog.rotate().unwrap();
+        let entries = vlog.segment_entries(0).unwrap();
+        assert_eq!(entries.iter().map(|(ptr, _, _)| *ptr).collect::<Vec<_>>(), vec![p1, p3]);
+    }
+
+    #[test]
+    fn test_open_error() {
+        let path = "/tmp/terra/tests/vlog-open";
+        let _ = fs::remove_dir_all(path);
+        fs::create_dir_all(path).unwrap();
+
+        // 段文件路径被目录占用时返回错误而不是 panic
+        fs::create_dir_all(format!("{}/{}-0", path, VLOG_NAME)).unwrap();
+        assert!(matches!(ValueLog::new(path, 64), Err(Error::ValueLogWriteFailed(_))));
+    }
+}
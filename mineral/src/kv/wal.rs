@@ -1,10 +1,13 @@
 use crate::{error::Error, storage::wal::{Wal, WalReader}};
 
+use super::slot::{SlotEntry, KEY_LEN_MASK, TAG_SHIFT};
 use super::Bytes;
 
+// 旧版本 kv 层预写日志，写入已统一由存储层预写日志完成，这里只用于打开时回放
+// keylen 高 8 位存放 value 的 tag，与槽位条目保持一致
 pub struct KvWalEntryHeader {
     pub expires_at: u64, // 过期时间，精确到秒
-    keylen: u32, // key 长度
+    pub tag: u8, // value 压缩算法及值日志指针标识
 }
 
 pub struct KvWalEntry {
@@ -14,42 +17,51 @@ pub struct KvWalEntry {
 }
 
 impl KvWalEntry {
-    fn new(op: u8, key: &Bytes, val: &Bytes, expires_at: u64) -> Self {
+    #[cfg(test)]
+    fn new(key: &Bytes, val: &Bytes, tag: u8, expires_at: u64) -> Self {
         KvWalEntry {
             header: KvWalEntryHeader{
-                keylen: key.len() as u32,
                 expires_at,
+                tag,
             },
             key: key.clone(),
             val: val.clone()
         }
     }
 
+    // +------8-----+--1--+---3---+--n--+--n--+
+    // | expires-at | tag | keylen | key | val |
+    // +------------+-----+--------+-----+-----+
+    #[cfg(test)]
     fn encode(&mut self) -> Bytes {
+        let tag_keylen = ((self.header.tag as u32) << TAG_SHIFT) | self.key.len() as u32;
         let mut buf = self.header.expires_at.to_be_bytes().to_vec();
-        buf.append(self.header.keylen.to_be_bytes().to_vec().as_mut());
+        buf.append(tag_keylen.to_be_bytes().to_vec().as_mut());
         buf.append(self.key.to_vec().as_mut());
         buf.append(self.val.as_mut());
         buf
     }
 
-    pub fn decode(buf: Bytes) -> Self {
+    pub fn decode(buf: Bytes) -> Result<Self, Error> {
         let expires_at = u64::from_be_bytes(buf[0..8].try_into().unwrap());
-        let keylen = u32::from_be_bytes(buf[8..12].try_into().unwrap());
+        let tag_keylen = u32::from_be_bytes(buf[8..12].try_into().unwrap());
+        let tag = (tag_keylen >> TAG_SHIFT) as u8;
+        let keylen = tag_keylen & KEY_LEN_MASK;
         let key_end = (keylen + 12) as usize;
         let key = buf[12..key_end].to_vec();
         let val = buf[key_end..].to_vec();
-        KvWalEntry {
+        Ok(KvWalEntry {
             header: KvWalEntryHeader{
-                keylen: keylen,
                 expires_at,
+                tag,
             }, key, val
-        }
+        })
     }
-}
 
-const OP_SET: u8 = 1;
-const OP_DEL: u8 = 2;
+    pub fn slot_entry(&self) -> Result<SlotEntry, Error> {
+        SlotEntry::from_tag(&self.val, self.header.tag, self.header.expires_at)
+    }
+}
 
 #[derive(Debug)]
 pub struct KvWal {
@@ -63,12 +75,15 @@ impl KvWal {
         }
     }
 
-    pub fn set(&mut self, key: &Bytes, val: &Bytes, expire: u64) -> Result<u64, Error> {
-        self.append(OP_SET, key, val, expire)
+    // 测试中用于构造旧版本日志
+    #[cfg(test)]
+    pub fn set(&mut self, key: &Bytes, entry: &SlotEntry) -> Result<u64, Error> {
+        let mut entry = KvWalEntry::new(key, &entry.stored_value(), entry.tag(), entry.expires_at());
+        self.wal.append(&entry.encode())
     }
 
-    pub fn del(&mut self, key: &Bytes) -> Result<u64, Error> {
-        self.append(OP_DEL, key, &vec![], 0)
+    pub fn version(&self) -> u64 {
+        self.wal.version()
     }
 
     pub fn checkpoint(&mut self, version: u64) -> Vec<u64> {
@@ -79,11 +94,4 @@ impl KvWal {
         self.wal.reader(min_version, max_version)
     }
 
-    fn append(&mut self, op: u8, key: &Bytes, val: &Bytes, expire: u64) -> Result<u64, Error> {
-
-        let mut entry = KvWalEntry::new(op, key, val, expire);
-        
-        self.wal.append(&entry.encode())
-    }
-
 }
@@ -1,11 +1,13 @@
 mod state;
-mod error;
+pub mod error;
 pub mod config;
 pub use config::*;
 mod types;
-mod flate;
+pub mod flate;
 mod cache;
+mod worker;
 pub mod storage;
 pub mod kv;
+pub mod metrics;
 pub use kv::hash::HashKv;
 pub mod btree;
\ No newline at end of file
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// 耗时直方图的桶上界，单位微秒，最后一个桶收集更大的值
+pub const LATENCY_BUCKETS_US: [u64; 14] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+];
+
+// 存储运行指标，计数只增不减，由上层按需读取导出
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub cache_hits: AtomicU64,   // lru 命中次数
+    pub cache_misses: AtomicU64, // lru 未命中次数
+    pub cbf_bytes: AtomicU64,    // 待刷盘的缓冲字节数
+    pub wal_append: Histogram,   // 预写日志追加耗时
+    pub flush: Histogram,        // 刷盘一轮的耗时
+}
+
+impl Metrics {
+    pub fn cache_hit(&self, hit: bool) {
+        let counter = if hit { &self.cache_hits } else { &self.cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 命中率，尚无读取时为 0
+    pub fn cache_hit_ratio(&self) -> f64 {
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let total = hits + self.cache_misses.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        hits as f64 / total as f64
+    }
+}
+
+// 固定分桶的耗时直方图，可在多线程间共享
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_US.len() + 1],
+    sum_us: AtomicU64,
+    count: AtomicU64,
+}
+
+// 直方图快照，buckets 为各桶的非累计计数，上界单位秒，最后一个桶上界为 f64::MAX
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64, // 总耗时，单位秒
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let us = elapsed.as_micros() as u64;
+        let idx = LATENCY_BUCKETS_US.iter().position(|bound| us <= *bound).unwrap_or(LATENCY_BUCKETS_US.len());
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 各计数分别读取，并发写入时快照内可能相差几次观测
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let buckets = LATENCY_BUCKETS_US
+            .iter()
+            .map(|bound| *bound as f64 / 1_000_000.0)
+            .chain(std::iter::once(f64::MAX))
+            .zip(&self.buckets)
+            .map(|(bound, count)| (bound, count.load(Ordering::Relaxed)))
+            .collect();
+
+        HistogramSnapshot {
+            buckets,
+            sum: self.sum_us.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+            count: self.count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let hist = Histogram::default();
+        hist.observe(Duration::from_micros(10));
+        hist.observe(Duration::from_micros(50));
+        hist.observe(Duration::from_micros(700));
+        hist.observe(Duration::from_secs(3));
+
+        let snap = hist.snapshot();
+        assert_eq!(snap.count, 4);
+        assert_eq!(snap.buckets.len(), LATENCY_BUCKETS_US.len() + 1);
+        assert_eq!(snap.buckets[0], (0.00005, 2));
+        assert_eq!(snap.buckets[3], (0.0005, 0));
+        assert_eq!(snap.buckets[4], (0.001, 1));
+        assert_eq!(snap.buckets[LATENCY_BUCKETS_US.len()], (f64::MAX, 1));
+        assert!((snap.sum - 3.00076).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cache_hit_ratio() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.cache_hit_ratio(), 0.0);
+
+        metrics.cache_hit(true);
+        metrics.cache_hit(true);
+        metrics.cache_hit(true);
+        metrics.cache_hit(false);
+        assert_eq!(metrics.cache_hit_ratio(), 0.75);
+    }
+}
@@ -18,14 +18,20 @@ pub struct Disk {
 
 impl Disk {
     pub fn new (path: &str) -> Disk {
-        Disk{
+        Self::open(path).unwrap()
+    }
+
+    // 打开或创建文件，失败时返回错误
+    pub fn open(path: &str) -> Result<Disk> {
+        Ok(Disk{
             path: path.to_string(),
             handle: OpenOptions::new()
                 .read(true)
                 .write(true)
                 .create(true)
-                .open(path).unwrap(),
-        }
+                .truncate(false)
+                .open(path)?,
+        })
     }
 
 // }
@@ -54,6 +60,11 @@ impl Disk {
         Ok(())
     }
 
+    pub fn set_len(&mut self, size: usize) -> Result<()> {
+        self.handle.set_len(size as u64)?;
+        Ok(())
+    }
+
     pub fn append(&mut self, buf: &[u8]) -> Result<()> {
         self.handle.seek(SeekFrom::End(0))?;
         self.handle.write_all(buf)?;
@@ -66,12 +77,22 @@ impl Disk {
         Ok(())
     }
 
+    // 将已写入的数据落盘
+    pub fn sync(&self) -> Result<()> {
+        self.handle.sync_data()
+    }
+
+    // 连同文件元数据一起落盘
+    pub fn sync_all(&self) -> Result<()> {
+        self.handle.sync_all()
+    }
+
     pub fn meta(&self) -> Result<MetaData> {
-        let metadata = self.handle.metadata();
+        let metadata = self.handle.metadata()?;
         #[cfg(target_family = "windows")]
-        let file_size = metadata.unwrap().file_size();
+        let file_size = metadata.file_size();
         #[cfg(target_family = "unix")]
-        let file_size = metadata.unwrap().size();
+        let file_size = metadata.size();
 
         Ok(MetaData{
             size: file_size as usize,
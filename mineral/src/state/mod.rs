@@ -36,4 +36,15 @@ pub fn build_path(path: &str, file_name: &str) -> String {
     let binding = binding.join(file_name);
     let datablock_path = binding.to_str().unwrap();
     datablock_path.to_string()
+}
+
+// 落盘目录项，保证目录下新建或改名的文件在崩溃后可见
+#[cfg(target_family = "unix")]
+pub fn sync_dir(path: &str) -> std::io::Result<()> {
+    fs::File::open(path)?.sync_all()
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn sync_dir(_path: &str) -> std::io::Result<()> {
+    Ok(())
 }
\ No newline at end of file
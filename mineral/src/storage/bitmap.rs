@@ -1,56 +1,117 @@
-use std::{collections::BTreeMap, io::Result, str};
+use std::{collections::{BTreeMap, BTreeSet}, fs, io::{Error, ErrorKind, Result}, path::Path};
+use crc32fast::Hasher;
 use crate::state::{self, disk::Disk};
 
+// 旧版位图文件，仅用于迁移
 const BITMAP_FILE_NAME: &str = "@bitmap";
+const EXTENT_FILE_NAME: &str = "@extents";
+const EXTENT_TMP_FILE_NAME: &str = "@extents.tmp";
+const JOURNAL_FILE_NAME: &str = "@extents-journal";
 
+const SNAPSHOT_MAGIC: &[u8; 4] = b"EXT1";
+const SNAPSHOT_HEADER_LEN: usize = 28;
 
+// 日志记录操作标识
+const OP_ALLOC: u8 = 1;
+const OP_FREE: u8 = 2;
+const OP_CHECKPOINT: u8 = 3;
+const OP_TRUNCATE: u8 = 4;
+const OP_TRIM: u8 = 5;
+
+const RECORD_LEN: usize = 21;
+
+// 日志超过该大小时合并为快照
+const JOURNAL_MAX_SIZE: usize = 1024 * 1024;
+
+// 空间以块为单位按空闲区间管理，不再逐位扫描
+// 快照只保存空闲区间，变更以日志追加的方式增量落盘，打开时间只与元数据量相关
 #[derive(Debug)]
 pub struct BitMap {
     checkpoint: u64,
-    version: u64,
     delay: bool,
     meta: BlockMeta,
-    state: Disk,
+
+    path: String,
+    journal: Disk,
+    journal_size: usize,
+    // 延迟模式下待写入的日志，flush_all 时随检查点一起落盘
+    pending: Vec<u8>,
 }
 
+// 日志记录
+//   1      8      8      4
+// +----+-------+------+-------+
+// | op | start | size | crc32 |
+// +----+-------+------+-------+
+// 记录均为幂等操作，快照与日志重叠回放不影响结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Record {
+    op: u8,
+    start: u64,
+    size: u64,
+}
 
-#[derive(Debug)]
+impl Record {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![self.op];
+        buf.extend_from_slice(&self.start.to_be_bytes());
+        buf.extend_from_slice(&self.size.to_be_bytes());
+        let crc32 = checksum(&buf);
+        buf.extend_from_slice(&crc32.to_be_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8]) -> Option<Record> {
+        if buf.len() < RECORD_LEN {
+            return None;
+        }
+        let crc32 = u32::from_be_bytes(buf[17..21].try_into().unwrap());
+        if crc32 != checksum(&buf[..17]) {
+            return None;
+        }
+        Some(Record {
+            op: buf[0],
+            start: u64::from_be_bytes(buf[1..9].try_into().unwrap()),
+            size: u64::from_be_bytes(buf[9..17].try_into().unwrap()),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct BlockMeta {
-    bits: Vec<u8>,
+    // 空闲区间 start -> size
     idles: BTreeMap<usize, usize>,
+    // 按大小索引的空闲区间 (size, start)
+    sizes: BTreeSet<(usize, usize)>,
 
-    max: u64,
-    cnt: u64,
-    objects: u64,
+    // 已使用的总块数，按 8 块对齐
+    total: usize,
+    free_cnt: usize,
 }
 
 impl BlockMeta {
-    pub fn new(bits: Vec<u8>) -> Self {
-        let mut block_meta = BlockMeta {
-            bits: bits,
-            idles: BTreeMap::new(),
-            max: 0,
-            cnt: 0,
-            objects: 0,
-        };
-        block_meta.init_idles();
-        block_meta
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn init_idles(&mut self) {
+    // 由旧版位图构建空闲区间
+    fn from_bits(bits: &[u8]) -> Self {
+        let mut block_meta = Self::new();
+        block_meta.total = bits.len() * 8;
+
         let mut index: usize = 0;
         let mut size: usize = 0;
         let mut i = 0;
-        for byte in self.bits.clone() {
+        for byte in bits {
             for bit_index in 0..8 {
-                if self.get_bit(byte, bit_index as u8) == 0 {
+                if block_meta.get_bit(*byte, bit_index as u8) == 0 {
                     if size == 0 {
                         index = i;
                     }
                     size += 1;
                 } else {
                     if size > 0 {
-                        self.add_idle(index, size);
+                        block_meta.add_idle(index, size);
                     }
                     size = 0;
                 }
@@ -59,76 +120,82 @@ impl BlockMeta {
         }
 
         if size > 0 {
-            self.add_idle(index, size);
-        }
-        
-    }
-
-    fn add_idle(&mut self, index: usize, size: usize) {
-        let mut size = size;
-        if let Some((next_index, next_size)) = self.idles.range(index..).next() {
-            let next_index = *next_index;
-            let index_end = index + size;
-            if next_index <= index_end {
-                size = next_index + *next_size - index;
-                self.idles.remove(&next_index);
-            }
+            block_meta.add_idle(index, size);
         }
 
-        let mut has_pre_neighbor = false;
-        if let Some((pre_index, pre_size)) = self.idles.range(..index).next_back() {
-            if *pre_index + *pre_size >= index {
-                has_pre_neighbor = true;
-                let new_pre_size = index + size - *pre_index;
-                self.idles.insert(*pre_index, new_pre_size);
-            }
-        }
-
-        if !has_pre_neighbor {
-            self.idles.insert(index, size);
-        }
+        block_meta
+    }
 
+    fn insert_idle(&mut self, index: usize, size: usize) {
+        self.idles.insert(index, size);
+        self.sizes.insert((size, index));
+        self.free_cnt += size;
     }
 
-    fn find_idle(&mut self, len: usize) -> Option<usize> {
-        let mut find_index: Option<usize> = None;
-        let min_len = 0;
-        for (key, val) in self.idles.iter() {
-            if (min_len > *val || min_len == 0) && *val >= len {
-                find_index = Some(*key)
-            }
-        }
-        find_index
+    fn remove_idle(&mut self, index: usize) -> Option<usize> {
+        let size = self.idles.remove(&index)?;
+        self.sizes.remove(&(size, index));
+        self.free_cnt -= size;
+        Some(size)
     }
 
-    fn consumer_idle(&mut self, index: usize, size: usize) {
-        if let Some(len) = self.idles.remove(&index) {
-            self.add_idle(index + size, len - size);
+    // 加入空闲区间，与相邻或重叠的区间合并
+    fn add_idle(&mut self, index: usize, size: usize) {
+        if size == 0 {
+            return;
+        }
+        let mut start = index;
+        let mut end = index + size;
+
+        let neighbors: Vec<(usize, usize)> = self.idles.range(..=end)
+            .rev()
+            .take_while(|(idx, sz)| **idx + **sz >= start)
+            .map(|(idx, sz)| (*idx, *sz))
+            .collect();
+
+        for (idx, sz) in neighbors {
+            self.remove_idle(idx);
+            start = start.min(idx);
+            end = end.max(idx + sz);
         }
+
+        self.insert_idle(start, end - start);
     }
 
-    fn toggle_bit(&self, byte: u8, bit_index: u8) -> u8 {
-        if bit_index > 7 {
-            panic!("bit_index out of range (0-7)");
+    // 从空闲区间中移除 [index, index + size)
+    fn consumer_idle(&mut self, index: usize, size: usize) {
+        let end = index + size;
+
+        let overlaps: Vec<(usize, usize)> = self.idles.range(..end)
+            .rev()
+            .take_while(|(idx, sz)| **idx + **sz > index)
+            .map(|(idx, sz)| (*idx, *sz))
+            .collect();
+
+        for (idx, sz) in overlaps {
+            self.remove_idle(idx);
+            if idx < index {
+                self.insert_idle(idx, index - idx);
+            }
+            if idx + sz > end {
+                self.insert_idle(end, idx + sz - end);
+            }
         }
-        let bit_index = 7 - bit_index;
-        byte ^ (1 << bit_index)
     }
 
-    fn toggle_bit_2_1(&self, byte: u8, bit_index: u8) -> u8 {
-        let new_byte = self.toggle_bit(byte, bit_index);
-        if new_byte > byte {
-            return new_byte;
+    // 优先使用最大的空闲区间，均不满足时从尾部扩展
+    fn find_idle(&self, len: usize) -> Option<usize> {
+        match self.sizes.iter().next_back() {
+            Some((size, index)) if *size >= len => Some(*index),
+            _ => None,
         }
-        byte
     }
 
-    fn toggle_bit_2_0(&self, byte: u8, bit_index: u8) -> u8 {
-        let new_byte = self.toggle_bit(byte, bit_index);
-        if new_byte < byte {
-            return new_byte;
+    fn tail_index(&self) -> usize {
+        match self.idles.iter().next_back() {
+            Some((index, size)) if index + size == self.total => *index,
+            _ => self.total,
         }
-        byte
     }
 
     fn get_bit(&self, byte: u8, bit_index: u8) -> u8 {
@@ -139,186 +206,311 @@ impl BlockMeta {
         (byte & (1 << bit_index)) >> bit_index
     }
 
-    fn consumer_bit(&mut self, index: usize, size: usize) {
-        for i in 0..size {
-            let byte_index = (index + i) / 8;
-            let bit_index = (index + i) % 8;
-            self.bits[byte_index] = self.toggle_bit_2_1(self.bits[byte_index], bit_index as u8);
+    fn consumer(&mut self, index: usize, size: usize) {
+        let end = index + size;
+        if end > self.total {
+            // 尾部扩展按 8 块对齐，多出的部分记为空闲
+            let new_total = end.div_ceil(8) * 8;
+            self.add_idle(self.total, new_total - self.total);
+            self.total = new_total;
         }
+        self.consumer_idle(index, size);
     }
 
-    fn malloc_bit(&mut self, size: usize) -> usize {
-        let bits_len = self.bits.len();
-        let mut count = 0;
-        if bits_len > 0 {
-            for bit_index in 0..8 {
-                let toggled = self.toggle_bit_2_1(self.bits[bits_len - 1], bit_index as u8);
-                if toggled > self.bits[bits_len - 1] {
-                    self.bits[bits_len - 1] = toggled;
-                    count += 1;
-                } else {
-                    count = 0;
-                }
-            }
+    fn free(&mut self, index: usize, size: usize) {
+        if index >= self.total {
+            return;
         }
+        let size = size.min(self.total - index);
+        self.add_idle(index, size);
+    }
 
-        let start_index = bits_len * 8 - count;
-        
-        let new_byte_cnt = (size - count + 7) / 8;
-        if new_byte_cnt > 0 {
-            for i in 0..new_byte_cnt {
-                // 最后一个字节
-                if (i + 1) == new_byte_cnt {
-                    let tail_bit_cnt = (size - count) % 8;
-                    self.bits.push(255 ^ (255 >> tail_bit_cnt));
-                    if tail_bit_cnt > 0 {
-                        self.add_idle(start_index + size, 8 - tail_bit_cnt);
-                    }
-                } else {
-                    self.bits.push(255);
-                }
-            }
+    // 回收尾部空闲区间，total 收缩到 index
+    fn trim(&mut self, index: usize) {
+        if index >= self.total {
+            return;
         }
-        
-        start_index
+        self.consumer_idle(index, self.total - index);
+        self.total = index;
     }
 
-    fn free_bit(&mut self, index: usize, size: usize) {
-        let bytes_len = self.bits.len();
-        for i in 0..size {
-            let byte_index = (index + i) / 8;
-            if byte_index >= bytes_len {
-                break;
-            }
-            let bit_index = (index + i) % 8;
-            self.bits[byte_index] = self.toggle_bit_2_0(self.bits[byte_index], bit_index as u8);
+    fn apply(&mut self, record: &Record) {
+        match record.op {
+            OP_ALLOC => self.consumer(record.start as usize, record.size as usize),
+            OP_FREE => self.free(record.start as usize, record.size as usize),
+            OP_TRIM => self.trim(record.start as usize),
+            OP_TRUNCATE => self.truncate(),
+            _ => {},
         }
     }
-    
-    fn free(&mut self, index: usize, size: usize) {
-        self.free_bit(index, size);
-        self.add_idle(index, size);
-    }
 
-    fn consumer(&mut self, index: usize, size: usize) {
-        self.consumer_bit(index, size);
-        self.consumer_idle(index, size);
+    fn truncate(&mut self) {
+        *self = Self::new();
     }
 
-    fn truncate(&mut self) {
-        self.bits = vec![];
-        self.idles = BTreeMap::new();
+    // +---4---+-----8------+---8---+---8---+-------16*n-------+---4---+
+    // | magic | checkpoint | total | count | (start, size) * n | crc32 |
+    // +-------+------------+-------+-------+-------------------+-------+
+    fn encode(&self, checkpoint: u64) -> Vec<u8> {
+        let mut buf = SNAPSHOT_MAGIC.to_vec();
+        buf.extend_from_slice(&checkpoint.to_be_bytes());
+        buf.extend_from_slice(&(self.total as u64).to_be_bytes());
+        buf.extend_from_slice(&(self.idles.len() as u64).to_be_bytes());
+        for (index, size) in self.idles.iter() {
+            buf.extend_from_slice(&(*index as u64).to_be_bytes());
+            buf.extend_from_slice(&(*size as u64).to_be_bytes());
+        }
+        let crc32 = checksum(&buf);
+        buf.extend_from_slice(&crc32.to_be_bytes());
+        buf
     }
 
+    fn decode(buf: &[u8]) -> Result<(Self, u64)> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "invalid extent snapshot");
+        if buf.len() < SNAPSHOT_HEADER_LEN + 4 || &buf[..4] != SNAPSHOT_MAGIC {
+            return Err(invalid());
+        }
+        let data_len = buf.len() - 4;
+        let crc32 = u32::from_be_bytes(buf[data_len..].try_into().unwrap());
+        if crc32 != checksum(&buf[..data_len]) {
+            return Err(invalid());
+        }
+
+        let checkpoint = u64::from_be_bytes(buf[4..12].try_into().unwrap());
+        let total = u64::from_be_bytes(buf[12..20].try_into().unwrap()) as usize;
+        let count = u64::from_be_bytes(buf[20..28].try_into().unwrap()) as usize;
+        if SNAPSHOT_HEADER_LEN + count * 16 != data_len {
+            return Err(invalid());
+        }
+
+        let mut block_meta = Self::new();
+        block_meta.total = total;
+        for i in 0..count {
+            let pos = SNAPSHOT_HEADER_LEN + i * 16;
+            let index = u64::from_be_bytes(buf[pos..pos + 8].try_into().unwrap()) as usize;
+            let size = u64::from_be_bytes(buf[pos + 8..pos + 16].try_into().unwrap()) as usize;
+            block_meta.insert_idle(index, size);
+        }
+
+        Ok((block_meta, checkpoint))
+    }
 }
 
 impl BitMap {
-    // 创建一个新的位图，所有位都初始化为0，0位表示空闲，1位表示占用
-    pub fn new(path: &str, delay: bool) -> Self {
-        let bytemap_path = state::build_path(path, BITMAP_FILE_NAME);
-        let mut stat = Disk::new(&bytemap_path);
-        let sz = stat.meta().unwrap().size;
-        let mut bites = vec![];
-        let mut checkpoint: u64 = 0;
-
-        if sz > 0 {
-            let mut bitmeta = vec![0u8; sz as usize];
-            let _ = stat.get(0, &mut bitmeta);
-            checkpoint = u64::from_be_bytes(bitmeta[..8].try_into().unwrap());
-            bites = bitmeta[8..].to_vec()
+    // 加载空闲区间快照并回放日志，旧版位图文件会被迁移为快照
+    // 快照损坏或文件读写失败时返回错误
+    pub fn new(path: &str, delay: bool) -> Result<Self> {
+        let snapshot_path = state::build_path(path, EXTENT_FILE_NAME);
+        let legacy_path = state::build_path(path, BITMAP_FILE_NAME);
+
+        let (mut meta, mut checkpoint) = if Path::new(&snapshot_path).exists() {
+            let buf = fs::read(&snapshot_path)?;
+            if buf.is_empty() {
+                (BlockMeta::new(), 0)
+            } else {
+                BlockMeta::decode(&buf)?
+            }
+        } else if Path::new(&legacy_path).exists() {
+            let buf = fs::read(&legacy_path)?;
+            if buf.len() >= 8 {
+                let checkpoint = u64::from_be_bytes(buf[..8].try_into().unwrap());
+                (BlockMeta::from_bits(&buf[8..]), checkpoint)
+            } else {
+                (BlockMeta::new(), 0)
+            }
+        } else {
+            (BlockMeta::new(), 0)
+        };
+
+        let journal_path = state::build_path(path, JOURNAL_FILE_NAME);
+        let records = Self::read_journal(&journal_path);
+
+        // 延迟模式只有检查点之前的记录是完整的
+        let valid = if delay {
+            records.iter().rposition(|r| r.op == OP_CHECKPOINT).map_or(0, |pos| pos + 1)
+        } else {
+            records.len()
+        };
+
+        for record in &records[..valid] {
+            if record.op == OP_CHECKPOINT {
+                checkpoint = record.start;
+            } else {
+                meta.apply(record);
+            }
         }
 
-        BitMap {
-            meta: BlockMeta::new(bites),
-            state: stat,
+        let mut bitmap = BitMap {
+            checkpoint,
             delay,
-            version: checkpoint,
-            checkpoint: checkpoint,
+            meta,
+            path: path.to_string(),
+            journal: Disk::open(&journal_path)?,
+            journal_size: records.len() * RECORD_LEN,
+            pending: vec![],
+        };
+
+        // 打开时将日志合并为快照，迁移完成后删除旧版位图
+        if !records.is_empty() || !Path::new(&snapshot_path).exists() {
+            bitmap.write_snapshot()?;
+        }
+        if Path::new(&legacy_path).exists() {
+            let _ = fs::remove_file(&legacy_path);
         }
+
+        Ok(bitmap)
     }
 
-    // 获取位图总位数
+    fn read_journal(path: &str) -> Vec<Record> {
+        let buf = fs::read(path).unwrap_or_default();
+        let mut records = vec![];
+        for chunk in buf.chunks(RECORD_LEN) {
+            match Record::decode(chunk) {
+                Some(record) => records.push(record),
+                // 尾部不完整的记录直接丢弃
+                None => break,
+            }
+        }
+        records
+    }
+
+    // 获取总块数
     pub fn len(&self) -> usize {
-        self.meta.bits.len() * 8
+        self.meta.total
     }
 
-    // 找到n个连续为0的起始索引位置
+    // 找到n个连续空闲块的最小起始索引
     pub fn find_next_n_zeros(&self, n: usize) -> Option<usize> {
-        let mut count = 0;
-        for (byte_index, &byte) in self.meta.bits.iter().enumerate() {
-            for bit_index in 0..8 {
-                if self.meta.get_bit(byte, bit_index as u8) == 0 {
-                    count += 1;
-                    if count == n {
-                        return Some((byte_index * 8 + bit_index + 1) as usize - n);
-                    }
-                } else {
-                    count = 0;
-                }
-            }
+        self.meta.idles.iter()
+            .find(|(_, size)| **size >= n)
+            .map(|(index, _)| *index)
+    }
+
+    // 空闲块数
+    pub fn free_len(&self) -> usize {
+        self.meta.free_cnt
+    }
+
+    // 最大空闲区间的块数
+    pub fn largest_free(&self) -> usize {
+        self.meta.sizes.iter().next_back().map_or(0, |(size, _)| *size)
+    }
+
+    // 在指定位置分配，调用方需确保该区间空闲
+    pub fn malloc_at(&mut self, start_index: usize, n: usize) -> Result<usize> {
+        self.meta.consumer(start_index, n);
+        self.log(Record { op: OP_ALLOC, start: start_index as u64, size: n as u64 })?;
+        Ok(start_index)
+    }
+
+    // 回收尾部空闲区间，返回收缩后的总块数
+    pub fn trim(&mut self) -> Result<usize> {
+        let tail_index = self.meta.tail_index();
+        if tail_index < self.meta.total {
+            self.meta.trim(tail_index);
+            self.log(Record { op: OP_TRIM, start: tail_index as u64, size: 0 })?;
         }
-        None // 没有找到
+        Ok(self.meta.total)
     }
 
     pub fn free(&mut self, start_index: usize, n: usize) ->Result<()> {
         self.meta.free(start_index, n);
-        self.flush(start_index, n)
+        self.log(Record { op: OP_FREE, start: start_index as u64, size: n as u64 })
     }
 
     // 分配空间方法
-    pub fn malloc(&mut self, n: usize) -> usize {
-        if let Some(start_index) = self.meta.find_idle(n) {
-            self.meta.consumer(start_index, n);
-            let _ = self.flush(start_index, n);
-            return start_index;
-        } else {
-            let start_index = self.meta.malloc_bit(n);
-
-            let _ = self.flush(start_index, n);
+    pub fn malloc(&mut self, n: usize) -> Result<usize> {
+        let start_index = match self.meta.find_idle(n) {
+            Some(start_index) => start_index,
+            None => self.meta.tail_index(),
+        };
 
-            return start_index;
-        }
+        self.malloc_at(start_index, n)
     }
 
+    // 延迟模式下将待写入日志与检查点一并落盘
+    // 写入失败时保留待写入日志，下次刷盘时重试
     pub fn flush_all(&mut self, version: u64) -> Result<()> {
+        let mut buf = self.pending.clone();
+        buf.extend(Record { op: OP_CHECKPOINT, start: version, size: 0 }.encode());
+        self.append_journal(&buf)?;
+        self.pending.clear();
         self.checkpoint = version;
-        let mut buf = version.to_be_bytes().to_vec();
-        buf.extend_from_slice(&self.meta.bits[..]);
-        self.state.set(0, &buf)?;
-        Ok(())
+        self.compact_journal()
     }
 
     pub fn checkpoint(&self) -> u64 {
         self.checkpoint
     }
 
-    pub fn flush(&mut self, index: usize, size: usize) -> Result<()> {
-        if !self.delay {
-            let bit_index = index / 8;
-            let byte_len = (size + 7) / 8;
-            self.state.set(bit_index + 8, &self.meta.bits[bit_index..bit_index+byte_len])?;
+    pub fn sync(&self) -> Result<()> {
+        self.journal.sync()
+    }
+
+    fn log(&mut self, record: Record) -> Result<()> {
+        if self.delay {
+            self.pending.extend(record.encode());
+            return Ok(());
         }
+        self.append_journal(&record.encode())?;
+        self.compact_journal()
+    }
+
+    fn append_journal(&mut self, buf: &[u8]) -> Result<()> {
+        if let Err(err) = self.journal.append(buf) {
+            // 截掉写入了一部分的记录，否则回放时会丢弃之后追加的记录
+            let _ = self.journal.set_len(self.journal_size);
+            return Err(err);
+        }
+        self.journal_size += buf.len();
         Ok(())
     }
 
-    pub fn truncate(&mut self) -> Result<()> {
-        self.meta.truncate();
-        if !self.delay {
-            self.state.truncate()?;
+    fn compact_journal(&mut self) -> Result<()> {
+        if self.journal_size > JOURNAL_MAX_SIZE && self.pending.is_empty() {
+            self.write_snapshot()?;
         }
+        Ok(())
+    }
 
+    // 快照先写临时文件再重命名，快照和目录项都落盘后才清空日志
+    fn write_snapshot(&mut self) -> Result<()> {
+        let tmp_path = state::build_path(&self.path, EXTENT_TMP_FILE_NAME);
+        let mut tmp = Disk::open(&tmp_path)?;
+        tmp.truncate()?;
+        tmp.set(0, &self.meta.encode(self.checkpoint))?;
+        tmp.sync_all()?;
+        tmp.rename(&state::build_path(&self.path, EXTENT_FILE_NAME))?;
+        state::sync_dir(&self.path)?;
+
+        self.journal.truncate()?;
+        self.journal_size = 0;
         Ok(())
     }
 
-    pub fn print(&self) {
-        println!("groups: {}, bytes:{}", self.meta.bits.len(), self.len());
-        for (_, &byte) in self.meta.bits.iter().enumerate() {
-            println!("{:08b}", byte);
+    pub fn truncate(&mut self) -> Result<()> {
+        self.meta.truncate();
+        if self.delay {
+            self.pending.extend(Record { op: OP_TRUNCATE, start: 0, size: 0 }.encode());
+            return Ok(());
         }
+        self.pending.clear();
+        self.write_snapshot()
+    }
+
+    #[cfg(test)]
+    pub fn print(&self) {
+        println!("blocks: {}, free: {}", self.len(), self.meta.free_cnt);
         println!("idles: {:?}", self.meta.idles);
     }
 }
 
+fn checksum(buf: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(buf);
+    hasher.finalize()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -329,15 +521,15 @@ mod tests {
 
     #[test]
     fn test_new_bitmap() {
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap1"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap1"), false).unwrap();
         let _ = bitmap.truncate();
-        
+
         assert_eq!(bitmap.len(), 0);
-        assert_eq!(bitmap.meta.bits.len(), 0);
+        assert_eq!(bitmap.meta.idles.len(), 0);
 
-        let index = bitmap.malloc(100);
+        let index = bitmap.malloc(100).unwrap();
         bitmap.print();
-        
+
         assert_eq!(index, 0);
 
         let _ = bitmap.free(3, 10);
@@ -347,52 +539,143 @@ mod tests {
         let _ = bitmap.free(9, 10);
         bitmap.print();
 
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap1"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap1"), false).unwrap();
         bitmap.print();
-        let index = bitmap.malloc(5);
+        let index = bitmap.malloc(5).unwrap();
         bitmap.print();
         assert_eq!(index, 40);
 
-        let index = bitmap.malloc(10);
+        let index = bitmap.malloc(10).unwrap();
         assert_eq!(index, 45);
         assert_eq!(bitmap.len(), 104);
     }
 
     #[test]
     fn test_get_bit() {
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false).unwrap();
         let _ = bitmap.truncate();
         assert_eq!(bitmap.meta.get_bit(0b00000001, 7), 1);
         assert_eq!(bitmap.meta.get_bit(0b00000010, 6), 1);
         assert_eq!(bitmap.meta.get_bit(0b00000100, 5), 1);
         assert_eq!(bitmap.meta.get_bit(0b10000000, 0), 1);
         assert_eq!(bitmap.meta.get_bit(0b10000000, 1), 0);
-        
+
     }
 
     #[test]
     fn test_find_next_n_zeros() {
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap3"), false).unwrap();
         let _ = bitmap.truncate();
-        bitmap.meta.bits.push(0b11110000);   // 前4位被占用
-        assert_eq!(bitmap.find_next_n_zeros(4), Some(4)); // 下一个4个连续的0位从索引4开始
+        bitmap.malloc(4).unwrap();   // 前4块被占用
+        assert_eq!(bitmap.find_next_n_zeros(4), Some(4)); // 下一个4个连续的空闲块从索引4开始
     }
 
     #[test]
     fn test_malloc_and_free() {
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap4"), false).unwrap();
         let _ = bitmap.truncate();
-        let index = bitmap.malloc(4);
+        let index = bitmap.malloc(4).unwrap();
         assert_eq!(index, 0); // 应该从索引0开始分配
         let _ = bitmap.free(index, 4);
-        assert_eq!(bitmap.meta.bits[0], 0); // 分配的4位应该被释放
+        assert_eq!(bitmap.meta.free_cnt, 8); // 分配的4块应该被释放
+        assert_eq!(bitmap.meta.idles.get(&0), Some(&8));
     }
 
     #[test]
     #[should_panic(expected = "bit_index out of range (0-7)")]
     fn test_get_bit_out_of_range() {
-        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false);
+        let mut bitmap = BitMap::new(&tmp_bitmap_path("bitmap2"), false).unwrap();
         let _ = bitmap.truncate();
         bitmap.meta.get_bit(0b00000001, 8); // 应该触发恐慌，因为索引超出范围
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_migrate_legacy_bitmap() {
+        let path = tmp_bitmap_path("bitmap5");
+        let _ = fs::remove_dir_all(&path);
+
+        // 旧版格式: checkpoint(8) + bits
+        let mut buf = 9u64.to_be_bytes().to_vec();
+        buf.extend_from_slice(&[0b11110000, 0b00001111]);
+        fs::write(state::build_path(&path, BITMAP_FILE_NAME), buf).unwrap();
+
+        let bitmap = BitMap::new(&path, true).unwrap();
+        assert_eq!(bitmap.checkpoint(), 9);
+        assert_eq!(bitmap.len(), 16);
+        assert_eq!(bitmap.find_next_n_zeros(8), Some(4));
+        assert!(!Path::new(&state::build_path(&path, BITMAP_FILE_NAME)).exists());
+    }
+
+    #[test]
+    fn test_delay_journal() {
+        let path = tmp_bitmap_path("bitmap6");
+        let _ = fs::remove_dir_all(&path);
+
+        let mut bitmap = BitMap::new(&path, true).unwrap();
+        assert_eq!(bitmap.malloc(10).unwrap(), 0);
+        let _ = bitmap.flush_all(3);
+        // 检查点之后的变更未落盘，重新打开时丢弃
+        assert_eq!(bitmap.malloc(20).unwrap(), 10);
+        let _ = bitmap.free(0, 10);
+
+        let mut bitmap = BitMap::new(&path, true).unwrap();
+        assert_eq!(bitmap.checkpoint(), 3);
+        assert_eq!(bitmap.len(), 16);
+        assert_eq!(bitmap.meta.idles.get(&10), Some(&6));
+        assert_eq!(bitmap.malloc(6).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_trim() {
+        let path = tmp_bitmap_path("bitmap7");
+        let _ = fs::remove_dir_all(&path);
+
+        let mut bitmap = BitMap::new(&path, false).unwrap();
+        bitmap.malloc(10).unwrap();
+        bitmap.malloc(20).unwrap();
+        let _ = bitmap.free(10, 20);
+        assert_eq!(bitmap.trim().unwrap(), 10);
+        assert_eq!(bitmap.free_len(), 0);
+
+        let bitmap = BitMap::new(&path, false).unwrap();
+        assert_eq!(bitmap.len(), 10);
+        assert_eq!(bitmap.free_len(), 0);
+    }
+
+    #[test]
+    fn test_corrupt_snapshot() {
+        let path = tmp_bitmap_path("bitmap8");
+        let _ = fs::remove_dir_all(&path);
+
+        fs::write(state::build_path(&path, EXTENT_FILE_NAME), b"EXT1 broken").unwrap();
+        let err = BitMap::new(&path, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_journal_error() {
+        let path = tmp_bitmap_path("bitmap9");
+        let _ = fs::remove_dir_all(&path);
+
+        // 写入 /dev/full 总是失败，模拟磁盘写满
+        let mut bitmap = BitMap::new(&path, false).unwrap();
+        let journal = std::mem::replace(&mut bitmap.journal, Disk::open("/dev/full").unwrap());
+        assert!(bitmap.malloc(10).is_err());
+        bitmap.journal = journal;
+
+        let mut bitmap = BitMap::new(&path, true).unwrap();
+        assert_eq!(bitmap.malloc(10).unwrap(), 0);
+        let journal = std::mem::replace(&mut bitmap.journal, Disk::open("/dev/full").unwrap());
+        assert!(bitmap.flush_all(3).is_err());
+        assert_eq!(bitmap.checkpoint(), 0);
+
+        // 待写入日志仍保留，恢复后随下一个检查点落盘
+        bitmap.journal = journal;
+        bitmap.flush_all(4).unwrap();
+        let bitmap = BitMap::new(&path, true).unwrap();
+        assert_eq!(bitmap.checkpoint(), 4);
+        assert_eq!(bitmap.len(), 16);
+        assert_eq!(bitmap.free_len(), 6);
+    }
+}
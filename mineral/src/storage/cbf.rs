@@ -1,4 +1,4 @@
-use std::{collections::BTreeMap, time::SystemTime};
+use std::{collections::BTreeMap, time::{Duration, SystemTime}};
 
 use crate::error::Error;
 
@@ -14,6 +14,7 @@ pub struct Page {
     page_no: PageNo,
     pub max_version: usize,
     cap: PageCap,
+    created_at: SystemTime, // 首次写入时间，用于计算刷盘延迟
     pub entrys: BTreeMap<EntryPos, Entry>,
 }
 
@@ -23,16 +24,23 @@ impl Page {
             page_no,
             max_version: page_no,
             cap: 0,
+            created_at: SystemTime::now(),
             entrys: BTreeMap::new(),
         }
     }
 
-    fn insert(&mut self, version: usize, entry_pos: EntryPos, entry: Entry) {
+    // 同一位置的旧条目被替换，返回其长度，cap 只计入当前保留的条目
+    fn insert(&mut self, version: usize, entry_pos: EntryPos, entry: Entry) -> usize {
+        if self.entrys.is_empty() {
+            self.created_at = SystemTime::now();
+        }
         if version > self.max_version {
             self.max_version = version;
         }
         self.cap += entry.len();
-        self.entrys.insert(entry_pos, entry);
+        let replaced = self.entrys.insert(entry_pos, entry).map_or(0, |old| old.len());
+        self.cap -= replaced;
+        replaced
     }
 }
 
@@ -42,6 +50,8 @@ pub struct Cbf {
     pages: BTreeMap<PageNo, Page>,
     page_max_cap: PageCap,
     active_page: Page,
+    // 全部待刷盘页的字节数，含活动页
+    pending_bytes: usize,
 
     rotation_live_time: u64,
     rotation_time: SystemTime,
@@ -55,6 +65,7 @@ impl Cbf {
             pages: BTreeMap::new(),
             page_max_cap: cap,
             active_page: Page::new(0),
+            pending_bytes: 0,
 
             rotation_live_time: 5,   // 5min
             rotation_time: SystemTime::now(),
@@ -66,7 +77,8 @@ impl Cbf {
         self.version = version;
 
         self.rotation_page(buf.len());
-        self.active_page.insert(version, entry_pos, buf);
+        self.pending_bytes += buf.len();
+        self.pending_bytes -= self.active_page.insert(version, entry_pos, buf);
 
         Ok(())
     }
@@ -99,15 +111,63 @@ impl Cbf {
         None
     }
 
+    // 待刷盘的页数，含未轮转的活动页
+    pub fn pending_pages(&self) -> usize {
+        self.pages.len() + if self.active_page.cap > 0 { 1 } else { 0 }
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
+    // 是否有已轮转等待刷盘的页
+    pub fn has_sealed_pages(&self) -> bool {
+        !self.pages.is_empty()
+    }
+
+    // 刷盘延迟，即最早一个待刷盘页已等待的时间
+    pub fn flush_lag(&self) -> Duration {
+        let oldest = match self.pages.first_key_value() {
+            Some((_page_no, page)) => page.created_at,
+            None if self.active_page.cap > 0 => self.active_page.created_at,
+            None => return Duration::ZERO,
+        };
+        SystemTime::now().duration_since(oldest).unwrap_or_default()
+    }
+
+    // 关闭时使用，活动页未满也一并取出
+    pub fn pop_page_force(&mut self) -> Option<(PageNo, Page)> {
+        if self.pages.is_empty() && self.active_page.cap > 0 {
+            self.pages.insert(self.active_page.page_no, self.active_page.clone());
+            self.active_page = Page::new(self.version);
+            self.rotation_time = SystemTime::now();
+        }
+        self.pop_page()
+    }
+
     pub fn pop_first_page(&mut self) -> Option<(PageNo, Page)> {
         if self.pages.len() > 0 {
-            return self.pages.pop_first()
+            return self.pop_page()
         } else if self.active_page.cap > 0 {
             self.rotation_page(0);
         }
         None
     }
 
+    // 出页后写入失败时放回，数据仍可读取
+    pub fn restore_page(&mut self, page_no: PageNo, page: Page) {
+        self.pending_bytes += page.cap;
+        self.pages.insert(page_no, page);
+    }
+
+    fn pop_page(&mut self) -> Option<(PageNo, Page)> {
+        let popped = self.pages.pop_first();
+        if let Some((_page_no, page)) = &popped {
+            self.pending_bytes -= page.cap;
+        }
+        popped
+    }
+
 }
 
 #[cfg(test)]
@@ -137,4 +197,18 @@ mod tests {
         }
         println!("pages: {}", cbf.pages.len());
     }
+
+    #[test]
+    fn rewrite_test() {
+        let mut cbf = Cbf::new(1024 * 1024);
+        for version in 0..100 {
+            cbf.insert(version, 7, vec![version as u8; 100]).unwrap();
+        }
+        cbf.insert(100, 8, vec![1u8; 10]).unwrap();
+
+        // 重复改写同一位置，只计入最后一次写入
+        assert_eq!(cbf.pending_bytes(), 110);
+        assert_eq!(cbf.active_page.cap, 110);
+        assert_eq!(cbf.get(7).unwrap(), vec![99u8; 100]);
+    }
 }
\ No newline at end of file
@@ -20,16 +20,16 @@ pub struct DataBlock {
 }
 
 impl DataBlock {
-    pub fn new(path: &str, block_size: usize, delay: bool) -> Self {
+    pub fn new(path: &str, block_size: usize, delay: bool) -> Result<Self> {
         let datablock_path = state::build_path(path, DATA_BLOCK_FILE_NAME);
 
-        DataBlock {
+        Ok(DataBlock {
             state: Disk::new(datablock_path.as_str()),
-            bitmap: BitMap::new(path, delay),
+            bitmap: BitMap::new(path, delay)?,
             block_size: block_size,
             delay,
             delay_bufs: BTreeMap::new(),
-        }
+        })
     }
 
     pub fn get(&mut self, index: usize, size: usize) -> Result<Vec<u8>> {
@@ -49,7 +49,7 @@ impl DataBlock {
     pub fn set(&mut self, buf: &Vec<u8>) -> Result<usize> {
         let block_nums = (buf.len() + self.block_size - 1) / self.block_size;
         // 先通过位图找到存储位置
-        let index = self.bitmap.malloc(block_nums);
+        let index = self.bitmap.malloc(block_nums)?;
 
         let pos = index * self.block_size;
         if self.delay {
@@ -64,9 +64,51 @@ impl DataBlock {
     pub fn free(&mut self, index: usize, size: usize) -> Result<()> {
         let block_nums = (size + self.block_size - 1) / self.block_size;
         self.bitmap.free(index, block_nums)?;
+        self.delay_bufs.remove(&(index * self.block_size));
         Ok(())
     }
 
+    // 查找比当前位置更靠前、可容纳该数据的空闲区间
+    pub fn relocation_target(&self, index: usize, size: usize) -> Option<usize> {
+        let block_nums = size.div_ceil(self.block_size);
+        self.bitmap.find_next_n_zeros(block_nums)
+            .filter(|target| *target < index)
+    }
+
+    // 将数据搬迁到 target，返回占用块数
+    pub fn relocate(&mut self, index: usize, size: usize, target: usize) -> Result<usize> {
+        let block_nums = size.div_ceil(self.block_size);
+        let buf = self.get(index, size)?;
+
+        self.bitmap.malloc_at(target, block_nums)?;
+        let pos = target * self.block_size;
+        if self.delay {
+            self.delay_bufs.insert(pos, buf);
+        } else {
+            self.state.set(pos, &buf)?;
+        }
+
+        self.free(index, size)?;
+        Ok(block_nums)
+    }
+
+    // 回收尾部空闲块并截断文件，延迟模式下先落盘位图再截断
+    pub fn trim(&mut self, version: usize) -> Result<usize> {
+        let blocks = self.bitmap.trim()?;
+        self.flush(version)?;
+        self.state.set_len(blocks * self.block_size)?;
+        Ok(blocks)
+    }
+
+    // (总块数, 空闲块数)
+    pub fn usage(&self) -> (usize, usize) {
+        (self.bitmap.len(), self.bitmap.free_len())
+    }
+
+    pub fn largest_free(&self) -> usize {
+        self.bitmap.largest_free()
+    }
+
     pub fn truncate(&mut self) ->Result<()> {
         self.state.truncate()?;
         self.bitmap.truncate()?;
@@ -82,7 +124,7 @@ impl DataBlock {
         let mut index = index;
         if block_nums != old_block_nums {
             self.free(index, old_size)?;
-            index = self.bitmap.malloc(block_nums);
+            index = self.bitmap.malloc(block_nums)?;
         }
 
         let pos = index * self.block_size;
@@ -101,10 +143,11 @@ impl DataBlock {
             return Ok(());
         }
 
-        for (pos, buf) in self.delay_bufs.clone() {
-            self.state.set(pos, &buf)?;
+        for (pos, buf) in self.delay_bufs.iter() {
+            self.state.set(*pos, buf)?;
         }
-        
+        self.delay_bufs.clear();
+
         // flush 
         self.bitmap.flush_all(version as u64)
     }
@@ -113,6 +156,11 @@ impl DataBlock {
         self.bitmap.checkpoint()
     }
 
+    pub fn sync(&self) -> Result<()> {
+        self.state.sync()?;
+        self.bitmap.sync()
+    }
+
 }
 
 #[cfg(test)]
@@ -125,7 +173,7 @@ mod tests {
 
     #[test]
     fn test_get() {
-        let mut db = DataBlock::new(&tmp_path("datablock1"), 1024, false);
+        let mut db = DataBlock::new(&tmp_path("datablock1"), 1024, false).unwrap();
         let _ = db.truncate();
 
         let list: Vec<(u8, usize, usize)> = vec![
@@ -155,7 +203,7 @@ mod tests {
 
     #[test]
     fn test_free() {
-        let mut db = DataBlock::new(&tmp_path("datablock2"), 1024, false);
+        let mut db = DataBlock::new(&tmp_path("datablock2"), 1024, false).unwrap();
         let _ = db.truncate();
 
         let list: Vec<(bool, usize, usize)> = vec![
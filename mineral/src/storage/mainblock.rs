@@ -5,7 +5,7 @@ use std::{cmp, io::Result};
 use byteorder::{BigEndian, ByteOrder};
 
 const MAIN_BLOCK_FILE_NAME: &str = "@mainblock";
-const HEADER_SIZE: usize = 17;
+pub(crate) const HEADER_SIZE: usize = 17;
 
 #[derive(Debug)]
 pub struct MainBlock {
@@ -23,6 +23,15 @@ const FLAG_DEL: u8 = 0; // 删除
 const FLAG_NORMAL: u8 = 1;  // 未溢出
 const FLAG_OVERFLOW: u8 = 2; // 溢出
 
+// 槽位使用情况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SlotUsage {
+    pub used: usize,      // 有数据的槽位数
+    pub total_size: u64,  // 槽位数据总字节数
+    pub max_size: u64,    // 最大槽位字节数
+    pub overflows: usize, // 溢出到数据块的槽位数
+}
+
 pub struct Header {
     flag: u8,   // 标识位
     size: u64,  // 最大支持到2^32 - 1
@@ -31,14 +40,14 @@ pub struct Header {
 
 impl MainBlock {
 
-    pub fn new(path: &str, fetch_size: usize, delay: bool) -> Self {
+    pub fn new(path: &str, fetch_size: usize, delay: bool) -> Result<Self> {
         let main_block_file = state::build_path(path, MAIN_BLOCK_FILE_NAME);
-        MainBlock {
+        Ok(MainBlock {
             path: path.to_string(),
             state: Disk::new(&main_block_file),
             fetch_size: fetch_size,
-            datablock: DataBlock::new(path, 1024, delay),
-        }
+            datablock: DataBlock::new(path, 1024, delay)?,
+        })
     }
 
     fn get_header(&mut self, index: usize) -> Result<Header> {
@@ -157,6 +166,86 @@ impl MainBlock {
         self.datablock.flush(version)
     }
 
+    // 将已写入的槽位、数据块和位图日志落盘
+    pub fn sync(&self) -> Result<()> {
+        self.state.sync()?;
+        self.datablock.sync()
+    }
+
+    // 扫描所有槽位 header 统计使用情况
+    pub fn slot_usage(&mut self) -> Result<SlotUsage> {
+        let slots = self.state.meta()?.size / self.fetch_size;
+        let mut usage = SlotUsage::default();
+        let mut buf = vec![0u8; HEADER_SIZE];
+        for index in 0..slots {
+            self.state.get(self.get_real_pos(index), &mut buf)?;
+            let header = self.cast_to_header(&buf);
+            if header.flag == FLAG_DEL || header.size == 0 {
+                continue;
+            }
+            usage.used += 1;
+            usage.total_size += header.size;
+            usage.max_size = usage.max_size.max(header.size);
+            if header.flag == FLAG_OVERFLOW {
+                usage.overflows += 1;
+            }
+        }
+        Ok(usage)
+    }
+
+    // 所有溢出数据链 (index, 数据块位置)
+    pub fn overflows(&mut self) -> Result<Vec<(usize, usize)>> {
+        let slots = self.state.meta()?.size / self.fetch_size;
+        let mut list = vec![];
+        let mut buf = vec![0u8; HEADER_SIZE];
+        for index in 0..slots {
+            self.state.get(self.get_real_pos(index), &mut buf)?;
+            let header = self.cast_to_header(&buf);
+            if header.flag == FLAG_OVERFLOW {
+                list.push((index, header.pos as usize));
+            }
+        }
+        Ok(list)
+    }
+
+    // 溢出数据可搬迁到的更靠前的位置
+    pub fn relocation_target(&mut self, index: usize) -> Result<Option<usize>> {
+        let header = self.get_header(index)?;
+        if header.flag != FLAG_OVERFLOW {
+            return Ok(None);
+        }
+        let data_buf_size = header.size as usize + HEADER_SIZE - self.fetch_size;
+        Ok(self.datablock.relocation_target(header.pos as usize, data_buf_size))
+    }
+
+    // 搬迁溢出数据并更新 header，返回搬迁的块数
+    pub fn relocate(&mut self, index: usize, target: usize) -> Result<usize> {
+        let mut header = self.get_header(index)?;
+        if header.flag != FLAG_OVERFLOW {
+            return Ok(0);
+        }
+        let data_buf_size = header.size as usize + HEADER_SIZE - self.fetch_size;
+        let blocks = self.datablock.relocate(header.pos as usize, data_buf_size, target)?;
+
+        header.pos = target as u64;
+        self.state.set(self.get_real_pos(index), &self.cast_header_to_buf(&header))?;
+        Ok(blocks)
+    }
+
+    // 回收数据块尾部空闲空间，返回剩余总块数
+    pub fn trim_datablock(&mut self, version: usize) -> Result<usize> {
+        self.datablock.trim(version)
+    }
+
+    // 数据块 (总块数, 空闲块数)
+    pub fn datablock_usage(&self) -> (usize, usize) {
+        self.datablock.usage()
+    }
+
+    pub fn datablock_largest_free(&self) -> usize {
+        self.datablock.largest_free()
+    }
+
     fn cast_to_header(&self, buf: &Vec<u8>) -> Header {
         Header {
             flag: buf[0],
@@ -191,7 +280,7 @@ mod tests {
 
     #[test]
     fn test_get() {
-        let mut mb = MainBlock::new(&tmp_path("mainblock1"), 1024, false);
+        let mut mb = MainBlock::new(&tmp_path("mainblock1"), 1024, false).unwrap();
         let _ = mb.truncate();
         let get_buf = mb.get(100);
         assert!(get_buf.is_ok());
@@ -200,7 +289,7 @@ mod tests {
 
     #[test]
     fn test_get_and_set() {
-        let mut mb = MainBlock::new(&tmp_path("mainblock2"), 1024, false);
+        let mut mb = MainBlock::new(&tmp_path("mainblock2"), 1024, false).unwrap();
         let _ = mb.truncate();
 
         let list: Vec<(u8, usize, usize)> = vec![
@@ -228,4 +317,32 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_relocate() {
+        let mut mb = MainBlock::new(&tmp_path("mainblock3"), 1024, false).unwrap();
+        let _ = mb.truncate();
+
+        mb.set(0, &vec![1u8; 1024 * 4]).unwrap();
+        mb.set(1, &vec![2u8; 1024 * 2]).unwrap();
+        mb.set(2, &vec![3u8; 1024 * 3]).unwrap();
+        // 释放最前面的溢出链，形成空洞
+        mb.set(0, &vec![1u8; 100]).unwrap();
+
+        let overflows = mb.overflows().unwrap();
+        assert_eq!(overflows.len(), 2);
+
+        for (index, _) in overflows.into_iter().rev() {
+            if let Some(target) = mb.relocation_target(index).unwrap() {
+                mb.relocate(index, target).unwrap();
+            }
+        }
+
+        // 索引 2 搬迁到 [0, 3)，索引 1 仍在 [4, 6)
+        assert_eq!(mb.datablock_usage(), (16, 11));
+        assert_eq!(mb.trim_datablock(0).unwrap(), 6);
+        assert_eq!(mb.datablock_usage(), (6, 1));
+        assert_eq!(mb.get(1).unwrap(), vec![2u8; 1024 * 2]);
+        assert_eq!(mb.get(2).unwrap(), vec![3u8; 1024 * 3]);
+    }
+
 }
\ No newline at end of file
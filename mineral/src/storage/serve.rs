@@ -1,19 +1,26 @@
-use core::panic;
-use std::{sync::{Arc, Mutex}, thread, time::Duration};
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, Instant}};
 
-use crate::{config::StorageConfig, error::Error};
+use tracing::{error, info};
 
-use super::{cbf::Cbf, mainblock::MainBlock, wal::Wal};
+use crate::{config::StorageConfig, error::Error, metrics::Metrics, worker::{Throttle, Worker}};
+
+use super::{cbf::{Cbf, Page}, mainblock::MainBlock, wal::Wal};
 
 
 const BLOCK_OP_SET: u8 = 1;
+const BLOCK_OP_MERGE: u8 = 2;
 const BLOCK_OP_DEL: u8 = 3;
 // BlockOp block operate
+// Merge 只出现在预写日志中，进入缓冲前已与槽位数据合并为 Set
 pub enum BlockOp {
     Set(u64, Vec<u8>),
+    Merge(u64, Vec<u8>),
     Del(u64),
 }
 
+// 将预写日志中的变更合并到槽位数据，参数为 (槽位数据, 变更)
+pub type Merger = fn(&[u8], &[u8]) -> Result<Vec<u8>, Error>;
+
 impl BlockOp {
     pub fn encode_from(op: u8, pos: u64, data: Vec<u8>) -> Vec<u8> {
         let mut block_op_buf = vec![op];
@@ -29,6 +36,8 @@ impl BlockOp {
         if op == BLOCK_OP_SET {
             
             BlockOp::Set(u64::from_be_bytes(buf[1..9].try_into().unwrap()), buf[9..].to_vec())
+        } else if op == BLOCK_OP_MERGE {
+            BlockOp::Merge(u64::from_be_bytes(buf[1..9].try_into().unwrap()), buf[9..].to_vec())
         } else {
             BlockOp::Del(u64::from_be_bytes(buf[1..9].try_into().unwrap()))
         }
@@ -37,29 +46,116 @@ impl BlockOp {
     pub fn get_pos(block_op: BlockOp) -> u64 {
         match block_op {
             BlockOp::Set(pos, _) => pos,
+            BlockOp::Merge(pos, _) => pos,
             BlockOp::Del(pos) => pos
         }
     }
 }
 
+// 数据块整理结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompactReport {
+    pub scanned: usize,       // 检查的溢出链数
+    pub moved: usize,         // 搬迁的溢出链数
+    pub moved_blocks: usize,  // 搬迁的块数
+    pub skipped: usize,       // 有待刷入变更而跳过的溢出链数
+    pub blocks_before: usize, // 整理前总块数
+    pub blocks_after: usize,  // 整理后总块数
+    pub free_before: usize,   // 整理前空闲块数
+    pub free_after: usize,    // 整理后空闲块数
+}
+
+// 存储层空间使用统计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StorageStats {
+    pub slots_used: usize,          // 有数据的槽位数
+    pub slot_avg_size: u64,         // 平均槽位字节数
+    pub slot_max_size: u64,         // 最大槽位字节数
+    pub overflows: usize,           // 溢出到数据块的槽位数
+    pub blocks_allocated: usize,    // 已分配的数据块数
+    pub blocks_free: usize,         // 空闲数据块数
+    pub largest_free_extent: usize, // 最大连续空闲块数
+    pub wal_bytes: u64,             // 预写日志字节数
+    pub cbf_pages: usize,           // 待刷盘的缓冲页数
+    pub cbf_bytes: usize,           // 待刷盘的缓冲字节数
+    pub flush_lag_ms: u64,          // 最早的待刷盘页已等待的毫秒数
+    pub write_stalls: u64,          // 等待过刷盘的写入次数
+    pub write_rejects: u64,         // 缓冲已满被拒绝的写入次数
+}
+
 #[derive(Debug)]
 pub struct Serve {
     mainblock: Arc<Mutex<MainBlock>>,
     wal: Arc<Mutex<Wal>>,
     cbf: Arc<Mutex<Cbf>>,
+    compact_threshold: f64,
+    cbf_high_water: usize,
+    throttle: Throttle,
+    merger: Option<Merger>,
+    // 写入失败后置为只读，拒绝新的写入，与后台刷盘线程共享
+    read_only: Arc<AtomicBool>,
+    // 运行指标，与后台刷盘线程及 kv 层共享
+    metrics: Arc<Metrics>,
+    // 后台刷盘线程，close 后为 None
+    worker: Option<Worker>,
 }
 
 impl Serve {
+    // 打开失败时 panic
     pub fn new(conf: StorageConfig) -> Self {
-        let serve = Serve {
+        Self::open(conf, None).unwrap()
+    }
+
+    // 使用 merge 写入时需要提供 merger，打开时用于重放预写日志中的变更
+    pub fn with_merger(conf: StorageConfig, merger: Merger) -> Result<Self, Error> {
+        Self::open(conf, Some(merger))
+    }
+
+    fn open(conf: StorageConfig, merger: Option<Merger>) -> Result<Self, Error> {
+        let mainblock = MainBlock::new(&conf.path, conf.block_size, true).map_err(Error::StorageOpenFailed)?;
+        let mut serve = Serve {
             wal: Arc::new(Mutex::new(Wal::new(&conf.path))),
-            mainblock: Arc::new(Mutex::new(MainBlock::new(&conf.path, conf.block_size, true))),
+            mainblock: Arc::new(Mutex::new(mainblock)),
             cbf: Arc::new(Mutex::new(Cbf::new(conf.page_max_cap))),
+            compact_threshold: conf.compact_threshold,
+            cbf_high_water: conf.cbf_high_water,
+            throttle: Throttle::new(conf.cbf_high_water, conf.cbf_stall_ms),
+            merger,
+            read_only: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Metrics::default()),
+            worker: None,
         };
         serve.init_wait_block();
 
-        serve.run();
-        serve
+        serve.worker = Some(serve.run());
+        Ok(serve)
+    }
+
+    // 停止后台线程，将缓冲中的变更全部写入 mainblock 并落盘，随后检查预写日志
+    // 重复调用无副作用
+    pub fn close(&mut self) -> Result<(), Error> {
+        match self.worker.take() {
+            Some(mut worker) => worker.stop(),
+            None => return Ok(()),
+        }
+
+        let mut mainblock = self.mainblock.lock().unwrap();
+        loop {
+            let popped = self.cbf.lock().unwrap().pop_page_force();
+            match popped {
+                Some((_page_no, page)) => Self::apply_page(&mut mainblock, &page).map_err(Error::BlockDataGetFailed)?,
+                None => break,
+            }
+        }
+
+        // 所有日志均已写入，检查点推进到最新版本
+        let mut wal = self.wal.lock().unwrap();
+        let version = wal.version();
+        mainblock.flush_datablock(version as usize).map_err(Error::BlockDataGetFailed)?;
+        mainblock.sync().map_err(Error::BlockDataGetFailed)?;
+        wal.checked_version(version + 1);
+
+        Ok(())
     }
 
     pub fn get(&mut self, pos: usize) -> Result<Vec<u8>, Error> {
@@ -68,6 +164,7 @@ impl Serve {
             return match block_op {
                 BlockOp::Del(_) => Ok(vec![]),
                 BlockOp::Set(_, data) => Ok(data),
+                BlockOp::Merge(..) => unreachable!("缓冲中不存在未合并的变更"),
             }
         }
 
@@ -79,26 +176,173 @@ impl Serve {
 
     pub fn set(&mut self, pos: usize, buf: Vec<u8>) -> Result<(), Error> {
         let buf = BlockOp::encode_from(BLOCK_OP_SET, pos as u64, buf);
-        match self.wal.lock().unwrap().append(&buf) {
-            Ok(version) => {
-                self.cbf.lock().unwrap().insert(version as usize, pos, buf)
-            },
-            Err(err) => Err(err)
+        self.append(&buf, buf.clone(), pos)
+    }
+
+    // 预写日志只记录变更 delta，缓冲中保存合并后的完整数据 merged
+    // 重放时由 merger 将 delta 合并到当时的数据上，合并需可重复执行
+    pub fn merge(&mut self, pos: usize, delta: Vec<u8>, merged: Vec<u8>) -> Result<(), Error> {
+        if self.merger.is_none() {
+            return Err(Error::MergerMissing);
         }
+        let log = BlockOp::encode_from(BLOCK_OP_MERGE, pos as u64, delta);
+        self.append(&log, BlockOp::encode_from(BLOCK_OP_SET, pos as u64, merged), pos)
     }
 
+    // 删除与写入一样先写日志再进入缓冲，避免缓冲中未刷盘的旧值覆盖删除
     pub fn del(&mut self, pos: usize) -> Result<(), Error> {
-        if let Err(err) = self.mainblock.lock().unwrap().del(pos) {
-            return Err(Error::MainDataDelFailed(err));
+        let buf = BlockOp::encode_from(BLOCK_OP_DEL, pos as u64, vec![]);
+        self.append(&buf, buf.clone(), pos)
+    }
+
+    fn append(&mut self, log: &Vec<u8>, buf: Vec<u8>, pos: usize) -> Result<(), Error> {
+        self.check_writable()?;
+        self.throttle.wait(self.worker.as_ref(), || self.cbf.lock().unwrap().pending_bytes())?;
+
+        let start = Instant::now();
+        let version = self.wal.lock().unwrap().append(log)
+            .map_err(|err| Self::degrade(&self.read_only, err))?;
+        self.metrics.wal_append.observe(start.elapsed());
+        {
+            let mut cbf = self.cbf.lock().unwrap();
+            cbf.insert(version as usize, pos, buf)?;
+            self.metrics.cbf_bytes.store(cbf.pending_bytes() as u64, Ordering::Relaxed);
+        }
+
+        // 有已轮转的页时立即唤醒刷盘线程
+        if self.cbf.lock().unwrap().has_sealed_pages() {
+            if let Some(worker) = &self.worker {
+                worker.wake();
+            }
         }
         Ok(())
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    // 上层写入值日志等失败时同样切换为只读
+    pub(crate) fn degrade_with(&self, err: Error) -> Error {
+        Self::degrade(&self.read_only, err)
+    }
+
+    // 写入失败后切换为只读，已写入预写日志的数据重启后恢复
+    fn degrade(read_only: &AtomicBool, err: Error) -> Error {
+        if !read_only.swap(true, Ordering::SeqCst) {
+            error!(cause = %err, "storage switched to read-only mode");
+        }
+        err
+    }
+
+    pub fn stats(&self) -> Result<StorageStats, Error> {
+        let mut mainblock = self.mainblock.lock().unwrap();
+        let usage = mainblock.slot_usage().map_err(Error::BlockDataGetFailed)?;
+        let (blocks, free) = mainblock.datablock_usage();
+        let cbf = self.cbf.lock().unwrap();
+
+        Ok(StorageStats {
+            slots_used: usage.used,
+            slot_avg_size: if usage.used > 0 { usage.total_size / usage.used as u64 } else { 0 },
+            slot_max_size: usage.max_size,
+            overflows: usage.overflows,
+            blocks_allocated: blocks - free,
+            blocks_free: free,
+            largest_free_extent: mainblock.datablock_largest_free(),
+            wal_bytes: self.wal.lock().unwrap().size(),
+            cbf_pages: cbf.pending_pages(),
+            cbf_bytes: cbf.pending_bytes(),
+            flush_lag_ms: cbf.flush_lag().as_millis() as u64,
+            write_stalls: self.throttle.stalls,
+            write_rejects: self.throttle.rejects,
+        })
+    }
+
+    // 数据块空闲占比
+    pub fn fragmentation(&self) -> f64 {
+        Self::fragmentation_of(&self.mainblock.lock().unwrap())
+    }
+
+    fn fragmentation_of(mainblock: &MainBlock) -> f64 {
+        let (blocks, free) = mainblock.datablock_usage();
+        if blocks == 0 {
+            return 0.0;
+        }
+        free as f64 / blocks as f64
+    }
+
+    // 将溢出链从文件尾部搬迁到前部的空闲区间，随后截断数据块文件
+    pub fn compact(&self) -> Result<CompactReport, Error> {
+        self.check_writable()?;
+        Self::compact_blocks(&self.mainblock, &self.cbf, &self.wal)
+    }
+
+    fn compact_blocks(mainblock: &Arc<Mutex<MainBlock>>, cbf: &Arc<Mutex<Cbf>>, wal: &Arc<Mutex<Wal>>) -> Result<CompactReport, Error> {
+        // 整理期间持有 mainblock 锁，后台刷盘线程无法同时写入
+        let mut mainblock = mainblock.lock().unwrap();
+
+        let (blocks_before, free_before) = mainblock.datablock_usage();
+        let mut report = CompactReport {
+            blocks_before,
+            free_before,
+            ..Default::default()
+        };
+
+        // 检查点不前移，崩溃后从原检查点重放，包括下面写入的搬迁记录
+        let checkpoint = mainblock.checkpoint() as usize;
+
+        let mut chains = mainblock.overflows().map_err(Error::CompactFailed)?;
+        chains.sort_by(|a, b| b.1.cmp(&a.1));
+
+        for (index, _) in chains {
+            report.scanned += 1;
+
+            // 有待刷入的变更，交由后续写入重新分配
+            if cbf.lock().unwrap().get(index).is_some() {
+                report.skipped += 1;
+                continue;
+            }
+
+            let target = match mainblock.relocation_target(index).map_err(Error::CompactFailed)? {
+                Some(target) => target,
+                None => continue,
+            };
+
+            // 先写预写日志，重放时按完整数据重新写入该槽位
+            let data = mainblock.get(index).map_err(Error::CompactFailed)?;
+            let buf = BlockOp::encode_from(BLOCK_OP_SET, index as u64, data);
+            wal.lock().unwrap().append(&buf)?;
+
+            report.moved_blocks += mainblock.relocate(index, target).map_err(Error::CompactFailed)?;
+            report.moved += 1;
+        }
+
+        mainblock.flush_datablock(checkpoint).map_err(Error::CompactFailed)?;
+        mainblock.trim_datablock(checkpoint).map_err(Error::CompactFailed)?;
+
+        let (blocks_after, free_after) = mainblock.datablock_usage();
+        report.blocks_after = blocks_after;
+        report.free_after = free_after;
+
+        Ok(report)
+    }
+
     fn init_wait_block(&self) {
         // 初始化检查点后的数据，全部写入缓冲
         let checkpoint = self.mainblock.lock().unwrap().checkpoint();
         
-        let wal_reader = self.wal.lock().unwrap().reader(checkpoint, 0);
+        let wal_reader = self.wal.lock().unwrap().reader(checkpoint + 1, 0);
         if wal_reader.is_none() {
             return;
         }
@@ -106,45 +350,132 @@ impl Serve {
         for s in wal_reader.unwrap() {
             let payload = s.unwrap();
             let block_op = BlockOp::decode(&payload.data);
-            self.cbf.lock().unwrap().insert(payload.version as usize, 
-                BlockOp::get_pos(block_op) as usize, payload.data).unwrap();
+            let (pos, buf) = match block_op {
+                BlockOp::Merge(pos, delta) => match self.replay_merge(pos as usize, &delta) {
+                    Ok(merged) => (pos, BlockOp::encode_from(BLOCK_OP_SET, pos, merged)),
+                    Err(err) => {
+                        error!(cause = %err, version = payload.version, pos, "skip invalid merge op");
+                        continue;
+                    },
+                },
+                block_op => (BlockOp::get_pos(block_op), payload.data),
+            };
+            self.cbf.lock().unwrap().insert(payload.version as usize, pos as usize, buf).unwrap();
         }
     }
 
-    fn run(&self) {
+    // 以缓冲中或 mainblock 中的数据为基准合并变更
+    fn replay_merge(&self, pos: usize, delta: &[u8]) -> Result<Vec<u8>, Error> {
+        let merger = self.merger.ok_or(Error::MergerMissing)?;
+        let cached = self.cbf.lock().unwrap().get(pos);
+        let base = match cached.map(|buf| BlockOp::decode(&buf)) {
+            Some(BlockOp::Set(_, data)) => data,
+            Some(_) => vec![],
+            None => self.mainblock.lock().unwrap().get(pos).map_err(Error::BlockDataGetFailed)?,
+        };
+        merger(&base, delta)
+    }
+
+    // 将一页变更写入 mainblock 并落盘数据块
+    fn apply_page(mainblock: &mut MainBlock, page: &Page) -> std::io::Result<()> {
+        for buf in page.entrys.values() {
+            let block_op = BlockOp::decode(buf);
+            match block_op {
+                BlockOp::Set(p, data) => mainblock.set(p as usize, &data)?,
+                BlockOp::Del(p) => mainblock.del(p as usize)?,
+                BlockOp::Merge(..) => unreachable!("缓冲中不存在未合并的变更"),
+            }
+        }
+
+        mainblock.flush_datablock(page.max_version)
+    }
+
+    fn run(&self) -> Worker {
         
         let cbf = self.cbf.clone();
         let mainblock = self.mainblock.clone();
-        thread::spawn(move || {
+        let wal = self.wal.clone();
+        let compact_threshold = self.compact_threshold;
+        let read_only = self.read_only.clone();
+        let high_water = self.cbf_high_water;
+        let metrics = self.metrics.clone();
+        Worker::spawn(Duration::from_millis(100), move || {
+            // 只读后不再刷盘，缓冲中的数据仍可读取
+            if read_only.load(Ordering::SeqCst) {
+                return;
+            }
+
+            // 先取 mainblock 锁再出页，保证整理时不存在已出页未写入的变更
+            let mut mb = mainblock.lock().unwrap();
+            let mut flushed = None;
+            let start = Instant::now();
             loop {
-                if let Some((_page_no, page)) = cbf.lock().unwrap().pop_first_page() {
-                    // println!("page no: {}, entrys: {:?}", page_no, page.entrys);
-                    for (_pos, buf) in page.entrys {
-                        // println!("pos: {}", pos);
-                        let block_op = BlockOp::decode(&buf);
-                        match block_op {
-                            BlockOp::Set(p, data) => {
-                                mainblock.lock().unwrap().set(p as usize, &data).unwrap()
-                            },
-                            BlockOp::Del(p) => {
-                                mainblock.lock().unwrap().del(p as usize).unwrap()
-                            }
-                        }
+                // 超过高水位时活动页也一并刷盘
+                let popped = {
+                    let mut cbf = cbf.lock().unwrap();
+                    if high_water > 0 && cbf.pending_bytes() > high_water {
+                        cbf.pop_page_force()
+                    } else {
+                        cbf.pop_first_page()
                     }
+                };
+                let (page_no, page) = match popped {
+                    Some(popped) => popped,
+                    None => break,
+                };
+                if let Err(err) = Self::apply_page(&mut mb, &page) {
+                    cbf.lock().unwrap().restore_page(page_no, page);
+                    Self::degrade(&read_only, Error::BlockDataGetFailed(err));
+                    return;
+                }
+                flushed = Some(page.max_version as u64);
+            }
 
-                    if let Err(_err) = mainblock.lock().unwrap().flush_datablock(page.max_version) {
-                        panic!("flush page error");
-                    }
+            // 按版本顺序出页，已刷入页的最大版本之前的变更均已写入，落盘后检查之前的预写日志
+            if let Some(version) = flushed {
+                if let Err(err) = mb.sync() {
+                    Self::degrade(&read_only, Error::BlockDataGetFailed(err));
+                    return;
                 }
+                wal.lock().unwrap().checked_rotated(version + 1);
+            }
 
-                thread::sleep(Duration::from_millis(100));
+            // 只统计实际刷入数据的轮次
+            if flushed.is_some() {
+                metrics.flush.observe(start.elapsed());
+                metrics.cbf_bytes.store(cbf.lock().unwrap().pending_bytes() as u64, Ordering::Relaxed);
             }
-        });
-        
+
+            // 有新数据刷入且碎片率超过阈值时整理
+            let need_compact = flushed.is_some() && compact_threshold > 0.0
+                && Self::fragmentation_of(&mb) >= compact_threshold;
+            drop(mb);
+
+            if need_compact {
+                match Self::compact_blocks(&mainblock, &cbf, &wal) {
+                    Ok(report) => info!(
+                        moved = report.moved,
+                        moved_blocks = report.moved_blocks,
+                        skipped = report.skipped,
+                        blocks_before = report.blocks_before,
+                        blocks_after = report.blocks_after,
+                        "compact datablock"),
+                    Err(err) => error!(cause = %err, "failed to compact datablock"),
+                }
+            }
+        })
     }
 
 }
 
+impl Drop for Serve {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            error!(cause = %err, "failed to close storage");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{time::Instant, vec};
@@ -158,6 +489,9 @@ mod tests {
             path: "/tmp/terra/tests/serve1".to_string(),
             block_size: 1024,
             page_max_cap: 1024 * 1024 * 50,
+            compact_threshold: 0.0,
+            cbf_high_water: 0,
+            cbf_stall_ms: 0,
         }
     }
 
@@ -204,6 +538,160 @@ mod tests {
         assert_eq!(del.unwrap(), vec![]);
     }
 
+    #[test]
+    fn test_compact() {
+        let path = "/tmp/terra/tests/serve2";
+        let _ = std::fs::remove_dir_all(path);
+        let mut conf = get_conf();
+        conf.path = path.to_string();
+        let serve = Serve::new(conf);
+
+        {
+            let mut mb = serve.mainblock.lock().unwrap();
+            mb.set(0, &vec![1u8; 1024 * 4]).unwrap();
+            mb.set(1, &vec![2u8; 1024 * 3]).unwrap();
+            mb.set(2, &vec![3u8; 1024 * 3]).unwrap();
+            mb.set(0, &vec![1u8; 100]).unwrap();
+        }
+        assert!(serve.fragmentation() > 0.5);
+
+        let report = serve.compact().unwrap();
+        assert_eq!(report.scanned, 2);
+        assert_eq!(report.moved, 1);
+        assert_eq!(report.moved_blocks, 3);
+        assert_eq!(report.blocks_before, 16);
+        assert_eq!(report.blocks_after, 7);
+
+        let stats = serve.stats().unwrap();
+        assert_eq!(stats.slots_used, 3);
+        assert_eq!(stats.overflows, 2);
+        assert_eq!(stats.slot_max_size, 1024 * 3);
+        assert_eq!(stats.blocks_allocated, 6);
+        assert_eq!(stats.blocks_free, 1);
+        assert_eq!(stats.largest_free_extent, 1);
+
+        let mut serve = serve;
+        assert_eq!(serve.get(1).unwrap(), vec![2u8; 1024 * 3]);
+        assert_eq!(serve.get(2).unwrap(), vec![3u8; 1024 * 3]);
+    }
+
+    #[test]
+    fn test_close() {
+        let path = "/tmp/terra/tests/serve3";
+        let _ = std::fs::remove_dir_all(path);
+        let mut conf = get_conf();
+        conf.path = path.to_string();
+
+        let mut serve = Serve::new(conf.clone());
+        serve.set(1, vec![1u8; 3000]).unwrap();
+        serve.set(2, vec![2u8; 10]).unwrap();
+        serve.close().unwrap();
+        assert_eq!(serve.cbf.lock().unwrap().pending_pages(), 0);
+        assert_eq!(serve.mainblock.lock().unwrap().get(1).unwrap(), vec![1u8; 3000]);
+        // 再次关闭无副作用
+        serve.close().unwrap();
+        drop(serve);
+
+        // 日志已检查，重新打开时无需重放
+        let mut serve = Serve::new(conf);
+        assert_eq!(serve.cbf.lock().unwrap().pending_pages(), 0);
+        assert_eq!(serve.get(1).unwrap(), vec![1u8; 3000]);
+        assert_eq!(serve.get(2).unwrap(), vec![2u8; 10]);
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let path = "/tmp/terra/tests/serve4";
+        let _ = std::fs::remove_dir_all(path);
+        let mut conf = get_conf();
+        conf.path = path.to_string();
+        conf.cbf_high_water = 2048;
+        conf.cbf_stall_ms = 5000;
+
+        let mut serve = Serve::new(conf);
+        for i in 0..20 {
+            serve.set(i, vec![i as u8; 512]).unwrap();
+        }
+
+        // 超过高水位的写入等待刷盘后才继续，缓冲始终有界
+        let stats = serve.stats().unwrap();
+        assert!(stats.write_stalls > 0);
+        assert_eq!(stats.write_rejects, 0);
+        assert!(stats.cbf_bytes <= 2048 + 512 + 9);
+        for i in 0..20 {
+            assert_eq!(serve.get(i).unwrap(), vec![i as u8; 512]);
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let path = "/tmp/terra/tests/serve5";
+        let _ = std::fs::remove_dir_all(path);
+        let mut conf = get_conf();
+        conf.path = path.to_string();
+
+        fn concat(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok([base, delta].concat())
+        }
+
+        let mut serve = Serve::new(conf.clone());
+        assert!(matches!(serve.merge(1, b"a".to_vec(), b"a".to_vec()), Err(Error::MergerMissing)));
+        drop(serve);
+
+        let mut serve = Serve::with_merger(conf.clone(), concat).unwrap();
+        serve.set(1, b"a".to_vec()).unwrap();
+        let wal_bytes = serve.stats().unwrap().wal_bytes;
+        serve.merge(1, b"b".to_vec(), b"ab".to_vec()).unwrap();
+        serve.merge(1, b"c".to_vec(), b"abc".to_vec()).unwrap();
+        assert_eq!(serve.get(1).unwrap(), b"abc".to_vec());
+        // 日志只记录变更
+        assert!(serve.stats().unwrap().wal_bytes - wal_bytes < 2 * 32);
+
+        // 模拟崩溃：停止刷盘线程后直接丢弃，重新打开时重放变更
+        serve.worker.take().unwrap().stop();
+        drop(serve);
+        let mut serve = Serve::with_merger(conf, concat).unwrap();
+        assert_eq!(serve.get(1).unwrap(), b"abc".to_vec());
+        serve.close().unwrap();
+        assert_eq!(serve.mainblock.lock().unwrap().get(1).unwrap(), b"abc".to_vec());
+    }
+
+    #[test]
+    fn test_wal_checkpoint() {
+        let path = "/tmp/terra/tests/serve6";
+        let _ = std::fs::remove_dir_all(path);
+        let mut conf = get_conf();
+        conf.path = path.to_string();
+        conf.page_max_cap = 512;
+
+        let mut serve = Serve::new(conf.clone());
+        serve.wal.lock().unwrap().set_file_max_size(1024);
+        serve.worker.take().unwrap().stop();
+        for i in 0..50 {
+            serve.set(i, vec![i as u8; 100]).unwrap();
+        }
+        let wal_bytes = serve.stats().unwrap().wal_bytes;
+
+        // 刷盘后检查已写入的日志文件，只保留未刷盘页所在的日志
+        serve.worker = Some(serve.run());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while serve.stats().unwrap().wal_bytes > 2048 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(serve.stats().unwrap().wal_bytes <= 2048);
+        assert!(serve.stats().unwrap().wal_bytes < wal_bytes / 2);
+
+        // 模拟崩溃，重新打开时从检查点重放未刷盘的变更
+        serve.set(50, vec![50u8; 100]).unwrap();
+        serve.worker.take().unwrap().stop();
+        drop(serve);
+        let mut serve = Serve::new(conf);
+        for i in 0..51 {
+            assert_eq!(serve.get(i).unwrap(), vec![i as u8; 100]);
+        }
+        serve.close().unwrap();
+    }
+
     #[test]
     fn test_byte() {
         let mv = [0, 0, 0, 0, 0, 0, 0, 1];
@@ -137,6 +137,9 @@ impl Entry {
 const WAL_NAME: &str = "@wal";
 const WAL_CK_NAME: &str = "@checked-wal";
 
+// 轮转后的日志文件在其中的版本全部刷盘后即可检查
+const WAL_FILE_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
 #[derive(Debug)]
 pub struct Wal {
     seq: u64,
@@ -167,7 +170,7 @@ impl Wal {
             seq: version,
 
             wlock: Mutex::new(0),
-            file_max_size: 12624855040, // 10GB
+            file_max_size: WAL_FILE_MAX_SIZE,
             rotation_live_time: 1800,   // 30min
             rotation_time: SystemTime::now(),   // 30min
 
@@ -180,15 +183,14 @@ impl Wal {
     }
 
     pub fn append(&mut self, buf: &Vec<u8>) -> Result<u64, Error> {
-        let mut flate_buf = buf.clone();
         // self.wlock.lock();
 
-        self.seq += 1;
-        
-        flate_buf = Payload::new(self.seq, &flate_buf).encode();
+        let flate_buf = Payload::new(self.seq + 1, buf).encode();
 
         self.rotation_log(flate_buf.len(), false);
 
+        self.seq += 1;
+
         let save_res = self.wlog.append(&flate_buf);
         
         if let Err(err) = save_res {
@@ -198,6 +200,7 @@ impl Wal {
         Ok(self.seq)
     }
 
+    // 日志文件以其中的第一个版本号命名，即下一个写入的版本号
     fn rotation_log(&mut self, buf_len: usize, force: bool) {
 
         if force || 
@@ -205,11 +208,11 @@ impl Wal {
             (self.rotation_live_time > 0 &&
             SystemTime::now().duration_since(self.rotation_time).unwrap().as_secs() > self.rotation_live_time) {
 
-            self.log_version_list.push(self.seq);
+            self.log_version_list.push(self.seq + 1);
 
             self.wlog.close();
             
-            self.wlog = Wlog::new(&self.path, self.seq);
+            self.wlog = Wlog::new(&self.path, self.seq + 1);
 
             self.rotation_time = SystemTime::now();
         }
@@ -274,33 +277,49 @@ impl Wal {
         WalReader::new(&self, min_version, max_version)
     }
 
+    // 最新写入的版本号
+    pub fn version(&self) -> u64 {
+        self.seq
+    }
+
+    // 未检查的日志文件总字节数
+    pub fn size(&self) -> u64 {
+        self.log_version_list.iter()
+            .filter_map(|version| std::fs::metadata(self.build_log_name(version)).ok())
+            .map(|meta| meta.len())
+            .sum()
+    }
+
     pub fn checked_version(&mut self, lt_version: u64) -> Vec<u64> {
-        if self.wlog.version <= lt_version {
-            // 当活动日志存在数据时，强制轮转
-            if self.wlog.file_size > 0 {
-                self.rotation_log(0, true);
-            }
+        // 活动日志中的版本均小于 lt_version 时，强制轮转后一并检查
+        if self.seq < lt_version && self.wlog.file_size > 0 {
+            self.rotation_log(0, true);
         }
 
-        let mut version_list = self.log_version_list.clone();
-        version_list.pop();
+        self.checked_rotated(lt_version)
+    }
 
+    // 只检查已轮转的日志文件，下一个文件的首个版本号不大于 lt_version 时，该文件中的版本均小于 lt_version
+    pub fn checked_rotated(&mut self, lt_version: u64) -> Vec<u64> {
         let mut checked_list = vec![];
 
-        for version in version_list {
-            if version >= lt_version {
+        while self.log_version_list.len() > 1 && self.log_version_list[1] <= lt_version {
+            let version = self.log_version_list[0];
+            if Wlog::new(&self.path, version).checked().is_err() {
                 break;
             }
-
-            if let Ok(_) = Wlog::new(&self.path, version).checked() {
-                checked_list.push(version);
-                self.log_version_list.remove(0);
-            }
+            checked_list.push(version);
+            self.log_version_list.remove(0);
         }
 
         checked_list
     }
 
+    #[cfg(test)]
+    pub(crate) fn set_file_max_size(&mut self, size: u64) {
+        self.file_max_size = size;
+    }
+
     fn truncate_all(&mut self) {
         for version in self.log_version_list.clone() {
             if self.wlog.version == version {
@@ -334,30 +353,24 @@ pub struct WalReader {
 }
 
 impl WalReader {
+    // 从可能包含 min_version 的日志文件开始读取，之前的版本在读取时跳过
     fn new(wal: &Wal, min_version: u64, max_version: u64) -> Option<WalReader> {
-        let mut wal_reader = None;
-        let wlog_version_list = wal.log_version_list.clone();
-        for version in wlog_version_list {
-            if version < min_version || (max_version > 0 && version > max_version) {
-                continue;
-            }
-
-            let wlog = Wlog::new(&wal.path, version);
-
-            wal_reader = Some(WalReader{
-                path: wal.path.to_string(),
-                wlog_version_list: wal.log_version_list.clone(),
-                wlog_reader: PageReader::new(wlog),
-                wlog_version: version,
-                max_version,
-                min_version,
-            });
-
-            break;
+        let list = &wal.log_version_list;
+        let version = list[list.iter().rposition(|version| *version <= min_version).unwrap_or(0)];
+        if max_version > 0 && version > max_version {
+            return None;
         }
 
-        wal_reader
-        
+        let wlog = Wlog::new(&wal.path, version);
+
+        Some(WalReader{
+            path: wal.path.to_string(),
+            wlog_version_list: list.clone(),
+            wlog_reader: PageReader::new(wlog),
+            wlog_version: version,
+            max_version,
+            min_version,
+        })
     }
 
 }
@@ -366,25 +379,22 @@ impl Iterator for WalReader {
     type Item = Result<Payload, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let data = self.wlog_reader.next();
-        if data.is_none() {
-            for version in self.wlog_version_list.clone() {
-                if version <= self.wlog_version {
-                    continue;
-                }
+        loop {
+            match self.wlog_reader.next() {
+                Some(Ok(payload)) if payload.version < self.min_version => continue,
+                Some(data) => return Some(data),
+                None => {},
+            }
 
-                if self.max_version > 0 && version > self.max_version {
-                    return None;
-                }
-    
-                let wlog = Wlog::new(&self.path, version);
-                self.wlog_reader = PageReader::new(wlog);
-                self.wlog_version = version;
-                return self.next();
+            let version = self.wlog_version_list.iter().copied().find(|version| *version > self.wlog_version)?;
+            if self.max_version > 0 && version > self.max_version {
+                return None;
             }
-        }
 
-        data
+            let wlog = Wlog::new(&self.path, version);
+            self.wlog_reader = PageReader::new(wlog);
+            self.wlog_version = version;
+        }
     }
 }
 
@@ -666,7 +676,7 @@ mod tests {
 
         println!("list:{:?}", wal.log_version_list);
 
-        let mut read = wal.reader(0, 1000);
+        let read = wal.reader(0, 1000);
 
         let mut index = 0;
         for result in read.unwrap() {
@@ -679,4 +689,29 @@ mod tests {
         }
 
     }
+
+    #[test]
+    fn test_checked_rotated() {
+        let mut wal = Wal::new(&tmp_bitmap_path("wal-checked"));
+        wal.truncate_all();
+        wal.file_max_size = 200;
+
+        for i in 0..30u8 {
+            wal.append(&vec![i; 40]).unwrap();
+        }
+
+        // 只检查版本全部小于 15 的日志文件，活动日志不轮转
+        let checked = wal.checked_rotated(15);
+        assert!(!checked.is_empty());
+        assert!(wal.log_version_list[0] <= 15);
+        assert_eq!(wal.log_version_list.last(), Some(&wal.wlog.version));
+
+        let versions: Vec<u64> = wal.reader(15, 0).unwrap().map(|payload| payload.unwrap().version).collect();
+        assert_eq!(versions, (15..=30).collect::<Vec<_>>());
+
+        // 全部写入后活动日志也一并检查
+        wal.checked_version(31);
+        assert_eq!(wal.log_version_list.len(), 1);
+        assert_eq!(wal.size(), 0);
+    }
 }
@@ -0,0 +1,184 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+
+#[derive(Debug, Default)]
+struct State {
+    stopped: bool,
+    woken: bool,
+    runs: u64, // 任务已执行次数
+}
+
+// 后台线程，按固定间隔执行任务，可通过 wake 提前唤醒，stop 时唤醒并等待线程退出
+#[derive(Debug)]
+pub(crate) struct Worker {
+    signal: Arc<(Mutex<State>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    pub fn spawn<F>(interval: Duration, mut task: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let signal = Arc::new((Mutex::new(State::default()), Condvar::new()));
+        let thread_signal = signal.clone();
+        let handle = thread::spawn(move || {
+            let (state, cvar) = &*thread_signal;
+            loop {
+                task();
+
+                let mut guard = state.lock().unwrap();
+                guard.runs += 1;
+                cvar.notify_all();
+
+                let (mut guard, _) = cvar.wait_timeout_while(guard, interval, |st| !st.stopped && !st.woken).unwrap();
+                if guard.stopped {
+                    break;
+                }
+                guard.woken = false;
+            }
+        });
+
+        Worker {
+            signal,
+            handle: Some(handle),
+        }
+    }
+
+    // 立即执行下一次任务，任务执行中调用时执行完成后不再等待间隔
+    pub fn wake(&self) {
+        let (state, cvar) = &*self.signal;
+        state.lock().unwrap().woken = true;
+        cvar.notify_all();
+    }
+
+    // 唤醒并等待任务再执行完成一次，超时或线程已停止时返回
+    pub fn wake_wait(&self, timeout: Duration) {
+        let (state, cvar) = &*self.signal;
+        let mut guard = state.lock().unwrap();
+        let runs = guard.runs;
+        guard.woken = true;
+        cvar.notify_all();
+        let _ = cvar.wait_timeout_while(guard, timeout, |st| !st.stopped && st.runs == runs).unwrap();
+    }
+
+    // 通知线程退出并等待当前任务执行完成
+    pub fn stop(&mut self) {
+        let (state, cvar) = &*self.signal;
+        state.lock().unwrap().stopped = true;
+        cvar.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// 变更缓冲背压：待刷盘字节数超过高水位时唤醒刷盘线程并等待，超时仍未回落则拒绝写入
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    high_water: usize,
+    stall: Duration,
+    pub stalls: u64,  // 等待过刷盘的写入次数
+    pub rejects: u64, // 缓冲已满被拒绝的写入次数
+}
+
+impl Throttle {
+    pub fn new(high_water: usize, stall_ms: u64) -> Self {
+        Throttle {
+            high_water,
+            stall: Duration::from_millis(stall_ms),
+            ..Default::default()
+        }
+    }
+
+    pub fn over(&self, pending: usize) -> bool {
+        self.high_water > 0 && pending > self.high_water
+    }
+
+    pub fn wait<F>(&mut self, worker: Option<&Worker>, pending: F) -> Result<(), Error>
+    where
+        F: Fn() -> usize,
+    {
+        if !self.over(pending()) {
+            return Ok(());
+        }
+        self.stalls += 1;
+
+        if let Some(worker) = worker {
+            worker.wake();
+            let deadline = Instant::now() + self.stall;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                worker.wake_wait(deadline - now);
+                if !self.over(pending()) {
+                    return Ok(());
+                }
+            }
+        }
+
+        self.rejects += 1;
+        Err(Error::BufferFull)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_wake() {
+        let count = Arc::new(AtomicU64::new(0));
+        let task_count = count.clone();
+        let mut worker = Worker::spawn(Duration::from_secs(60), move || {
+            task_count.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // 间隔很长，只有唤醒才会再次执行
+        let start = Instant::now();
+        worker.wake_wait(Duration::from_secs(5));
+        worker.wake_wait(Duration::from_secs(5));
+        assert!(count.load(Ordering::SeqCst) >= 2);
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        worker.stop();
+        let stopped = count.load(Ordering::SeqCst);
+        worker.wake_wait(Duration::from_secs(5));
+        assert_eq!(count.load(Ordering::SeqCst), stopped);
+    }
+
+    #[test]
+    fn test_throttle() {
+        let pending = Arc::new(AtomicUsize::new(100));
+        let task_pending = pending.clone();
+        let worker = Worker::spawn(Duration::from_secs(60), move || {
+            let _ = task_pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| Some(n.saturating_sub(30)));
+        });
+
+        let mut throttle = Throttle::new(50, 1000);
+        throttle.wait(Some(&worker), || pending.load(Ordering::SeqCst)).unwrap();
+        assert!(pending.load(Ordering::SeqCst) <= 50);
+        assert_eq!(throttle.stalls, 1);
+
+        // 不等待时直接拒绝
+        pending.store(100, Ordering::SeqCst);
+        let mut throttle = Throttle::new(50, 0);
+        assert!(matches!(throttle.wait(None, || pending.load(Ordering::SeqCst)), Err(Error::BufferFull)));
+        assert_eq!(throttle.rejects, 1);
+        assert!(Throttle::new(0, 0).wait(None, || usize::MAX).is_ok());
+    }
+}
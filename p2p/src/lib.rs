@@ -3,6 +3,7 @@ pub mod config;
 pub mod error;
 
 mod node;
+pub use node::NodeStatus;
 
 pub mod service;
 pub use service::{new, Client};
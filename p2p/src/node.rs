@@ -8,4 +8,10 @@ pub struct NodeStatus {
     pub listened_addresses: Vec<Multiaddr>,
     pub known_peers_count: usize,
     pub known_peers: HashMap<PeerId, Vec<Multiaddr>>,
+    /// The number of peers with an open connection.
+    pub connected_peers: usize,
+    /// The number of requests sent to other peers that failed.
+    pub outbound_failures: u64,
+    /// The number of requests from other peers that could not be answered.
+    pub inbound_failures: u64,
 }
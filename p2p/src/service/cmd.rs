@@ -58,6 +58,9 @@ impl <E: EventHandler> Server<E> {
             listened_addresses: self.listened_addresses.clone(),
             known_peers_count: known_peers.len(),
             known_peers,
+            connected_peers: self.network_service.connected_peers().count(),
+            outbound_failures: self.outbound_failures,
+            inbound_failures: self.inbound_failures,
         }
     }
     
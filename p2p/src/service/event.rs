@@ -106,6 +106,15 @@ impl <E: EventHandler> Server<E> {
                 ..
             }) => self.handle_outbound_failure(request_id, error),
 
+            BehaviourEvent::ReqResp(request_response::Event::InboundFailure {
+                peer,
+                error,
+                ..
+            }) => {
+                warn!("❗ Inbound request from {} failed: {:?}", peer, error);
+                self.inbound_failures += 1;
+            }
+
             BehaviourEvent::Pubsub(gossipsub::Event::Message {
                 propagation_source: _,
                 message_id: _,
@@ -142,6 +151,7 @@ impl <E: EventHandler> Server<E> {
 
      // An outbound request failed, notify the application layer.
      fn handle_outbound_failure(&mut self, request_id: OutboundRequestId, error: OutboundFailure) {
+        self.outbound_failures += 1;
         if let Some(responder) = self.pending_outbound_requests.remove(&request_id) {
             error!("❌ Outbound request failed: {:?}", error);
             let _ = responder.send(Err(()));
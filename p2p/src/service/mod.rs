@@ -46,6 +46,10 @@ pub struct Server<E: EventHandler> {
     pubsub_topics: Vec<String>,
 
     pending_outbound_requests: HashMap<OutboundRequestId, oneshot::Sender<ResponseType>>,
+
+    /// The number of failed outbound and inbound requests, for monitoring.
+    outbound_failures: u64,
+    inbound_failures: u64,
 }
 
 impl <E: EventHandler> Server<E> {
@@ -101,6 +105,8 @@ impl <E: EventHandler> Server<E> {
             pubsub_topics,
             listened_addresses: Vec::new(),
             pending_outbound_requests: HashMap::new(),
+            outbound_failures: 0,
+            inbound_failures: 0,
         })
     }
 
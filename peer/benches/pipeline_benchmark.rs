@@ -0,0 +1,80 @@
+use std::fs;
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use p2p::P2pConfig;
+use peer::clients::{BufferedClient, Client};
+use peer::config::Config;
+use peer::server;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+const BENCH_PATH: &str = "/tmp/terra/benches/pipeline";
+const REQUESTS: usize = 1000;
+const VALUE_SIZE: usize = 100;
+
+/// Starts a server in the background and returns its address.
+fn start_server(rt: &Runtime) -> SocketAddr {
+    let _ = fs::remove_dir_all(BENCH_PATH);
+
+    let config = Config {
+        data_dir: BENCH_PATH.to_string(),
+        p2p: P2pConfig {
+            addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+    let addr = listener.local_addr().unwrap();
+    rt.spawn(server::run(listener, config, std::future::pending::<()>()));
+    addr
+}
+
+/// Compares a client that waits for each response before sending the next
+/// request with `REQUESTS` tasks sharing one connection through a
+/// `BufferedClient`, which writes all queued requests in one batch.
+fn criterion_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let addr = start_server(&rt);
+    let val = Bytes::from(vec![1u8; VALUE_SIZE]);
+
+    let mut group = c.benchmark_group(format!("set {} x {}byte", REQUESTS, VALUE_SIZE));
+    group.throughput(Throughput::Elements(REQUESTS as u64));
+
+    let mut client = rt.block_on(Client::connect(addr)).unwrap();
+    group.bench_function("sequential", |b| b.iter(|| {
+        rt.block_on(async {
+            for i in 0..REQUESTS {
+                let key = Bytes::from(format!("key-{}", i));
+                client.set(key, val.clone()).await.unwrap();
+            }
+        })
+    }));
+
+    let buffered = rt.block_on(async { BufferedClient::buffer(Client::connect(addr).await.unwrap()) });
+    group.bench_function("pipelined", |b| b.iter(|| {
+        rt.block_on(async {
+            let tasks: Vec<_> = (0..REQUESTS)
+                .map(|i| {
+                    let client = buffered.clone();
+                    let val = val.clone();
+                    tokio::spawn(async move {
+                        let key = Bytes::from(format!("key-{}", i));
+                        client.set(key, val).await.unwrap();
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        })
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,276 @@
+use crate::config::{AuthConfig, UserConfig};
+use crate::utils::glob_match;
+use crate::Command;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the user `AUTH <password>` authenticates as.
+pub(crate) const DEFAULT_USER: &str = "default";
+
+/// The users allowed to connect and what each of them may access.
+///
+/// Built from the `[auth]` section of the configuration. Without users,
+/// authentication is disabled and every connection may run every command.
+#[derive(Debug, Default)]
+pub(crate) struct Acl {
+    users: HashMap<String, Arc<User>>,
+}
+
+/// An authenticated user and its access rules.
+pub(crate) struct User {
+    name: String,
+
+    password: String,
+
+    /// Allowed command names, `*` for all. Names prefixed with `-` are denied.
+    commands: Vec<String>,
+
+    /// Glob patterns of the keys the user may access.
+    keys: Vec<String>,
+}
+
+impl Acl {
+    /// Builds the access rules of `config`.
+    pub(crate) fn new(config: &AuthConfig) -> Acl {
+        let mut users = HashMap::new();
+
+        if let Some(password) = &config.requirepass {
+            let user = User {
+                name: DEFAULT_USER.to_string(),
+                password: password.clone(),
+                commands: vec!["*".to_string()],
+                keys: vec!["*".to_string()],
+            };
+            users.insert(user.name.clone(), Arc::new(user));
+        }
+
+        for user in &config.users {
+            users.insert(user.name.clone(), Arc::new(User::from(user)));
+        }
+
+        Acl { users }
+    }
+
+    /// Returns `true` if connections must authenticate before running
+    /// commands.
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Returns the user `name` if `password` is its password.
+    pub(crate) fn authenticate(&self, name: &str, password: &str) -> Option<Arc<User>> {
+        let user = self.users.get(name)?;
+        if constant_time_eq(user.password.as_bytes(), password.as_bytes()) {
+            Some(user.clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl User {
+    /// Returns the user name.
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks that the user may run `cmd` on the keys it accesses.
+    ///
+    /// On failure the `NOPERM` error reported to the client is returned.
+    pub(crate) fn check(&self, cmd: &Command) -> Result<(), String> {
+        let name = cmd.get_name();
+        if !self.can_run(name) {
+            return Err(format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                self.name, name
+            ));
+        }
+
+        let denied = cmd.keys().into_iter().any(|key| {
+            !self.keys.iter().any(|pattern| glob_match(pattern.as_bytes(), key))
+        });
+        if denied {
+            return Err("NOPERM No permissions to access a key".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn can_run(&self, name: &str) -> bool {
+        let mut allowed = false;
+        for rule in &self.commands {
+            match rule.strip_prefix('-') {
+                Some(denied) if denied.eq_ignore_ascii_case(name) => return false,
+                Some(_) => {}
+                None if rule == "*" || rule.eq_ignore_ascii_case(name) => allowed = true,
+                None => {}
+            }
+        }
+        allowed
+    }
+}
+
+impl From<&UserConfig> for User {
+    fn from(config: &UserConfig) -> User {
+        User {
+            name: config.name.clone(),
+            password: config.password.clone(),
+            commands: config.commands.clone(),
+            keys: config.keys.clone(),
+        }
+    }
+}
+
+// Leaves the password out of the logs.
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("User")
+            .field("name", &self.name)
+            .field("commands", &self.commands)
+            .field("keys", &self.keys)
+            .finish()
+    }
+}
+
+/// Compares two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::ClientRegistry;
+    use crate::session::Session;
+    use crate::Frame;
+    use bytes::Bytes;
+
+    fn cmd(args: &[&str]) -> Command {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+        Command::from_frame(frame).unwrap()
+    }
+
+    fn user(commands: &[&str], keys: &[&str]) -> UserConfig {
+        UserConfig {
+            name: "alice".to_string(),
+            password: "secret".to_string(),
+            commands: commands.iter().map(|rule| rule.to_string()).collect(),
+            keys: keys.iter().map(|pattern| pattern.to_string()).collect(),
+        }
+    }
+
+    fn with_users(users: Vec<UserConfig>) -> Acl {
+        Acl::new(&AuthConfig {
+            requirepass: Some("root".to_string()),
+            users,
+        })
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"user:*", b"user:"));
+        assert!(glob_match(b"user:*", b"user:42"));
+        assert!(!glob_match(b"user:*", b"users:42"));
+        assert!(glob_match(b"user:?", b"user:4"));
+        assert!(!glob_match(b"user:?", b"user:42"));
+        assert!(!glob_match(b"user:?", b"user:"));
+        assert!(glob_match(b"*:cache:*", b"app:cache:item"));
+        assert!(glob_match(b"a*b*c", b"axxbyyc"));
+        assert!(!glob_match(b"a*b*c", b"axxbyy"));
+        // The last `*` is retried after a partial match.
+        assert!(glob_match(b"*ab", b"aab"));
+        assert!(glob_match(b"exact", b"exact"));
+        assert!(!glob_match(b"exact", b"exactly"));
+    }
+
+    #[test]
+    fn key_patterns() {
+        let acl = with_users(vec![user(&["*"], &["user:*", "session:?"])]);
+        let alice = acl.authenticate("alice", "secret").unwrap();
+
+        assert!(alice.check(&cmd(&["get", "user:1"])).is_ok());
+        assert!(alice.check(&cmd(&["set", "session:a", "v"])).is_ok());
+        assert!(alice.check(&cmd(&["mget", "user:1", "user:2"])).is_ok());
+        assert_eq!(
+            alice.check(&cmd(&["mget", "user:1", "admin"])).unwrap_err(),
+            "NOPERM No permissions to access a key"
+        );
+        assert!(alice.check(&cmd(&["get", "session:ab"])).is_err());
+        // Commands without keys are not restricted by the patterns.
+        assert!(alice.check(&cmd(&["ping"])).is_ok());
+    }
+
+    #[test]
+    fn deny_precedence() {
+        let acl = with_users(vec![user(&["*", "-del", "-FLUSHDB"], &["*"])]);
+        let alice = acl.authenticate("alice", "secret").unwrap();
+
+        assert!(alice.check(&cmd(&["get", "k"])).is_ok());
+        assert_eq!(
+            alice.check(&cmd(&["del", "k"])).unwrap_err(),
+            "NOPERM User alice has no permissions to run the 'del' command"
+        );
+
+        // A denial wins regardless of where the allowing rule is listed.
+        let acl = with_users(vec![user(&["-set", "get", "set"], &["*"])]);
+        let alice = acl.authenticate("alice", "secret").unwrap();
+        assert!(alice.check(&cmd(&["get", "k"])).is_ok());
+        assert!(alice.check(&cmd(&["set", "k", "v"])).is_err());
+        assert!(alice.check(&cmd(&["exists", "k"])).is_err());
+
+        // The counter commands are told apart.
+        let acl = with_users(vec![user(&["*", "-INCR"], &["*"])]);
+        let alice = acl.authenticate("alice", "secret").unwrap();
+        assert_eq!(
+            alice.check(&cmd(&["incr", "k"])).unwrap_err(),
+            "NOPERM User alice has no permissions to run the 'incr' command"
+        );
+        assert!(alice.check(&cmd(&["incrby", "k", "2"])).is_ok());
+        assert!(alice.check(&cmd(&["decr", "k"])).is_ok());
+    }
+
+    #[test]
+    fn auth() {
+        let acl = Arc::new(with_users(vec![user(&["get"], &["*"])]));
+        assert!(acl.is_enabled());
+        assert!(acl.authenticate("alice", "secret").is_some());
+        assert!(acl.authenticate("alice", "secre").is_none());
+        assert!(acl.authenticate("alice", "root").is_none());
+        assert!(acl.authenticate("bob", "secret").is_none());
+
+        let clients = Arc::new(ClientRegistry::new(1));
+        let mut session = Session::new(clients.register("127.0.0.1:1".parse().unwrap()), acl);
+        assert_eq!(session.authorize(&cmd(&["get", "k"])).unwrap_err(), "NOAUTH Authentication required.");
+        assert!(session.authorize(&cmd(&["auth", "secret"])).is_ok());
+
+        // A failed attempt leaves the connection unauthenticated.
+        assert!(session.authenticate(Some("alice"), "wrong").unwrap_err().starts_with("WRONGPASS"));
+        assert!(session.authorize(&cmd(&["get", "k"])).is_err());
+
+        session.authenticate(Some("alice"), "secret").unwrap();
+        assert!(session.authorize(&cmd(&["get", "k"])).is_ok());
+        assert!(session.authorize(&cmd(&["set", "k", "v"])).unwrap_err().starts_with("NOPERM"));
+
+        // `AUTH <password>` authenticates as the default user.
+        session.authenticate(None, "root").unwrap();
+        assert!(session.authorize(&cmd(&["set", "k", "v"])).is_ok());
+    }
+
+    #[test]
+    fn disabled() {
+        let acl = Arc::new(Acl::new(&AuthConfig::default()));
+        assert!(!acl.is_enabled());
+
+        let clients = Arc::new(ClientRegistry::new(1));
+        let mut session = Session::new(clients.register("127.0.0.1:1".parse().unwrap()), acl);
+        assert!(session.authorize(&cmd(&["set", "k", "v"])).is_ok());
+        assert!(session.authenticate(None, "anything").unwrap_err().starts_with("ERR AUTH called"));
+    }
+}
@@ -1,9 +1,10 @@
-use peer::{clients::Client, DEFAULT_PORT};
+use peer::{clients::Client, TlsOptions, DEFAULT_PORT};
 
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
-use std::convert::Infallible;
+use std::convert::{Infallible, TryFrom};
 use std::num::ParseIntError;
+use std::path::PathBuf;
 use std::str;
 use std::time::Duration;
 
@@ -23,6 +24,39 @@ struct Cli {
 
     #[clap(long, default_value_t = DEFAULT_PORT)]
     port: u16,
+
+    /// Database index or namespace name to run the command against
+    #[clap(long)]
+    db: Option<String>,
+
+    /// User to authenticate as, the default user if not set
+    #[clap(long)]
+    user: Option<String>,
+
+    /// Password to authenticate with
+    #[clap(long = "pass")]
+    password: Option<String>,
+
+    /// Connect with TLS
+    #[clap(long, requires = "cacert")]
+    tls: bool,
+
+    /// CA certificate file to verify the server certificate with
+    #[clap(long, requires = "tls")]
+    cacert: Option<PathBuf>,
+
+    /// Client certificate file, for servers requiring mutual TLS
+    #[clap(long, requires = "tls", requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// Private key file of the client certificate
+    #[clap(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Server name to verify the server certificate against, defaults to the
+    /// hostname
+    #[clap(long, requires = "tls")]
+    sni: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -32,6 +66,33 @@ enum Command {
         #[clap(value_parser = bytes_from_str)]
         msg: Option<Bytes>,
     },
+    /// Negotiate the protocol version and show the server description.
+    Hello {
+        /// Protocol version, 2 or 3
+        protover: Option<u64>,
+    },
+    /// Get server information and statistics.
+    Info {
+        /// Section to return, e.g. keyspace or storage
+        section: Option<String>,
+    },
+    /// Get the number of keys in the selected database.
+    Dbsize,
+    /// Read the server configuration.
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Inspect and close client connections.
+    Client {
+        #[clap(subcommand)]
+        command: ClientCommand,
+    },
+    /// Read or clear the slow command log.
+    Slowlog {
+        #[clap(subcommand)]
+        command: SlowlogCommand,
+    },
     /// Get the value of key.
     Get {
         /// Name of key to get
@@ -48,6 +109,104 @@ enum Command {
         /// Expire the value after specified amount of time
         #[clap(value_parser = duration_from_ms_str)]
         expires: Option<Duration>,
+
+        /// Only set the key if it does not exist
+        #[clap(long, conflicts_with_all = ["xx", "get", "expires"])]
+        nx: bool,
+
+        /// Only set the key if it already exists
+        #[clap(long, conflicts_with_all = ["get", "expires"])]
+        xx: bool,
+
+        /// Return the previous value
+        #[clap(long, conflicts_with = "expires")]
+        get: bool,
+    },
+    /// Delete keys.
+    Del {
+        /// Names of keys to delete
+        #[clap(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Count how many of the keys exist.
+    Exists {
+        /// Names of keys to check
+        #[clap(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Get the values of several keys.
+    Mget {
+        /// Names of keys to get
+        #[clap(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set several keys at once.
+    Mset {
+        /// Alternating keys and values
+        #[clap(required = true, value_parser = bytes_from_str)]
+        pairs: Vec<Bytes>,
+    },
+    /// Publish a message to a channel.
+    Publish {
+        /// Name of channel
+        channel: String,
+
+        #[clap(value_parser = bytes_from_str)]
+        /// Message to publish
+        message: Bytes,
+    },
+    /// Subscribe a client to a specific channel or channels.
+    Subscribe {
+        /// Specific channel or channels
+        #[clap(required = true)]
+        channels: Vec<String>,
+    },
+    /// Get the value of key together with its version.
+    Getver {
+        /// Name of key to get
+        key: Bytes,
+    },
+    /// Set key to value if its version still matches.
+    Cas {
+        /// Name of key to set
+        key: Bytes,
+
+        /// Version returned by getver, 0 if the key must not exist
+        version: u64,
+
+        /// Value to set.
+        #[clap(value_parser = bytes_from_str)]
+        value: Bytes,
+    },
+    /// Increment the integer value of key by one.
+    Incr {
+        /// Name of the counter key
+        key: Bytes,
+    },
+    /// Decrement the integer value of key by one.
+    Decr {
+        /// Name of the counter key
+        key: Bytes,
+    },
+    /// Increment the integer value of key by delta.
+    #[clap(name = "incrby")]
+    IncrBy {
+        /// Name of the counter key
+        key: Bytes,
+
+        /// Amount to add
+        #[clap(allow_hyphen_values = true)]
+        delta: i64,
+    },
+    /// Decrement the integer value of key by delta.
+    #[clap(name = "decrby")]
+    DecrBy {
+        /// Name of the counter key
+        key: Bytes,
+
+        /// Amount to subtract
+        #[clap(allow_hyphen_values = true)]
+        delta: i64,
     },
     Peer {
         // Node subcommand
@@ -56,6 +215,40 @@ enum Command {
     }
 }
 
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Get the parameters matching a glob pattern.
+    Get {
+        /// Pattern of the parameter names, e.g. storage.*
+        pattern: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ClientCommand {
+    /// List the client connections.
+    List,
+    /// Close the connection from an address.
+    Kill {
+        /// Client address, as ip:port
+        addr: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SlowlogCommand {
+    /// Show the most recent entries.
+    Get {
+        /// Number of entries, -1 for all
+        #[clap(allow_hyphen_values = true)]
+        count: Option<i64>,
+    },
+    /// Show the number of entries.
+    Len,
+    /// Remove every entry.
+    Reset,
+}
+
 #[derive(Subcommand, Debug)]
 enum PeerCommand {
     Basic,
@@ -69,8 +262,28 @@ async fn main() -> peer::Result<()> {
     // Get the remote address to connect to
     let addr = format!("{}:{}", cli.host, cli.port);
 
-    // Establish a connection
-    let mut client = Client::connect(&addr).await?;
+    // Establish a connection. The TLS flags require each other, so `--cacert`
+    // is only set together with `--tls`.
+    let mut client = match cli.cacert {
+        Some(ca_file) => {
+            let tls = TlsOptions {
+                ca_file,
+                cert_file: cli.cert,
+                key_file: cli.key,
+                server_name: cli.sni,
+            };
+            Client::connect_tls(&addr, &tls).await?
+        }
+        _ => Client::connect(&addr).await?,
+    };
+
+    if let Some(password) = &cli.password {
+        client.auth(cli.user.as_deref(), password).await?;
+    }
+
+    if let Some(db) = &cli.db {
+        client.select(db).await?;
+    }
 
     // Process the requested command
     match cli.command {
@@ -82,33 +295,148 @@ async fn main() -> peer::Result<()> {
                 println!("{:?}", value);
             }
         }
-        Command::Get { key } => {
-            if let Some(value) = client.get(key).await? {
-                if let Ok(string) = str::from_utf8(&value) {
-                    println!("\"{}\"", string);
-                } else {
-                    println!("{:?}", value);
-                }
+        Command::Hello { protover } => {
+            for (field, value) in client.hello(protover).await? {
+                println!("{}: {}", field, value);
+            }
+        }
+        Command::Info { section } => {
+            let value = client.info(section).await?;
+            if let Ok(string) = str::from_utf8(&value) {
+                print!("{}", string.replace("\r\n", "\n"));
             } else {
-                println!("(nil)");
+                println!("{:?}", value);
             }
         }
-        Command::Set {
-            key,
-            value,
-            expires: None,
-        } => {
-            client.set(key, value).await?;
-            println!("OK");
+        Command::Dbsize => {
+            println!("(integer) {}", client.dbsize().await?);
         }
+        Command::Config { command } => match command {
+            ConfigCommand::Get { pattern } => {
+                for (name, value) in client.config_get(&pattern).await? {
+                    println!("{}: {}", name, value);
+                }
+            }
+        },
+        Command::Client { command } => match command {
+            ClientCommand::List => print!("{}", client.client_list().await?),
+            ClientCommand::Kill { addr } => {
+                client.client_kill(&addr).await?;
+                println!("OK");
+            }
+        },
+        Command::Slowlog { command } => match command {
+            SlowlogCommand::Get { count } => {
+                let count = match count {
+                    Some(-1) => None,
+                    Some(count) => Some(usize::try_from(count).map_err(|_| "count must be -1 or more")?),
+                    None => Some(10),
+                };
+                for entry in client.slowlog_get(count).await? {
+                    let args: Vec<_> = entry.args.iter().map(|arg| String::from_utf8_lossy(arg)).collect();
+                    println!(
+                        "{}) {} {} {}us (storage {}us, other {}us): {}",
+                        entry.id,
+                        entry.timestamp,
+                        entry.addr,
+                        entry.duration.as_micros(),
+                        entry.storage.as_micros(),
+                        entry.other().as_micros(),
+                        args.join(" ")
+                    );
+                }
+            }
+            SlowlogCommand::Len => {
+                println!("(integer) {}", client.slowlog_len().await?);
+            }
+            SlowlogCommand::Reset => {
+                client.slowlog_reset().await?;
+                println!("OK");
+            }
+        },
+        Command::Get { key } => print_value(client.get(key).await?),
         Command::Set {
             key,
             value,
-            expires: Some(expires),
+            expires,
+            nx,
+            xx,
+            get,
         } => {
-            client.set_expires(key, value, expires).await?;
+            let written = if nx {
+                client.set_nx(key, value).await?
+            } else if xx {
+                client.set_xx(key, value).await?
+            } else if get {
+                print_value(client.set_get(key, value).await?);
+                return Ok(());
+            } else if let Some(expires) = expires {
+                client.set_expires(key, value, expires).await?;
+                true
+            } else {
+                client.set(key, value).await?;
+                true
+            };
+            println!("{}", if written { "OK" } else { "(nil)" });
+        }
+        Command::Del { keys } => {
+            println!("(integer) {}", client.del(keys).await?);
+        }
+        Command::Exists { keys } => {
+            println!("(integer) {}", client.exists(keys).await?);
+        }
+        Command::Mget { keys } => {
+            for (i, value) in client.mget(keys).await?.into_iter().enumerate() {
+                print!("{}) ", i + 1);
+                print_value(value);
+            }
+        }
+        Command::Mset { pairs } => {
+            if pairs.len() % 2 != 0 {
+                return Err("mset expects key value pairs".into());
+            }
+            let pairs = pairs.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+            client.mset(pairs).await?;
             println!("OK");
         }
+        Command::Publish { channel, message } => {
+            println!("(integer) {}", client.publish(&channel, message).await?);
+        }
+        Command::Subscribe { channels } => {
+            let mut subscriber = client.subscribe(channels).await?;
+
+            // await messages on channels
+            while let Some(msg) = subscriber.next_message().await? {
+                println!(
+                    "got message from the channel: {}; message = {:?}",
+                    msg.channel, msg.content
+                );
+            }
+        }
+        Command::Getver { key } => match client.get_versioned(key).await? {
+            Some((value, version)) => {
+                print_value(Some(value));
+                println!("(version) {}", version);
+            }
+            None => println!("(nil)"),
+        },
+        Command::Cas { key, version, value } => match client.cas(key, version, value).await? {
+            Some(version) => println!("(integer) {}", version),
+            None => println!("(nil)"),
+        },
+        Command::Incr { key } => {
+            println!("(integer) {}", client.incr_by(key, 1).await?);
+        }
+        Command::Decr { key } => {
+            println!("(integer) {}", client.incr_by(key, -1).await?);
+        }
+        Command::IncrBy { key, delta } => {
+            println!("(integer) {}", client.incr_by(key, delta).await?);
+        }
+        Command::DecrBy { key, delta } => {
+            let delta = delta.checked_neg().ok_or("delta out of range")?;
+            println!("(integer) {}", client.incr_by(key, delta).await?);
+        }
         Command::Peer {
             command,
         } => {
@@ -131,6 +459,19 @@ async fn main() -> peer::Result<()> {
     Ok(())
 }
 
+fn print_value(value: Option<Bytes>) {
+    match value {
+        Some(value) => {
+            if let Ok(string) = str::from_utf8(&value) {
+                println!("\"{}\"", string);
+            } else {
+                println!("{:?}", value);
+            }
+        }
+        None => println!("(nil)"),
+    }
+}
+
 fn duration_from_ms_str(src: &str) -> Result<Duration, ParseIntError> {
     let ms = src.parse::<u64>()?;
     Ok(Duration::from_millis(ms))
@@ -139,3 +480,28 @@ fn duration_from_ms_str(src: &str) -> Result<Duration, ParseIntError> {
 fn bytes_from_str(src: &str) -> Result<Bytes, Infallible> {
     Ok(Bytes::from(src.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(["peer-cli"].iter().chain(args).chain(&["ping"]))
+    }
+
+    #[test]
+    fn tls_flags() {
+        let cli = parse(&["--tls", "--cacert", "ca.pem", "--cert", "c.pem", "--key", "k.pem", "--sni", "peer"]).unwrap();
+        assert!(cli.tls);
+        assert_eq!(cli.sni.as_deref(), Some("peer"));
+        assert!(!parse(&[]).unwrap().tls);
+
+        // TLS flags without `--tls` are rejected instead of connecting in
+        // plaintext.
+        assert!(parse(&["--tls"]).is_err());
+        assert!(parse(&["--cacert", "ca.pem"]).is_err());
+        assert!(parse(&["--cacert", "ca.pem", "--sni", "peer"]).is_err());
+        assert!(parse(&["--cacert", "ca.pem", "--cert", "c.pem", "--key", "k.pem"]).is_err());
+        assert!(parse(&["--tls", "--cacert", "ca.pem", "--cert", "c.pem"]).is_err());
+    }
+}
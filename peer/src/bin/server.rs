@@ -13,14 +13,12 @@ pub async fn main() -> peer::Result<()> {
     let port = cli.port.unwrap_or(DEFAULT_PORT);
     let config_path = cli.config;
 
-    let config = Config::load(&config_path).unwrap();
+    let config = Config::load(&config_path)?;
 
     // Bind a TCP listener
     let listener = TcpListener::bind(&format!("{}", config.http_addr)).await?;
 
-    server::run(listener, config, signal::ctrl_c()).await;
-
-    Ok(())
+    server::run(listener, config, signal::ctrl_c()).await
 }
 
 #[derive(Parser, Debug)]
@@ -2,6 +2,9 @@
 //!
 //! Provides a blocking connect and methods for issuing the supported commands.
 
+use crate::cmd::SlowLogEntry;
+use crate::TlsOptions;
+
 use bytes::Bytes;
 use std::time::Duration;
 use tokio::net::ToSocketAddrs;
@@ -80,6 +83,18 @@ impl BlockingClient {
         Ok(BlockingClient { inner, rt })
     }
 
+    /// Establish a TLS connection with the server located at `addr`, given as
+    /// `host:port`. See `Client::connect_tls`.
+    pub fn connect_tls(addr: &str, tls: &TlsOptions) -> crate::Result<BlockingClient> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let inner = rt.block_on(crate::clients::Client::connect_tls(addr, tls))?;
+
+        Ok(BlockingClient { inner, rt })
+    }
+
     /// Get the value of key.
     ///
     /// If the key does not exist the special value `None` is returned.
@@ -102,6 +117,18 @@ impl BlockingClient {
         self.rt.block_on(self.inner.get(key))
     }
 
+    /// Authenticate the connection as `username`, or as the default user if
+    /// no name is given.
+    pub fn auth(&mut self, username: Option<&str>, password: &str) -> crate::Result<()> {
+        self.rt.block_on(self.inner.auth(username, password))
+    }
+
+    /// Switch the connection to the database `db`, given by index or
+    /// namespace name.
+    pub fn select(&mut self, db: &str) -> crate::Result<()> {
+        self.rt.block_on(self.inner.select(db))
+    }
+
     /// Set `key` to hold the given `value`.
     ///
     /// The `value` is associated with `key` until it is overwritten by the next
@@ -131,6 +158,89 @@ impl BlockingClient {
         self.rt.block_on(self.inner.set(key, value))
     }
 
+    /// Posts `message` to the given `channel`.
+    ///
+    /// Returns the number of subscribers currently listening on the channel
+    /// of the node the client is connected to.
+    pub fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.publish(channel, message))
+    }
+
+    /// Subscribes the client to the specified channels.
+    ///
+    /// Once a client issues a subscribe command, it may no longer issue any
+    /// non-pub/sub commands. The function consumes `self` and returns a
+    /// `BlockingSubscriber`.
+    pub fn subscribe(self, channels: Vec<String>) -> crate::Result<BlockingSubscriber> {
+        let subscriber = self.rt.block_on(self.inner.subscribe(channels))?;
+        Ok(BlockingSubscriber {
+            inner: subscriber,
+            rt: self.rt,
+        })
+    }
+
+    /// Remove `keys` and return how many of them existed.
+    pub fn del(&mut self, keys: Vec<Bytes>) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.del(keys))
+    }
+
+    /// Return the number of keys in the selected database.
+    pub fn dbsize(&mut self) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.dbsize())
+    }
+
+    /// Return the configuration parameters whose name matches the glob
+    /// `pattern`, as name-value pairs.
+    pub fn config_get(&mut self, pattern: &str) -> crate::Result<Vec<(String, String)>> {
+        self.rt.block_on(self.inner.config_get(pattern))
+    }
+
+    /// Return the connections to the server, one line per connection.
+    pub fn client_list(&mut self) -> crate::Result<String> {
+        self.rt.block_on(self.inner.client_list())
+    }
+
+    /// Close the connection from `addr`, given as `ip:port`.
+    pub fn client_kill(&mut self, addr: &str) -> crate::Result<()> {
+        self.rt.block_on(self.inner.client_kill(addr))
+    }
+
+    /// Return up to `count` entries of the slow log, newest first, or every
+    /// entry if `count` is `None`.
+    pub fn slowlog_get(&mut self, count: Option<usize>) -> crate::Result<Vec<SlowLogEntry>> {
+        self.rt.block_on(self.inner.slowlog_get(count))
+    }
+
+    /// Return the number of entries in the slow log.
+    pub fn slowlog_len(&mut self) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.slowlog_len())
+    }
+
+    /// Remove every entry from the slow log.
+    pub fn slowlog_reset(&mut self) -> crate::Result<()> {
+        self.rt.block_on(self.inner.slowlog_reset())
+    }
+
+    /// Return how many of `keys` exist.
+    pub fn exists(&mut self, keys: Vec<Bytes>) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.exists(keys))
+    }
+
+    /// Get the values of `keys`, `None` for keys that do not exist.
+    pub fn mget(&mut self, keys: Vec<Bytes>) -> crate::Result<Vec<Option<Bytes>>> {
+        self.rt.block_on(self.inner.mget(keys))
+    }
+
+    /// Set every key-value pair of `pairs`.
+    pub fn mset(&mut self, pairs: Vec<(Bytes, Bytes)>) -> crate::Result<()> {
+        self.rt.block_on(self.inner.mset(pairs))
+    }
+
+    /// Add `delta` to the integer stored at `key` and return the new value.
+    pub fn incr_by(&mut self, key: Bytes, delta: i64) -> crate::Result<i64> {
+        self.rt.block_on(self.inner.incr_by(key, delta))
+    }
+
     /// Set `key` to hold the given `value`. The value expires after `expiration`
     ///
     /// The `value` is associated with `key` until one of the following:
@@ -188,6 +298,17 @@ impl BlockingSubscriber {
         self.inner.get_subscribed()
     }
 
+    /// Subscribe to a list of new channels
+    pub fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.subscribe(channels))
+    }
+
+    /// Unsubscribe from a list of channels, or from all channels if `channels`
+    /// is empty.
+    pub fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.unsubscribe(channels))
+    }
+
     /// Receive the next message published on a subscribed channel, waiting if
     /// necessary.
     ///
@@ -1,120 +1,201 @@
-// use crate::clients::Client;
-// use crate::Result;
-
-// use bytes::Bytes;
-// use tokio::sync::mpsc::{channel, Receiver, Sender};
-// use tokio::sync::oneshot;
-
-// // Enum used to message pass the requested command from the `BufferedClient` handle
-// #[derive(Debug)]
-// enum Command {
-//     Get(String),
-//     Set(String, Bytes),
-// }
-
-// // Message type sent over the channel to the connection task.
-// //
-// // `Command` is the command to forward to the connection.
-// //
-// // `oneshot::Sender` is a channel type that sends a **single** value. It is used
-// // here to send the response received from the connection back to the original
-// // requester.
-// type Message = (Command, oneshot::Sender<Result<Option<Bytes>>>);
-
-// /// Receive commands sent through the channel and forward them to client. The
-// /// response is returned back to the caller via a `oneshot`.
-// async fn run(mut client: Client, mut rx: Receiver<Message>) {
-//     // Repeatedly pop messages from the channel. A return value of `None`
-//     // indicates that all `BufferedClient` handles have dropped and there will never be
-//     // another message sent on the channel.
-//     while let Some((cmd, tx)) = rx.recv().await {
-//         // The command is forwarded to the connection
-//         let response = match cmd {
-//             Command::Get(key) => client.get(&key).await,
-//             Command::Set(key, value) => client.set(&key, value).await.map(|_| None),
-//         };
-
-//         // Send the response back to the caller.
-//         //
-//         // Failing to send the message indicates the `rx` half dropped
-//         // before receiving the message. This is a normal runtime event.
-//         let _ = tx.send(response);
-//     }
-// }
-
-// #[derive(Clone)]
-// pub struct BufferedClient {
-//     tx: Sender<Message>,
-// }
-
-// impl BufferedClient {
-//     /// Create a new client request buffer
-//     ///
-//     /// The `Client` performs Redis commands directly on the TCP connection. Only a
-//     /// single request may be in-flight at a given time and operations require
-//     /// mutable access to the `Client` handle. This prevents using a single Redis
-//     /// connection from multiple Tokio tasks.
-//     ///
-//     /// The strategy for dealing with this class of problem is to spawn a dedicated
-//     /// Tokio task to manage the Redis connection and using "message passing" to
-//     /// operate on the connection. Commands are pushed into a channel. The
-//     /// connection task pops commands off of the channel and applies them to the
-//     /// Redis connection. When the response is received, it is forwarded to the
-//     /// original requester.
-//     ///
-//     /// The returned `BufferedClient` handle may be cloned before passing the new handle to
-//     /// separate tasks.
-//     pub fn buffer(client: Client) -> BufferedClient {
-//         // Setting the message limit to a hard coded value of 32. in a real-app, the
-//         // buffer size should be configurable, but we don't need to do that here.
-//         let (tx, rx) = channel(32);
-
-//         // Spawn a task to process requests for the connection.
-//         tokio::spawn(async move { run(client, rx).await });
-
-//         // Return the `BufferedClient` handle.
-//         BufferedClient { tx }
-//     }
-
-//     /// Get the value of a key.
-//     ///
-//     /// Same as `Client::get` but requests are **buffered** until the associated
-//     /// connection has the ability to send the request.
-//     pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
-//         // Initialize a new `Get` command to send via the channel.
-//         let get = Command::Get(key.into());
-
-//         // Initialize a new oneshot to be used to receive the response back from the connection.
-//         let (tx, rx) = oneshot::channel();
-
-//         // Send the request
-//         self.tx.send((get, tx)).await?;
-
-//         // Await the response
-//         match rx.await {
-//             Ok(res) => res,
-//             Err(err) => Err(err.into()),
-//         }
-//     }
-
-//     /// Set `key` to hold the given `value`.
-//     ///
-//     /// Same as `Client::set` but requests are **buffered** until the associated
-//     /// connection has the ability to send the request
-//     pub async fn set(&mut self, key: &str, value: Bytes) -> Result<()> {
-//         // Initialize a new `Set` command to send via the channel.
-//         let set = Command::Set(key.into(), value);
-
-//         // Initialize a new oneshot to be used to receive the response back from the connection.
-//         let (tx, rx) = oneshot::channel();
-
-//         // Send the request
-//         self.tx.send((set, tx)).await?;
-
-//         // Await the response
-//         match rx.await {
-//             Ok(res) => res.map(|_| ()),
-//             Err(err) => Err(err.into()),
-//         }
-//     }
-// }
+use crate::clients::Client;
+use crate::cmd::{Del, Get, Incr, Ping, Set};
+use crate::{Error, Frame, Result};
+
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
+
+/// Maximum number of requests queued by the `BufferedClient` handles.
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Maximum number of requests written to the connection before reading their
+/// responses.
+const MAX_BATCH: usize = 128;
+
+// Message type sent over the channel to the connection task.
+//
+// `Frame` is the request to forward to the connection.
+//
+// `oneshot::Sender` is a channel type that sends a **single** value. It is used
+// here to send the response received from the connection back to the original
+// requester. `Err` is only sent when the connection failed; error replies are
+// passed on as `Frame::Error`.
+type Message = (Frame, oneshot::Sender<Result<Frame>>);
+
+/// Receive requests sent through the channel and forward them to client. The
+/// response is returned back to the caller via a `oneshot`.
+///
+/// All requests waiting in the channel are written to the connection before
+/// the first response is read, so callers issuing commands concurrently share
+/// round trips instead of waiting for each other.
+async fn run(mut client: Client, mut rx: Receiver<Message>) {
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+
+    // Repeatedly pop messages from the channel. A return value of `0`
+    // indicates that all `BufferedClient` handles have dropped and there will
+    // never be another message sent on the channel.
+    while rx.recv_many(&mut batch, MAX_BATCH).await > 0 {
+        let mut written = 0;
+        let mut failure = None;
+
+        for (request, _) in &batch {
+            if let Err(err) = client.write_request(request).await {
+                failure = Some(err.to_string());
+                break;
+            }
+            written += 1;
+        }
+
+        // Responses arrive in request order. The first read flushes the
+        // whole batch to the socket.
+        for (_, tx) in batch.drain(..written) {
+            if failure.is_some() {
+                let _ = tx.send(Err(connection_failed(&failure)));
+                continue;
+            }
+
+            let response = client.read_reply().await;
+            if let Err(err) = &response {
+                failure = Some(err.to_string());
+            }
+
+            // Failing to send the message indicates the `rx` half dropped
+            // before receiving the message. This is a normal runtime event.
+            let _ = tx.send(response);
+        }
+
+        // Once the connection failed, the responses of later requests could
+        // no longer be matched to them.
+        if failure.is_some() {
+            for (_, tx) in batch.drain(..) {
+                let _ = tx.send(Err(connection_failed(&failure)));
+            }
+            return;
+        }
+    }
+}
+
+fn connection_failed(cause: &Option<String>) -> Error {
+    Error::Other(format!("connection failed: {}", cause.as_deref().unwrap_or("unknown")))
+}
+
+/// A handle to a `Client` shared by many tasks.
+///
+/// Requests from all handles are queued and sent over the single connection
+/// as pipelined batches.
+#[derive(Clone, Debug)]
+pub struct BufferedClient {
+    tx: Sender<Message>,
+}
+
+impl BufferedClient {
+    /// Create a new client request buffer
+    ///
+    /// The `Client` performs Redis commands directly on the TCP connection. Only a
+    /// single request may be in-flight at a given time and operations require
+    /// mutable access to the `Client` handle. This prevents using a single Redis
+    /// connection from multiple Tokio tasks.
+    ///
+    /// The strategy for dealing with this class of problem is to spawn a dedicated
+    /// Tokio task to manage the Redis connection and using "message passing" to
+    /// operate on the connection. Commands are pushed into a channel. The
+    /// connection task pops commands off of the channel and applies them to the
+    /// Redis connection. When the response is received, it is forwarded to the
+    /// original requester.
+    ///
+    /// The returned `BufferedClient` handle may be cloned before passing the new handle to
+    /// separate tasks.
+    pub fn buffer(client: Client) -> BufferedClient {
+        let (tx, rx) = channel(QUEUE_CAPACITY);
+
+        // Spawn a task to process requests for the connection.
+        tokio::spawn(async move { run(client, rx).await });
+
+        // Return the `BufferedClient` handle.
+        BufferedClient { tx }
+    }
+
+    /// Ping the server.
+    ///
+    /// Same as `Client::ping` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn ping(&self, msg: Option<Bytes>) -> Result<Bytes> {
+        match self.request(Ping::new(msg).into_frame()).await? {
+            Frame::Simple(value) => Ok(value.into()),
+            Frame::Bulk(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the value of a key.
+    ///
+    /// Same as `Client::get` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn get(&self, key: Bytes) -> Result<Option<Bytes>> {
+        match self.request(Get::new(key).into_frame()).await? {
+            Frame::Simple(value) => Ok(Some(value.into())),
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to hold the given `value`.
+    ///
+    /// Same as `Client::set` but requests are **buffered** until the
+    /// associated connection has the ability to send the request.
+    pub async fn set(&self, key: Bytes, value: Bytes) -> Result<()> {
+        self.set_cmd(Set::new(key, value, None)).await
+    }
+
+    /// Set `key` to hold the given `value`, expiring after `expiration`.
+    pub async fn set_expires(&self, key: Bytes, value: Bytes, expiration: Duration) -> Result<()> {
+        self.set_cmd(Set::new(key, value, Some(expiration))).await
+    }
+
+    /// Remove `keys` and return how many of them existed.
+    pub async fn del(&self, keys: Vec<Bytes>) -> Result<u64> {
+        match self.request(Del::new(keys).into_frame()).await? {
+            Frame::Integer(count) if count >= 0 => Ok(count as u64),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Add `delta` to the integer stored at `key` and return the new value.
+    pub async fn incr_by(&self, key: Bytes, delta: i64) -> Result<i64> {
+        match self.request(Incr::new(key, delta).into_frame()).await? {
+            Frame::Integer(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    async fn set_cmd(&self, cmd: Set) -> Result<()> {
+        match self.request(cmd.into_frame()).await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Queue `frame` on the connection and wait for its response.
+    ///
+    /// Error replies are converted to `Err`.
+    async fn request(&self, frame: Frame) -> Result<Frame> {
+        // Initialize a new oneshot to be used to receive the response back from the connection.
+        let (tx, rx) = oneshot::channel();
+
+        // Send the request
+        self.tx
+            .send((frame, tx))
+            .await
+            .map_err(|_| Error::Other("connection task has stopped".into()))?;
+
+        // Await the response
+        match rx.await {
+            Ok(Ok(Frame::Error(msg))) => Err(msg.into()),
+            Ok(res) => res,
+            Err(_) => Err(Error::Other("connection task has stopped".into())),
+        }
+    }
+}
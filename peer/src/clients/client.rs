@@ -2,12 +2,13 @@
 //!
 //! Provides an async connect and methods for issuing the supported commands.
 
-use crate::cmd::{Get, Ping, Set, Peer};
+use crate::cmd::{Auth, Cas, ClientKill, ClientList, ConfigGet, DbSize, Del, Exists, Get, GetVer, Hello, Incr, Info, MGet, MSet, Ping, Publish, Select, Set, SlowLogEntry, SlowLogGet, SlowLogLen, SlowLogReset, Subscribe, Unsubscribe, Peer};
 use crate::error::Error;
-use crate::{frame, Connection, Frame};
+use crate::{frame, Connection, Frame, TlsOptions};
 
 use async_stream::try_stream;
 use bytes::Bytes;
+use std::convert::TryFrom;
 use std::io::ErrorKind;
 use std::time::Duration;
 use tokio::net::{TcpStream, ToSocketAddrs};
@@ -23,8 +24,29 @@ use tracing::{debug, instrument};
 /// Requests are issued using the various methods of `Client`.
 pub struct Client {
     connection: Connection,
+
+    /// How long to wait for the response to a request.
+    timeout: Option<Duration>,
+
+    /// Set once a request failed in a way that leaves the connection unusable,
+    /// for example a timed out response that may still arrive.
+    broken: bool,
+
+    /// Number of requests written whose response has not been read yet. A
+    /// request abandoned midway, for example by dropping its future, leaves
+    /// its response on the connection.
+    in_flight: usize,
+
+    /// Set once the connection selected a database, switched protocol or
+    /// subscribed, so it no longer behaves like a freshly opened one.
+    session_changed: bool,
 }
 
+/// A client that has entered pub/sub mode.
+///
+/// Once clients subscribe to a channel, they may only perform pub/sub related
+/// commands. The `Client` type is transitioned to a `Subscriber` type in order
+/// to prevent non-pub/sub methods from being called.
 pub struct Subscriber {
     /// The subscribed client.
     client: Client,
@@ -68,14 +90,55 @@ impl Client {
 
         let connection = Connection::new(socket);
 
-        Ok(Client { connection })
+        Ok(Client {
+            connection,
+            timeout: None,
+            broken: false,
+            in_flight: 0,
+            session_changed: false,
+        })
+    }
+
+    /// Establish a TLS connection with the server located at `addr`, given as
+    /// `host:port`.
+    ///
+    /// The server certificate must be signed by one of the CA certificates in
+    /// `tls` and be valid for `tls.server_name`, or for `host` if no name is
+    /// set.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use peer::clients::Client;
+    /// use peer::TlsOptions;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let tls = TlsOptions::new("ca.pem");
+    ///     let client = match Client::connect_tls("localhost:6379", &tls).await {
+    ///         Ok(client) => client,
+    ///         Err(_) => panic!("failed to establish connection"),
+    ///     };
+    /// # drop(client);
+    /// }
+    /// ```
+    pub async fn connect_tls(addr: &str, tls: &TlsOptions) -> crate::Result<Client> {
+        let stream = tls.connect(addr).await?;
+
+        Ok(Client {
+            connection: Connection::with_stream(stream),
+            timeout: None,
+            broken: false,
+            in_flight: 0,
+            session_changed: false,
+        })
     }
 
     #[instrument(skip(self))]
     pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
         let frame = Ping::new(msg).into_frame();
         debug!(request = ?frame);
-        self.connection.write_frame(&frame).await?;
+        self.write_frame(&frame).await?;
 
         match self.read_response().await? {
             Frame::Simple(value) => Ok(value.into()),
@@ -84,6 +147,160 @@ impl Client {
         }
     }
 
+    /// Fetch server statistics, optionally restricted to one `section`.
+    #[instrument(skip(self))]
+    pub async fn info(&mut self, section: Option<String>) -> crate::Result<Bytes> {
+        let frame = Info::new(section).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(value) => Ok(value.into()),
+            Frame::Bulk(value) | Frame::Verbatim(_, value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Switch the connection to protocol `protover`, 2 or 3, and return the
+    /// server description as field-value pairs.
+    ///
+    /// After `hello(Some(3))` replies may use the RESP3 frame types.
+    #[instrument(skip(self))]
+    pub async fn hello(&mut self, protover: Option<u64>) -> crate::Result<Vec<(String, Frame)>> {
+        let frame = Hello::new(protover).into_frame();
+        debug!(request = ?frame);
+        if protover.is_some() {
+            self.session_changed = true;
+        }
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Map(entries) => Ok(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect()),
+            // RESP2 sends the map as a flat array of fields and values.
+            Frame::Array(items) if items.len() % 2 == 0 => Ok(items
+                .chunks(2)
+                .map(|pair| (pair[0].to_string(), pair[1].clone()))
+                .collect()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Authenticate the connection as `username`, or as the default user
+    /// configured with `requirepass` if no name is given.
+    #[instrument(skip(self, password))]
+    pub async fn auth(&mut self, username: Option<&str>, password: &str) -> crate::Result<()> {
+        // The request is not logged, it holds the password.
+        let frame = Auth::new(username.map(str::to_string), password).into_frame();
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Switch the connection to the database `db`, given by index or
+    /// namespace name. Later commands on this connection use that database.
+    #[instrument(skip(self))]
+    pub async fn select(&mut self, db: &str) -> crate::Result<()> {
+        let frame = Select::new(db).into_frame();
+        debug!(request = ?frame);
+        self.session_changed = true;
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Return the number of keys in the selected database.
+    #[instrument(skip(self))]
+    pub async fn dbsize(&mut self) -> crate::Result<u64> {
+        self.integer_cmd(DbSize::new().into_frame()).await
+    }
+
+    /// Return the configuration parameters whose name matches the glob
+    /// `pattern`, as name-value pairs.
+    #[instrument(skip(self))]
+    pub async fn config_get(&mut self, pattern: &str) -> crate::Result<Vec<(String, String)>> {
+        let frame = ConfigGet::new(vec![pattern.to_string()]).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Map(entries) => Ok(entries
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()),
+            // RESP2 sends the map as a flat array of names and values.
+            Frame::Array(items) if items.len() % 2 == 0 => Ok(items
+                .chunks(2)
+                .map(|pair| (pair[0].to_string(), pair[1].to_string()))
+                .collect()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Return the connections to the server, one line per connection.
+    #[instrument(skip(self))]
+    pub async fn client_list(&mut self) -> crate::Result<String> {
+        let frame = ClientList::new().into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Bulk(value) | Frame::Verbatim(_, value) => Ok(String::from_utf8_lossy(&value).into_owned()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Close the connection from `addr`, given as `ip:port`.
+    #[instrument(skip(self))]
+    pub async fn client_kill(&mut self, addr: &str) -> crate::Result<()> {
+        let frame = ClientKill::addr(addr).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Return up to `count` entries of the slow log, newest first, or every
+    /// entry if `count` is `None`.
+    #[instrument(skip(self))]
+    pub async fn slowlog_get(&mut self, count: Option<usize>) -> crate::Result<Vec<SlowLogEntry>> {
+        let frame = SlowLogGet::new(count).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(entries) => entries.into_iter().map(SlowLogEntry::try_from).collect(),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Return the number of entries in the slow log.
+    #[instrument(skip(self))]
+    pub async fn slowlog_len(&mut self) -> crate::Result<u64> {
+        self.integer_cmd(SlowLogLen::new().into_frame()).await
+    }
+
+    /// Remove every entry from the slow log.
+    #[instrument(skip(self))]
+    pub async fn slowlog_reset(&mut self) -> crate::Result<()> {
+        let frame = SlowLogReset::new().into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
     #[instrument(skip(self))]
     pub async fn get(&mut self, key: Bytes) -> crate::Result<Option<Bytes>> {
         // Create a `Get` command for the `key` and convert it to a frame.
@@ -91,7 +308,7 @@ impl Client {
 
         debug!(request = ?frame);
 
-        self.connection.write_frame(&frame).await?;
+        self.write_frame(&frame).await?;
 
         match self.read_response().await? {
             Frame::Simple(value) => Ok(Some(value.into())),
@@ -116,19 +333,148 @@ impl Client {
         self.set_cmd(Set::new(key, value, Some(expiration))).await
     }
 
+    /// Remove `keys` and return how many of them existed.
+    #[instrument(skip(self))]
+    pub async fn del(&mut self, keys: Vec<Bytes>) -> crate::Result<u64> {
+        self.integer_cmd(Del::new(keys).into_frame()).await
+    }
+
+    /// Return how many of `keys` exist.
+    #[instrument(skip(self))]
+    pub async fn exists(&mut self, keys: Vec<Bytes>) -> crate::Result<u64> {
+        self.integer_cmd(Exists::new(keys).into_frame()).await
+    }
+
+    /// Get the values of `keys`, `None` for keys that do not exist.
+    #[instrument(skip(self))]
+    pub async fn mget(&mut self, keys: Vec<Bytes>) -> crate::Result<Vec<Option<Bytes>>> {
+        let frame = MGet::new(keys).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(values) => values
+                .into_iter()
+                .map(|value| match value {
+                    Frame::Bulk(value) => Ok(Some(value)),
+                    Frame::Null => Ok(None),
+                    frame => Err(frame.to_error()),
+                })
+                .collect(),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set every key-value pair of `pairs`.
+    #[instrument(skip(self))]
+    pub async fn mset(&mut self, pairs: Vec<(Bytes, Bytes)>) -> crate::Result<()> {
+        let frame = MSet::new(pairs).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Simple(response) if response == "OK" => Ok(()),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to `value` only if it does not exist yet.
+    ///
+    /// Returns `false` if the key already existed.
+    #[instrument(skip(self))]
+    pub async fn set_nx(&mut self, key: Bytes, value: Bytes) -> crate::Result<bool> {
+        match self.send_set(Set::new(key, value, None).if_absent()).await? {
+            Frame::Simple(response) if response == "OK" => Ok(true),
+            Frame::Null => Ok(false),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to `value` only if it already exists.
+    ///
+    /// Returns `false` if the key did not exist.
+    #[instrument(skip(self))]
+    pub async fn set_xx(&mut self, key: Bytes, value: Bytes) -> crate::Result<bool> {
+        match self.send_set(Set::new(key, value, None).if_present()).await? {
+            Frame::Simple(response) if response == "OK" => Ok(true),
+            Frame::Null => Ok(false),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Set `key` to `value` and return the previous value.
+    #[instrument(skip(self))]
+    pub async fn set_get(&mut self, key: Bytes, value: Bytes) -> crate::Result<Option<Bytes>> {
+        match self.send_set(Set::new(key, value, None).with_get()).await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Get the value of `key` together with its version.
+    #[instrument(skip(self))]
+    pub async fn get_versioned(&mut self, key: Bytes) -> crate::Result<Option<(Bytes, u64)>> {
+        let frame = GetVer::new(key).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Array(parts) => match &parts[..] {
+                [Frame::Bulk(value), Frame::Integer(version)] => Ok(Some((value.clone(), *version as u64))),
+                _ => Err(Frame::Array(parts).to_error()),
+            },
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Replace `key` with `value` if it still has `version`.
+    ///
+    /// Returns the new version, or `None` if the key was changed in the
+    /// meantime.
+    #[instrument(skip(self))]
+    pub async fn cas(&mut self, key: Bytes, version: u64, value: Bytes) -> crate::Result<Option<u64>> {
+        let frame = Cas::new(key, version, value, None).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(version) => Ok(Some(version as u64)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Add `delta` to the integer stored at `key` and return the new value.
+    ///
+    /// A missing key is treated as 0. `INCR`, `DECR` and `DECRBY` are all
+    /// sent as `INCRBY`.
+    #[instrument(skip(self))]
+    pub async fn incr_by(&mut self, key: Bytes, delta: i64) -> crate::Result<i64> {
+        let frame = Incr::new(key, delta).into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(value) => Ok(value),
+            frame => Err(frame.to_error()),
+        }
+    }
+
     pub async fn peer_basic(&mut self) -> crate::Result<Option<Bytes>> {
 
         let frame = Peer::new("basic").into_frame();
 
         debug!(request = ?frame);
 
-        self.connection.write_frame(&frame).await?;
+        self.write_frame(&frame).await?;
 
         match self.read_response().await? {
             Frame::Simple(value) => Ok(Some(value.into())),
             Frame::Bulk(value) => Ok(Some(value)),
             Frame::Null => Ok(None),
-            Frame::Array(frames) => {
+            Frame::Array(frames) | Frame::Set(frames) => {
                 let strings: Vec<String> = frames.iter()
                     .map(|frame| frame.to_string())
                     .collect();
@@ -147,7 +493,7 @@ impl Client {
 
         // Write the frame to the socket. This writes the full frame to the
         // socket, waiting if necessary.
-        self.connection.write_frame(&frame).await?;
+        self.write_frame(&frame).await?;
 
         // Wait for the response from the server. On success, the server
         // responds simply with `OK`. Any other response indicates an error.
@@ -157,19 +503,169 @@ impl Client {
         }
     }
 
+    /// Posts `message` to the given `channel`.
+    ///
+    /// Returns the number of subscribers currently listening on the channel
+    /// of the node the client is connected to. Subscribers connected to other
+    /// nodes through the pub/sub bridge are not counted.
+    #[instrument(skip(self))]
+    pub async fn publish(&mut self, channel: &str, message: Bytes) -> crate::Result<u64> {
+        self.integer_cmd(Publish::new(channel, message).into_frame()).await
+    }
+
+    /// Subscribes the client to the specified channels.
+    ///
+    /// Once a client issues a subscribe command, it may no longer issue any
+    /// non-pub/sub commands. The function consumes `self` and returns a
+    /// `Subscriber`.
+    #[instrument(skip(self))]
+    pub async fn subscribe(mut self, channels: Vec<String>) -> crate::Result<Subscriber> {
+        // Issue the subscribe command to the server and wait for confirmation.
+        // The client will then have been transitioned into the "subscriber"
+        // state and may only issue pub/sub commands from that point on.
+        self.subscribe_cmd(&channels).await?;
+
+        Ok(Subscriber {
+            client: self,
+            subscribed_channels: channels,
+        })
+    }
+
+    /// The core `SUBSCRIBE` logic, used by misc subscribe fns
+    async fn subscribe_cmd(&mut self, channels: &[String]) -> crate::Result<()> {
+        let frame = Subscribe::new(channels.to_vec()).into_frame();
+        debug!(request = ?frame);
+        self.session_changed = true;
+        self.write_frame(&frame).await?;
+
+        // For each channel being subscribed to, the server responds with a
+        // message confirming subscription to that channel.
+        for channel in channels {
+            let response = self.read_response().await?;
+
+            match response {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                    // The server responds with an array frame in the form of:
+                    //
+                    // ```
+                    // [ "subscribe", channel, num-subscribed ]
+                    // ```
+                    [subscribe, schannel, ..] if *subscribe == "subscribe" && *schannel == channel.as_str() => {}
+                    _ => return Err(response.to_error()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Sends a command answered with a non-negative integer.
+    async fn integer_cmd(&mut self, frame: Frame) -> crate::Result<u64> {
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+
+        match self.read_response().await? {
+            Frame::Integer(count) if count >= 0 => Ok(count as u64),
+            frame => Err(frame.to_error()),
+        }
+    }
+
+    /// Sends a `SET` command with options and returns the raw response.
+    async fn send_set(&mut self, cmd: Set) -> crate::Result<Frame> {
+        let frame = cmd.into_frame();
+        debug!(request = ?frame);
+        self.write_frame(&frame).await?;
+        self.read_response().await
+    }
+
+    /// Set how long to wait for the response to each request. `None`, the
+    /// default, waits forever.
+    ///
+    /// A request that times out fails with `Error::Timeout` and leaves the
+    /// connection unusable, since its response may still arrive later.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns true once a request failed on the connection itself, rather
+    /// than with an error reply, so the client must not be reused.
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Returns true if the connection may be handed to another user: it is
+    /// not broken, every response has been read and its database, protocol
+    /// and subscriptions are those of a new connection.
+    pub(crate) fn is_reusable(&self) -> bool {
+        !self.broken && self.in_flight == 0 && !self.session_changed
+    }
+
+    /// Writes a request frame without waiting for its response. The frame is
+    /// sent by the next read.
+    pub(crate) async fn write_request(&mut self, frame: &Frame) -> crate::Result<()> {
+        debug!(request = ?frame);
+        self.write_frame(frame).await
+    }
+
+    /// Reads the next response frame, including `Error` frames.
+    pub(crate) async fn read_reply(&mut self) -> crate::Result<Frame> {
+        self.receive().await
+    }
+
     /// Reads a response frame from the socket.
     ///
     /// If an `Error` frame is received, it is converted to `Err`.
     async fn read_response(&mut self) -> crate::Result<Frame> {
-        let response = self.connection.read_frame().await?;
+        let response = self.receive().await?;
 
         debug!(?response);
 
         match response {
             // Error frames are converted to `Err`
-            Some(Frame::Error(msg)) => Err(msg.into()),
-            Some(frame) => Ok(frame),
-            None => Err(Error::InvalidFrameType("connection reset by server".to_string()))
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame),
+        }
+    }
+
+    /// Buffers a request frame, marking the client broken if the connection
+    /// fails. The request counts as in flight until a response is read.
+    async fn write_frame(&mut self, frame: &Frame) -> crate::Result<()> {
+        self.in_flight += 1;
+        if let Err(err) = self.connection.write_frame(frame).await {
+            self.broken = true;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Reads the next frame within the request timeout, marking the client
+    /// broken if the connection fails.
+    async fn receive(&mut self) -> crate::Result<Frame> {
+        let read = self.connection.read_frame();
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, read).await {
+                Ok(result) => result,
+                Err(_) => Err(Error::Timeout("response".to_string())),
+            },
+            None => read.await,
+        };
+
+        match result {
+            Ok(Some(frame)) => {
+                // Subscribe replies and published messages arrive without a
+                // request of their own.
+                self.in_flight = self.in_flight.saturating_sub(1);
+                Ok(frame)
+            }
+            Ok(None) => {
+                self.broken = true;
+                Err(Error::InvalidFrameType("connection reset by server".to_string()))
+            }
+            Err(err) => {
+                self.broken = true;
+                Err(err)
+            }
         }
     }
 }
@@ -180,6 +676,66 @@ impl Subscriber {
         &self.subscribed_channels
     }
 
+    /// Subscribe to a list of new channels
+    #[instrument(skip(self))]
+    pub async fn subscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        self.client.subscribe_cmd(channels).await?;
+
+        self.subscribed_channels.extend(channels.iter().cloned());
+
+        Ok(())
+    }
+
+    /// Unsubscribe from a list of channels, or from all channels if `channels`
+    /// is empty.
+    #[instrument(skip(self))]
+    pub async fn unsubscribe(&mut self, channels: &[String]) -> crate::Result<()> {
+        let frame = Unsubscribe::new(channels).into_frame();
+        debug!(request = ?frame);
+        self.client.write_frame(&frame).await?;
+
+        // If the input channel list is empty, the server acknowledges
+        // unsubscribing from all subscribed channels, so we assert that the
+        // unsubscribe list received matches the client subscribed one.
+        let num = if channels.is_empty() {
+            self.subscribed_channels.len()
+        } else {
+            channels.len()
+        };
+
+        // Read the response
+        for _ in 0..num {
+            let response = self.client.read_response().await?;
+
+            match response {
+                Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
+                    [unsubscribe, channel, ..] if *unsubscribe == "unsubscribe" => {
+                        let len = self.subscribed_channels.len();
+
+                        if len == 0 {
+                            // There must be at least one channel
+                            return Err(response.to_error());
+                        }
+
+                        // unsubscribed channel should exist in the subscribed
+                        // list at this point
+                        self.subscribed_channels.retain(|c| *channel != &c[..]);
+
+                        // Only a single channel should be removed from the
+                        // list of subscribed channels.
+                        if self.subscribed_channels.len() != len - 1 {
+                            return Err(response.to_error());
+                        }
+                    }
+                    _ => return Err(response.to_error()),
+                },
+                frame => return Err(frame.to_error()),
+            };
+        }
+
+        Ok(())
+    }
+
     /// Receive the next message published on a subscribed channel, waiting if
     /// necessary.
     ///
@@ -190,7 +746,7 @@ impl Subscriber {
                 debug!(?mframe);
 
                 match mframe {
-                    Frame::Array(ref frame) => match frame.as_slice() {
+                    Frame::Array(ref frame) | Frame::Push(ref frame) => match frame.as_slice() {
                         [message, channel, content] if *message == "message" => Ok(Some(Message {
                             channel: channel.to_string(),
                             content: Bytes::from(content.to_string()),
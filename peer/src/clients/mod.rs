@@ -5,4 +5,7 @@ mod blocking_client;
 pub use blocking_client::BlockingClient;
 
 mod buffered_client;
-// pub use buffered_client::BufferedClient;
+pub use buffered_client::BufferedClient;
+
+mod pool;
+pub use pool::{Pool, PoolConfig, PooledClient};
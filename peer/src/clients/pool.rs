@@ -0,0 +1,340 @@
+use crate::clients::Client;
+use crate::{Error, TlsOptions};
+
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time;
+use tracing::debug;
+
+/// Settings of a `Pool`.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Maximum number of connections, idle or in use.
+    pub max_size: usize,
+    /// How long `Pool::get` waits for a connection to become free.
+    pub wait_timeout: Duration,
+    /// Idle connections unused for longer are closed.
+    pub idle_timeout: Duration,
+    /// Idle connections unused for longer are checked with `PING` before
+    /// they are handed out again.
+    pub health_check_interval: Duration,
+    /// How long a single connection attempt may take.
+    pub connect_timeout: Duration,
+    /// Number of further attempts after a failed connection attempt.
+    pub connect_retries: u32,
+    /// Delay before the first retry, doubled after every failed attempt.
+    pub backoff: Duration,
+    /// Upper bound of the delay between attempts.
+    pub max_backoff: Duration,
+    /// How long to wait for the response to each request. `None` waits
+    /// forever.
+    pub request_timeout: Option<Duration>,
+    /// User new connections authenticate as, the default user if not set.
+    pub username: Option<String>,
+    /// Password new connections authenticate with. `None` skips `AUTH`.
+    pub password: Option<String>,
+    /// Connect with TLS using these settings. `None` uses plain TCP.
+    pub tls: Option<TlsOptions>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 16,
+            wait_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(300),
+            health_check_interval: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            connect_retries: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            request_timeout: Some(Duration::from_secs(5)),
+            username: None,
+            password: None,
+            tls: None,
+        }
+    }
+}
+
+/// A pool of `Client` connections to one server.
+///
+/// Connections are opened on demand, up to `max_size`, and reused once the
+/// `PooledClient` holding them is dropped. A connection that failed or timed
+/// out is closed instead of being reused, so callers only see the failure of
+/// their own request; the next `get` reconnects.
+///
+/// A connection dropped while a request is still waiting for its response,
+/// for example because the future running it was cancelled, is closed as
+/// well, so its late response cannot be taken for the reply to the next
+/// user's request. So is a connection whose database, protocol or pub/sub
+/// mode was changed with `select`, `hello` or `subscribe`: every connection
+/// handed out behaves like a freshly opened one.
+///
+/// The `Pool` handle may be cloned before passing it to separate tasks.
+#[derive(Clone)]
+pub struct Pool {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    addr: String,
+
+    config: PoolConfig,
+
+    /// Connections that are not in use, the most recently used last.
+    idle: Mutex<VecDeque<IdleClient>>,
+
+    /// Limits the number of connections, one permit per open connection.
+    permits: Arc<Semaphore>,
+}
+
+struct IdleClient {
+    client: Client,
+
+    /// When the connection was returned to the pool.
+    since: Instant,
+}
+
+/// A connection checked out of a `Pool`.
+///
+/// Dereferences to `Client`. Dropping it returns the connection to the pool.
+pub struct PooledClient {
+    client: Option<Client>,
+
+    shared: Arc<Shared>,
+
+    // Released after the client went back to the pool, see `Drop`.
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Pool {
+    /// Create a pool of connections to the server at `addr`.
+    ///
+    /// No connection is opened until the first call to `get`.
+    pub fn new(addr: impl Into<String>, config: PoolConfig) -> Pool {
+        Pool {
+            shared: Arc::new(Shared {
+                addr: addr.into(),
+                permits: Arc::new(Semaphore::new(config.max_size)),
+                config,
+                idle: Mutex::new(VecDeque::new()),
+            }),
+        }
+    }
+
+    /// Check out a connection, opening a new one if none is idle.
+    ///
+    /// Fails with `Error::Timeout` if all `max_size` connections stay in use
+    /// for `wait_timeout`, or with the connection error once every connection
+    /// attempt failed.
+    pub async fn get(&self) -> crate::Result<PooledClient> {
+        let permits = self.shared.permits.clone();
+        let permit = match time::timeout(self.shared.config.wait_timeout, permits.acquire_owned()).await {
+            Ok(permit) => permit.expect("pool semaphore is never closed"),
+            Err(_) => return Err(Error::Timeout("a free connection".to_string())),
+        };
+
+        let client = match self.take_idle().await {
+            Some(client) => client,
+            None => self.connect().await?,
+        };
+
+        Ok(PooledClient {
+            client: Some(client),
+            shared: self.shared.clone(),
+            _permit: permit,
+        })
+    }
+
+    /// Returns the number of idle connections.
+    pub fn idle(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    /// Pops the most recently used idle connection that is still usable.
+    async fn take_idle(&self) -> Option<Client> {
+        let config = &self.shared.config;
+
+        loop {
+            let idle = self.shared.idle.lock().unwrap().pop_back()?;
+            let idle_for = idle.since.elapsed();
+            if idle_for >= config.idle_timeout {
+                continue;
+            }
+
+            let mut client = idle.client;
+            if idle_for >= config.health_check_interval && client.ping(None).await.is_err() {
+                debug!(addr = %self.shared.addr, "dropping connection that failed the health check");
+                continue;
+            }
+            return Some(client);
+        }
+    }
+
+    /// Opens and authenticates a new connection, retrying with exponential
+    /// backoff.
+    async fn connect(&self) -> crate::Result<Client> {
+        let config = &self.shared.config;
+        let mut backoff = config.backoff;
+        let mut attempt = 0;
+
+        loop {
+            let connect = async {
+                match &config.tls {
+                    Some(tls) => Client::connect_tls(&self.shared.addr, tls).await,
+                    None => Client::connect(self.shared.addr.as_str()).await,
+                }
+            };
+
+            let result = match time::timeout(config.connect_timeout, connect).await {
+                Ok(result) => result,
+                Err(_) => Err(Error::Timeout("connect".to_string())),
+            };
+
+            match result {
+                Ok(mut client) => {
+                    client.set_timeout(config.request_timeout);
+                    // Rejected credentials do not get better with retrying.
+                    if let Some(password) = &config.password {
+                        client.auth(config.username.as_deref(), password).await?;
+                    }
+                    return Ok(client);
+                }
+                Err(err) if attempt >= config.connect_retries => return Err(err),
+                Err(err) => debug!(addr = %self.shared.addr, cause = %err, attempt, "failed to connect, retrying"),
+            }
+
+            // Pause execution until the back off period elapses.
+            time::sleep(backoff).await;
+
+            // Double the back off
+            backoff = (backoff * 2).min(config.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+impl Shared {
+    /// Returns a connection to the pool and closes the expired ones.
+    fn put(&self, client: Client) {
+        let mut idle = self.idle.lock().unwrap();
+        while idle.front().is_some_and(|oldest| oldest.since.elapsed() >= self.config.idle_timeout) {
+            idle.pop_front();
+        }
+
+        idle.push_back(IdleClient {
+            client,
+            since: Instant::now(),
+        });
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("client is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("client is only taken on drop")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        // The permit is released after this returns, so a task waiting in
+        // `get` finds the connection idle.
+        if let Some(client) = self.client.take() {
+            if client.is_reusable() {
+                self.shared.put(client);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, Frame};
+    use bytes::Bytes;
+    use tokio::net::TcpListener;
+
+    /// Starts a server that replies `OK` to every request except `GET`, which
+    /// is never answered. Returns its address.
+    async fn server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut connection = Connection::new(socket);
+                    while let Ok(Some(frame)) = connection.read_frame().await {
+                        let is_get = matches!(&frame, Frame::Array(args) if args[0] == "get");
+                        if !is_get {
+                            connection.write_frame(&Frame::Simple("OK".to_string())).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    fn pool(addr: String) -> Pool {
+        let config = PoolConfig {
+            request_timeout: None,
+            ..Default::default()
+        };
+        Pool::new(addr, config)
+    }
+
+    #[tokio::test]
+    async fn reuses_clean_connections() {
+        let pool = pool(server().await);
+
+        let mut client = pool.get().await.unwrap();
+        client.ping(None).await.unwrap();
+        drop(client);
+        assert_eq!(pool.idle(), 1);
+
+        let mut client = pool.get().await.unwrap();
+        client.set(Bytes::from("key"), Bytes::from("value")).await.unwrap();
+        drop(client);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[tokio::test]
+    async fn discards_cancelled_requests() {
+        let pool = pool(server().await);
+
+        let mut client = pool.get().await.unwrap();
+        let get = client.get(Bytes::from("key"));
+        assert!(time::timeout(Duration::from_millis(50), get).await.is_err());
+        assert!(!client.is_broken());
+        drop(client);
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[tokio::test]
+    async fn discards_changed_sessions() {
+        let pool = pool(server().await);
+
+        let mut client = pool.get().await.unwrap();
+        client.select("1").await.unwrap();
+        drop(client);
+        assert_eq!(pool.idle(), 0);
+
+        let mut client = pool.get().await.unwrap();
+        client.ping(None).await.unwrap();
+        drop(client);
+        assert_eq!(pool.idle(), 1);
+    }
+}
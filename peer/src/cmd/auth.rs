@@ -0,0 +1,82 @@
+use crate::{error::Error, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use std::fmt;
+use tracing::{debug, instrument};
+
+/// Authenticates the connection.
+///
+/// `AUTH password` authenticates as the default user configured with
+/// `requirepass`, `AUTH username password` as one of the configured users.
+/// Later commands on the connection are checked against the rules of that
+/// user.
+pub struct Auth {
+    /// the user to authenticate as, the default user if not set
+    username: Option<String>,
+
+    /// the password of the user
+    password: String,
+}
+
+impl Auth {
+    /// Create a new `Auth` command authenticating as `username` with
+    /// `password`.
+    pub fn new(username: Option<String>, password: impl ToString) -> Auth {
+        Auth {
+            username,
+            password: password.to_string(),
+        }
+    }
+
+    /// Parse an `Auth` instance from a received frame.
+    ///
+    /// The `AUTH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// AUTH [username] password
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Auth> {
+        let first = parse.next_string()?;
+
+        match parse.next_string() {
+            Ok(password) => Ok(Auth::new(Some(first), password)),
+            Err(Error::EndOfStream) => Ok(Auth::new(None, first)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Apply the `Auth` command to the connection `session`.
+    #[instrument(skip(self, session, dst))]
+    pub(crate) async fn apply(self, session: &mut Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match session.authenticate(self.username.as_deref(), &self.password) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(msg) => Frame::Error(msg),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("auth".as_bytes()));
+        if let Some(username) = self.username {
+            frame.push_bulk(Bytes::from(username.into_bytes()));
+        }
+        frame.push_bulk(Bytes::from(self.password.into_bytes()));
+        frame
+    }
+}
+
+// Commands are logged when received, so the password is left out.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Auth").field("username", &self.username).finish()
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+use crate::error::Error;
+
+use bytes::Bytes;
+use mineral::kv::hash::{SetCond, SetOptions};
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+/// Compare-and-swap: set `key` to `value` only if its current version matches.
+///
+/// `CAS key version value [EX seconds|PX milliseconds]`. Versions are read
+/// with `GETVER`; version 0 matches a key that does not exist. The reply is
+/// the new version on success, or `nil` if the key was changed concurrently.
+#[derive(Debug)]
+pub struct Cas {
+    /// the lookup key
+    key: Bytes,
+
+    /// version the key must still have
+    version: u64,
+
+    /// the value to be stored
+    value: Bytes,
+
+    /// When to expire the key
+    expire: Option<Duration>,
+}
+
+impl Cas {
+    /// Create a new `Cas` command which replaces `key` at `version` with
+    /// `value`.
+    pub fn new(key: Bytes, version: u64, value: Bytes, expire: Option<Duration>) -> Cas {
+        Cas {
+            key,
+            version,
+            value,
+            expire,
+        }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Cas> {
+        let key = parse.next_bytes()?;
+        let version = parse.next_int()?;
+        let value = parse.next_bytes()?;
+
+        let expire = match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "EX" => Some(Duration::from_secs(parse.next_int()?)),
+            Ok(s) if s.to_uppercase() == "PX" => Some(Duration::from_millis(parse.next_int()?)),
+            Ok(_) => return Err(Error::Other("ERR syntax error".into())),
+            Err(Error::EndOfStream) => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Cas::new(key, version, value, expire))
+    }
+
+    /// Apply the `Cas` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let opts = SetOptions {
+            expire: self.expire,
+            cond: SetCond::IfVersion(self.version),
+            get: false,
+        };
+
+        let response = match node.set(session.db(), self.key, self.value, opts).await {
+            Ok(outcome) if outcome.written => Frame::Integer(outcome.version as i64),
+            Ok(_) => Frame::Null,
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("cas".as_bytes()));
+        frame.push_bulk(self.key);
+        frame.push_bulk(Bytes::from(self.version.to_string()));
+        frame.push_bulk(self.value);
+        if let Some(ms) = self.expire {
+            frame.push_bulk(Bytes::from("px".as_bytes()));
+            frame.push_int(ms.as_millis() as i64);
+        }
+        frame
+    }
+}
@@ -0,0 +1,197 @@
+use crate::node::{KillFilter, Node};
+use crate::{error::Error, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Lists the client connections.
+///
+/// The reply is a bulk string with one line per connection, oldest first, made
+/// of `field=value` pairs: the connection `id`, the client `addr`, its `age`
+/// and `idle` time in seconds, the selected `db`, the authenticated `user`
+/// and the last command run, `cmd`.
+#[derive(Debug, Default)]
+pub struct ClientList;
+
+/// Closes client connections.
+///
+/// `CLIENT KILL addr` closes the connection from `addr` and replies `OK`, or
+/// an error if there is none. With filters, every connection matching all of
+/// them is closed and the number of connections is returned:
+///
+/// ```text
+/// CLIENT KILL [ID id] [ADDR addr] [USER username] [SKIPME yes|no]
+/// ```
+///
+/// `SKIPME` defaults to `yes`, so the calling connection is only closed when
+/// it is given as `no`.
+#[derive(Debug, Default)]
+pub struct ClientKill {
+    /// `true` for the `CLIENT KILL addr` form, which replies `OK`.
+    legacy: bool,
+
+    id: Option<u64>,
+
+    addr: Option<String>,
+
+    user: Option<String>,
+
+    /// keep the calling connection open
+    skip_me: bool,
+}
+
+impl ClientList {
+    /// Create a new `ClientList` command.
+    pub fn new() -> ClientList {
+        ClientList
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<ClientList> {
+        Ok(ClientList)
+    }
+
+    /// Apply the `ClientList` command and write the connections to `dst`.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        let response = Frame::Bulk(Bytes::from(node.clients().list()));
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("client".as_bytes()));
+        frame.push_bulk(Bytes::from("list".as_bytes()));
+        frame
+    }
+}
+
+impl ClientKill {
+    /// Create a new `ClientKill` command closing the connection from `addr`.
+    pub fn addr(addr: impl ToString) -> ClientKill {
+        ClientKill {
+            legacy: true,
+            addr: Some(addr.to_string()),
+            ..ClientKill::default()
+        }
+    }
+
+    /// Create a new `ClientKill` command closing the connection `id`.
+    pub fn id(id: u64) -> ClientKill {
+        ClientKill {
+            id: Some(id),
+            skip_me: true,
+            ..ClientKill::default()
+        }
+    }
+
+    /// Parse a `ClientKill` instance from a received frame.
+    ///
+    /// The `CLIENT KILL` strings have already been consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ClientKill> {
+        let first = parse.next_string()?;
+
+        let mut filter = match parse.next_string() {
+            Err(Error::EndOfStream) => return Ok(ClientKill::addr(first)),
+            Err(err) => return Err(err),
+            Ok(value) => {
+                let mut filter = ClientKill {
+                    skip_me: true,
+                    ..ClientKill::default()
+                };
+                filter.set(&first, value)?;
+                filter
+            }
+        };
+
+        loop {
+            let name = match parse.next_string() {
+                Ok(name) => name,
+                Err(Error::EndOfStream) => break,
+                Err(err) => return Err(err),
+            };
+            filter.set(&name, parse.next_string()?)?;
+        }
+
+        Ok(filter)
+    }
+
+    fn set(&mut self, name: &str, value: String) -> crate::Result<()> {
+        match &name.to_lowercase()[..] {
+            "id" => {
+                let id = value.parse().map_err(|_| Error::Other(format!("invalid client id {:?}", value)))?;
+                self.id = Some(id);
+            }
+            "addr" => self.addr = Some(value),
+            "user" => self.user = Some(value),
+            "skipme" => match &value.to_lowercase()[..] {
+                "yes" => self.skip_me = true,
+                "no" => self.skip_me = false,
+                _ => return Err("`SKIPME` must be yes or no".into()),
+            },
+            _ => return Err(format!("unsupported `CLIENT KILL` filter {:?}", name).into()),
+        }
+        Ok(())
+    }
+
+    /// Apply the `ClientKill` command.
+    ///
+    /// The connections are closed once they finish their current command, so
+    /// a connection that kills itself still receives the reply.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let filter = KillFilter {
+            id: self.id,
+            addr: self.addr,
+            user: self.user,
+            skip: if self.skip_me { Some(session.client().id()) } else { None },
+        };
+        let killed = node.clients().kill(&filter);
+
+        let response = match (self.legacy, killed) {
+            (true, 0) => Frame::Error("ERR No such client".to_string()),
+            (true, _) => Frame::Simple("OK".to_string()),
+            (false, killed) => Frame::Integer(killed as i64),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("client".as_bytes()));
+        frame.push_bulk(Bytes::from("kill".as_bytes()));
+        if self.legacy {
+            if let Some(addr) = self.addr {
+                frame.push_bulk(Bytes::from(addr.into_bytes()));
+            }
+            return frame;
+        }
+
+        let mut push = |name: &'static str, value: String| {
+            frame.push_bulk(Bytes::from_static(name.as_bytes()));
+            frame.push_bulk(Bytes::from(value.into_bytes()));
+        };
+        if let Some(id) = self.id {
+            push("id", id.to_string());
+        }
+        if let Some(addr) = self.addr {
+            push("addr", addr);
+        }
+        if let Some(user) = self.user {
+            push("user", user);
+        }
+        push("skipme", if self.skip_me { "yes" } else { "no" }.to_string());
+        frame
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{error::Error, node::Node, utils::glob_match, Connection, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Returns the effective configuration of the server.
+///
+/// `CONFIG GET pattern [pattern ...]` replies with the parameters whose name
+/// matches any of the glob patterns, as a map of names to values. Settings of
+/// a section are named after it, e.g. `storage.block_size`. Passwords and
+/// secrets are never returned. The configuration can not be changed at
+/// runtime.
+#[derive(Debug)]
+pub struct ConfigGet {
+    /// glob patterns of the parameter names
+    patterns: Vec<String>,
+}
+
+impl ConfigGet {
+    /// Create a new `ConfigGet` command returning the parameters matching
+    /// `patterns`.
+    pub fn new(patterns: Vec<String>) -> ConfigGet {
+        ConfigGet { patterns }
+    }
+
+    /// Parse a `ConfigGet` instance from a received frame.
+    ///
+    /// The `CONFIG GET` strings have already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// CONFIG GET pattern [pattern ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<ConfigGet> {
+        // The first pattern is required, the others are optional.
+        let mut patterns = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => patterns.push(s),
+                Err(Error::EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(ConfigGet { patterns })
+    }
+
+    /// Apply the `ConfigGet` command and write the matching parameters to
+    /// `dst`.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        let mut response = Frame::Map(vec![]);
+        for (name, value) in node.config().parameters() {
+            if self.patterns.iter().any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes())) {
+                response.push_entry(&name, Frame::Bulk(Bytes::from(value)));
+            }
+        }
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("config".as_bytes()));
+        frame.push_bulk(Bytes::from("get".as_bytes()));
+        for pattern in self.patterns {
+            frame.push_bulk(Bytes::from(pattern.into_bytes()));
+        }
+        frame
+    }
+}
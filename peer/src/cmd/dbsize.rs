@@ -0,0 +1,45 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Returns the number of keys in the database selected by the connection.
+///
+/// Expired keys that have not been purged yet are not counted. Counting walks
+/// every slot on the blocking thread pool, so it is relatively expensive but
+/// does not stall other connections.
+#[derive(Debug, Default)]
+pub struct DbSize;
+
+impl DbSize {
+    /// Create a new `DbSize` command.
+    pub fn new() -> DbSize {
+        DbSize
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<DbSize> {
+        Ok(DbSize)
+    }
+
+    /// Apply the `DbSize` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.stats(session.db()).await {
+            Ok(stats) => Frame::Integer(stats.keys as i64),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("dbsize".as_bytes()));
+        frame
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Removes the given keys.
+///
+/// Keys that do not exist are ignored. The reply is the number of keys that
+/// were removed.
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<Bytes>,
+}
+
+impl Del {
+    /// Create a new `Del` command which removes `keys`.
+    pub fn new(keys: Vec<Bytes>) -> Del {
+        Del { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Del> {
+        Ok(Del { keys: parse.rest_bytes()? })
+    }
+
+    /// Apply the `Del` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.del(session.db(), &self.keys).await {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("del".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(key);
+        }
+        frame
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Counts how many of the given keys exist.
+///
+/// A key given more than once is counted each time, as in Redis.
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<Bytes>,
+}
+
+impl Exists {
+    /// Create a new `Exists` command which checks `keys`.
+    pub fn new(keys: Vec<Bytes>) -> Exists {
+        Exists { keys }
+    }
+
+    /// Get the keys
+    pub fn keys(&self) -> &[Bytes] {
+        &self.keys
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Exists> {
+        Ok(Exists { keys: parse.rest_bytes()? })
+    }
+
+    /// Apply the `Exists` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.exists(session.db(), &self.keys).await {
+            Ok(found) => Frame::Integer(found as i64),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("exists".as_bytes()));
+        for key in self.keys {
+            frame.push_bulk(key);
+        }
+        frame
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{error::Error, node::Node, Connection, Db, Frame, Parse};
+use crate::{error::Error, node::Node, Connection, Db, Frame, Parse, Session};
 
 use bytes::Bytes;
 use tracing::{debug, instrument};
@@ -27,14 +27,14 @@ impl Get {
         Ok(Get { key })
     }
 
-    #[instrument(skip(self, node, dst))]
-    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
-        // Get the value from the shared database state
-        let response = if let Some(value) = node.get(&self.key) {
-            Frame::Bulk(value)
-        } else {
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        // Get the value from the database selected by the connection
+        let response = match node.get(session.db(), &self.key).await {
+            Ok(Some(value)) => Frame::Bulk(value),
             // If there is no value, `Null` is written.
-            Frame::Null
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_resp()),
         };
 
         debug!(?response);
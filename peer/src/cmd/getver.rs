@@ -0,0 +1,60 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Get the value of a key together with its version.
+///
+/// The reply is a two element array of the value and its version, or `nil`
+/// if the key does not exist. The version is passed to `CAS`.
+#[derive(Debug)]
+pub struct GetVer {
+    key: Bytes,
+}
+
+impl GetVer {
+    /// Create a new `GetVer` command which fetches `key`.
+    pub fn new(key: Bytes) -> GetVer {
+        GetVer { key }
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<GetVer> {
+        let key = parse.next_bytes()?;
+
+        Ok(GetVer { key })
+    }
+
+    /// Apply the `GetVer` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.get_versioned(session.db(), &self.key).await {
+            Ok(Some((value, version))) => {
+                let mut frame = Frame::array();
+                frame.push_bulk(value);
+                frame.push_int(version as i64);
+                frame
+            }
+            Ok(None) => Frame::Null,
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("getver".as_bytes()));
+        frame.push_bulk(self.key);
+        frame
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{error::Error, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use std::fmt;
+use tracing::{debug, instrument};
+
+/// Switches the connection to a different protocol version and returns
+/// information about the server.
+///
+/// `HELLO 3` switches the connection to RESP3; replies written afterwards may
+/// use the RESP3 types and pub/sub messages are sent as push frames. `HELLO 2`
+/// switches back. Without a version the protocol is left unchanged.
+///
+/// The `AUTH` option authenticates the connection like the `AUTH` command
+/// before the protocol is switched.
+#[derive(Default)]
+pub struct Hello {
+    /// The requested protocol version.
+    protover: Option<u64>,
+
+    /// Username and password to authenticate with.
+    auth: Option<(String, String)>,
+}
+
+impl Hello {
+    /// Create a new `Hello` command requesting protocol `protover`.
+    pub fn new(protover: Option<u64>) -> Hello {
+        Hello { protover, auth: None }
+    }
+
+    /// Authenticate as `username` with `password` before switching the
+    /// protocol. Only sent together with a protocol version.
+    pub fn with_auth(mut self, username: impl ToString, password: impl ToString) -> Hello {
+        self.auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Parse a `Hello` instance from a received frame.
+    ///
+    /// The `HELLO` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// ```text
+    /// HELLO [protover [AUTH username password]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Hello> {
+        let hello = match parse.next_int() {
+            Ok(protover) => Hello::new(Some(protover)),
+            Err(Error::EndOfStream) => return Ok(Hello::default()),
+            Err(err) => return Err(err),
+        };
+
+        match parse.next_string() {
+            Ok(s) if s.to_uppercase() == "AUTH" => {
+                let username = parse.next_string()?;
+                let password = parse.next_string()?;
+                Ok(hello.with_auth(username, password))
+            }
+            Ok(_) => Err("`HELLO` only supports the AUTH option".into()),
+            Err(Error::EndOfStream) => Ok(hello),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Apply the `Hello` command.
+    ///
+    /// The reply is a map describing the server, encoded with the newly
+    /// selected protocol. An unsupported version is rejected with a `NOPROTO`
+    /// error and leaves the connection unchanged, as does a failed
+    /// authentication.
+    #[instrument(skip(self, session, dst))]
+    pub(crate) async fn apply(self, session: &mut Session, dst: &mut Connection) -> crate::Result<()> {
+        if let Some((username, password)) = &self.auth {
+            if let Err(msg) = session.authenticate(Some(username), password) {
+                dst.write_frame(&Frame::Error(msg)).await?;
+                return Ok(());
+            }
+        }
+
+        let response = match self.protover {
+            Some(protover @ 2..=3) => {
+                dst.set_protocol(protover as u8);
+                server_info(dst.protocol())
+            }
+            Some(_) => Frame::Error("NOPROTO unsupported protocol version".to_string()),
+            None => server_info(dst.protocol()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("hello".as_bytes()));
+        if let Some(protover) = self.protover {
+            frame.push_bulk(Bytes::from(protover.to_string()));
+            if let Some((username, password)) = self.auth {
+                frame.push_bulk(Bytes::from("auth".as_bytes()));
+                frame.push_bulk(Bytes::from(username.into_bytes()));
+                frame.push_bulk(Bytes::from(password.into_bytes()));
+            }
+        }
+        frame
+    }
+}
+
+// Commands are logged when received, so the password is left out.
+impl fmt::Debug for Hello {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hello")
+            .field("protover", &self.protover)
+            .field("username", &self.auth.as_ref().map(|(username, _)| username))
+            .finish()
+    }
+}
+
+/// Builds the server description returned by `HELLO`.
+fn server_info(protocol: u8) -> Frame {
+    let mut info = Frame::Map(vec![]);
+    info.push_entry("server", Frame::Bulk(Bytes::from_static(b"peer")));
+    info.push_entry("version", Frame::Bulk(Bytes::from_static(env!("CARGO_PKG_VERSION").as_bytes())));
+    info.push_entry("proto", Frame::Integer(protocol as i64));
+    info.push_entry("mode", Frame::Bulk(Bytes::from_static(b"standalone")));
+    info.push_entry("role", Frame::Bulk(Bytes::from_static(b"master")));
+    info.push_entry("modules", Frame::array());
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Acl;
+    use crate::config::AuthConfig;
+    use crate::node::ClientRegistry;
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Returns a session requiring `requirepass`, if set, and the server and
+    /// client ends of its connection.
+    async fn setup(requirepass: Option<&str>) -> (Session, Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, addr) = listener.accept().await.unwrap();
+
+        let auth = AuthConfig {
+            requirepass: requirepass.map(str::to_string),
+            ..Default::default()
+        };
+        let session = Session::new(Arc::new(ClientRegistry::new(1)).register(addr), Arc::new(Acl::new(&auth)));
+
+        (session, Connection::new(server), Connection::new(client))
+    }
+
+    /// Runs `hello` and returns the reply read by the client.
+    async fn hello(hello: Hello, session: &mut Session, server: &mut Connection, client: &mut Connection) -> Frame {
+        hello.apply(session, server).await.unwrap();
+        server.flush().await.unwrap();
+        client.read_frame().await.unwrap().unwrap()
+    }
+
+    fn proto(reply: &Frame) -> Option<i64> {
+        match reply {
+            Frame::Map(entries) => entries.iter().find_map(|(key, value)| match value {
+                Frame::Integer(proto) if *key == "proto" => Some(*proto),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn negotiate() {
+        let (mut session, mut server, mut client) = setup(None).await;
+        assert_eq!(server.protocol(), 2);
+
+        // The reply to `HELLO 3` is already a RESP3 map.
+        let reply = hello(Hello::new(Some(3)), &mut session, &mut server, &mut client).await;
+        assert_eq!(proto(&reply), Some(3));
+        assert_eq!(server.protocol(), 3);
+
+        // Without a version the protocol is left unchanged.
+        let reply = hello(Hello::new(None), &mut session, &mut server, &mut client).await;
+        assert_eq!(proto(&reply), Some(3));
+        assert_eq!(server.protocol(), 3);
+
+        // RESP2 receives the map as a flat array of keys and values.
+        let reply = hello(Hello::new(Some(2)), &mut session, &mut server, &mut client).await;
+        assert_eq!(server.protocol(), 2);
+        match reply {
+            Frame::Array(items) => {
+                let at = items.iter().position(|item| *item == "proto").unwrap();
+                assert!(matches!(items[at + 1], Frame::Integer(2)));
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[tokio::test]
+    async fn unsupported_version() {
+        let (mut session, mut server, mut client) = setup(None).await;
+
+        for protover in [0, 1, 4] {
+            let reply = hello(Hello::new(Some(protover)), &mut session, &mut server, &mut client).await;
+            assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("NOPROTO")));
+            assert_eq!(server.protocol(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn auth() {
+        let (mut session, mut server, mut client) = setup(Some("secret")).await;
+
+        // A failed authentication leaves the protocol unchanged.
+        let cmd = Hello::new(Some(3)).with_auth("default", "wrong");
+        let reply = hello(cmd, &mut session, &mut server, &mut client).await;
+        assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("WRONGPASS")));
+        assert_eq!(server.protocol(), 2);
+
+        let cmd = Hello::new(Some(3)).with_auth("default", "secret");
+        let reply = hello(cmd, &mut session, &mut server, &mut client).await;
+        assert_eq!(proto(&reply), Some(3));
+    }
+
+    #[test]
+    fn parse() {
+        let parse = |args: &[&str]| {
+            let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+            let mut parse = Parse::new(frame).unwrap();
+            Hello::parse_frames(&mut parse)
+        };
+
+        assert_eq!(parse(&[]).unwrap().protover, None);
+        assert_eq!(parse(&["3"]).unwrap().protover, Some(3));
+        let hello = parse(&["3", "auth", "alice", "pw"]).unwrap();
+        assert_eq!(hello.auth, Some(("alice".to_string(), "pw".to_string())));
+
+        assert!(parse(&["three"]).is_err());
+        assert!(parse(&["3", "setname", "x"]).is_err());
+        assert!(parse(&["3", "auth", "alice"]).is_err());
+    }
+}
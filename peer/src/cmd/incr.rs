@@ -0,0 +1,92 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Adds a delta to the integer stored at a key.
+///
+/// Covers `INCR`, `DECR`, `INCRBY` and `DECRBY`. A missing key is treated as
+/// 0. The reply is the value after the operation, or an error if the stored
+/// value is not an integer or the result would overflow.
+#[derive(Debug)]
+pub struct Incr {
+    /// the command name, reported by `CLIENT LIST` and checked by the ACL
+    name: &'static str,
+
+    /// the counter key
+    key: Bytes,
+
+    /// amount to add, negative for `DECR` and `DECRBY`
+    delta: i64,
+}
+
+impl Incr {
+    /// Create a new `Incr` command which adds `delta` to `key`.
+    pub fn new(key: Bytes, delta: i64) -> Incr {
+        Incr {
+            name: "incrby",
+            key,
+            delta,
+        }
+    }
+
+    /// Get the name of the command, `incr`, `decr`, `incrby` or `decrby`
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the key
+    pub fn key(&self) -> &Bytes {
+        &self.key
+    }
+
+    /// Get the delta
+    pub fn delta(&self) -> i64 {
+        self.delta
+    }
+
+    /// Parse the arguments of the command called `name`, one of `incr`,
+    /// `decr`, `incrby` or `decrby`.
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> crate::Result<Incr> {
+        let key = parse.next_bytes()?;
+
+        let (name, delta) = match name {
+            "incr" => ("incr", 1),
+            "decr" => ("decr", -1),
+            "incrby" => ("incrby", parse.next_signed_int()?),
+            _ => (
+                "decrby",
+                parse
+                    .next_signed_int()?
+                    .checked_neg()
+                    .ok_or(mineral::error::Error::IntegerOverflow)?,
+            ),
+        };
+
+        Ok(Incr { name, key, delta })
+    }
+
+    /// Apply the `Incr` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.incr_by(session.db(), &self.key, self.delta).await {
+            Ok(value) => Frame::Integer(value),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `INCRBY` frame.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("incrby".as_bytes()));
+        frame.push_bulk(self.key);
+        frame.push_bulk(Bytes::from(self.delta.to_string()));
+        frame
+    }
+}
@@ -0,0 +1,214 @@
+use crate::config::DEFAULT_NAMESPACE;
+use crate::{error::Error, node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use mineral::kv::hash::KvStats;
+use std::fmt::Write;
+use tracing::{debug, instrument};
+
+/// Returns information and statistics about the server.
+///
+/// The reply is a bulk string made of `# Section` headers followed by
+/// `field:value` lines, in the same layout Redis uses, sent as a verbatim
+/// `txt` string to RESP3 clients. An optional section name restricts the
+/// reply to that section.
+///
+/// The sections are `server`, `clients`, `memory`, `persistence`, `p2p`,
+/// `keyspace` and `storage`. Memory and persistence figures are summed over
+/// all namespaces, the keyspace section has one line per namespace, while the
+/// storage section describes the database selected by the connection.
+#[derive(Debug, Default)]
+pub struct Info {
+    /// optional section to return
+    section: Option<String>,
+}
+
+impl Info {
+    /// Create a new `Info` command with an optional `section`.
+    pub fn new(section: Option<String>) -> Info {
+        Info { section }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Info> {
+        match parse.next_string() {
+            Ok(section) => Ok(Info::new(Some(section.to_lowercase()))),
+            Err(Error::EndOfStream) => Ok(Info::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Apply the `Info` command and write the statistics to `dst`.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match render(node, session.db(), self.section.as_deref()).await {
+            Ok(info) => Frame::Verbatim("txt".to_string(), Bytes::from(info)),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("info".as_bytes()));
+        if let Some(section) = self.section {
+            frame.push_bulk(Bytes::from(section.into_bytes()));
+        }
+        frame
+    }
+}
+
+/// Formats the INFO text, keeping only `section` when given. Storage figures
+/// are those of database `selected`.
+async fn render(node: &Node, selected: usize, section: Option<&str>) -> crate::Result<String> {
+    let wanted = |name: &str| matches!(section, None | Some("all") | Some("default")) || section == Some(name);
+
+    let mut out = String::new();
+    if wanted("server") {
+        render_server(&mut out, node);
+    }
+    if wanted("clients") {
+        let clients = node.clients();
+        let _ = write!(out, "# Clients\r\n");
+        let _ = write!(out, "connected_clients:{}\r\n", clients.connected());
+        let _ = write!(out, "maxclients:{}\r\n", clients.max_clients());
+    }
+
+    // Collecting the storage statistics walks every slot, so it is skipped
+    // when no section needs them.
+    if ["memory", "persistence", "keyspace", "storage"].iter().any(|name| wanted(name)) {
+        let mut stats = vec![];
+        for (db, name) in node.namespaces().into_iter().enumerate() {
+            stats.push((name, node.stats(db).await?));
+        }
+
+        if wanted("memory") {
+            render_memory(&mut out, node, &stats);
+        }
+        if wanted("persistence") {
+            let mut read_only = vec![];
+            for db in 0..stats.len() {
+                read_only.push(node.is_read_only(db).await?);
+            }
+            render_persistence(&mut out, &stats, &read_only);
+        }
+        if wanted("keyspace") {
+            render_keyspace(&mut out, &stats);
+        }
+        if wanted("storage") {
+            render_storage(&mut out, &stats[selected]);
+        }
+    }
+
+    if wanted("p2p") {
+        let status = node.p2p_status().await;
+        let _ = write!(out, "# P2p\r\n");
+        let _ = write!(out, "peer_id:{}\r\n", status.local_peer_id);
+        let addrs: Vec<_> = status.listened_addresses.iter().map(|addr| addr.to_string()).collect();
+        let _ = write!(out, "listened_addresses:{}\r\n", addrs.join(","));
+        let _ = write!(out, "known_peers:{}\r\n", status.known_peers_count);
+        for (i, (peer_id, addrs)) in status.known_peers.iter().enumerate() {
+            let addrs: Vec<_> = addrs.iter().map(|addr| addr.to_string()).collect();
+            let _ = write!(out, "peer{}:id={},addrs={}\r\n", i, peer_id, addrs.join(","));
+        }
+    }
+    Ok(out)
+}
+
+fn render_server(out: &mut String, node: &Node) {
+    let uptime = node.started().elapsed().as_secs();
+    let config = node.config();
+    let _ = write!(out, "# Server\r\n");
+    let _ = write!(out, "peer_version:{}\r\n", env!("CARGO_PKG_VERSION"));
+    let _ = write!(out, "process_id:{}\r\n", std::process::id());
+    let _ = write!(out, "http_addr:{}\r\n", config.http_addr);
+    let _ = write!(out, "tls:{}\r\n", if config.tls.is_some() { "yes" } else { "no" });
+    let _ = write!(out, "uptime_in_seconds:{}\r\n", uptime);
+    let _ = write!(out, "uptime_in_days:{}\r\n", uptime / 86400);
+}
+
+fn render_memory(out: &mut String, node: &Node, stats: &[(String, KvStats)]) {
+    let config = node.config();
+    let cache_cap: usize = config
+        .namespaces
+        .iter()
+        .filter(|ns| ns.name != DEFAULT_NAMESPACE)
+        .map(|ns| ns.cache_cap.unwrap_or(config.storage.cache_cap))
+        .sum::<usize>()
+        + config
+            .namespaces
+            .iter()
+            .find(|ns| ns.name == DEFAULT_NAMESPACE)
+            .and_then(|ns| ns.cache_cap)
+            .unwrap_or(config.storage.cache_cap);
+
+    let _ = write!(out, "# Memory\r\n");
+    if let Some(rss) = resident_memory() {
+        let _ = write!(out, "used_memory_rss:{}\r\n", rss);
+    }
+    let _ = write!(out, "cache_cap:{}\r\n", cache_cap);
+    let _ = write!(out, "cache_entries:{}\r\n", stats.iter().map(|(_, s)| s.cache_entries).sum::<usize>());
+    let _ = write!(out, "cbf_bytes:{}\r\n", stats.iter().map(|(_, s)| s.storage.cbf_bytes).sum::<usize>());
+}
+
+fn render_persistence(out: &mut String, stats: &[(String, KvStats)], read_only: &[bool]) {
+    let sum = |field: fn(&KvStats) -> u64| stats.iter().map(|(_, s)| field(s)).sum::<u64>();
+
+    let _ = write!(out, "# Persistence\r\n");
+    let _ = write!(out, "read_only:{}\r\n", read_only.iter().any(|ro| *ro) as u8);
+    let _ = write!(out, "wal_bytes:{}\r\n", sum(|s| s.storage.wal_bytes));
+    let _ = write!(out, "cbf_pages:{}\r\n", sum(|s| s.storage.cbf_pages as u64));
+    let _ = write!(out, "cbf_bytes:{}\r\n", sum(|s| s.storage.cbf_bytes as u64));
+    let _ = write!(
+        out,
+        "flush_lag_ms:{}\r\n",
+        stats.iter().map(|(_, s)| s.storage.flush_lag_ms).max().unwrap_or(0)
+    );
+    let _ = write!(out, "write_stalls:{}\r\n", sum(|s| s.storage.write_stalls));
+    let _ = write!(out, "write_rejects:{}\r\n", sum(|s| s.storage.write_rejects));
+    let _ = write!(out, "vlog_bytes:{}\r\n", sum(|s| s.vlog_bytes));
+}
+
+fn render_keyspace(out: &mut String, stats: &[(String, KvStats)]) {
+    let _ = write!(out, "# Keyspace\r\n");
+    for (db, (name, stats)) in stats.iter().enumerate() {
+        let _ = write!(
+            out,
+            "db{}:name={},keys={},cache_entries={},vlog_segments={},vlog_bytes={}\r\n",
+            db, name, stats.keys, stats.cache_entries, stats.vlog_segments, stats.vlog_bytes
+        );
+    }
+}
+
+fn render_storage(out: &mut String, (name, stats): &(String, KvStats)) {
+    let storage = &stats.storage;
+    let _ = write!(out, "# Storage\r\n");
+    let _ = write!(out, "namespace:{}\r\n", name);
+    let _ = write!(out, "slots_used:{}\r\n", storage.slots_used);
+    let _ = write!(out, "slot_avg_size:{}\r\n", storage.slot_avg_size);
+    let _ = write!(out, "slot_max_size:{}\r\n", storage.slot_max_size);
+    let _ = write!(out, "overflows:{}\r\n", storage.overflows);
+    let _ = write!(out, "blocks_allocated:{}\r\n", storage.blocks_allocated);
+    let _ = write!(out, "blocks_free:{}\r\n", storage.blocks_free);
+    let _ = write!(out, "largest_free_extent:{}\r\n", storage.largest_free_extent);
+    let _ = write!(out, "wal_bytes:{}\r\n", storage.wal_bytes);
+    let _ = write!(out, "cbf_pages:{}\r\n", storage.cbf_pages);
+    let _ = write!(out, "cbf_bytes:{}\r\n", storage.cbf_bytes);
+    let _ = write!(out, "flush_lag_ms:{}\r\n", storage.flush_lag_ms);
+    let _ = write!(out, "write_stalls:{}\r\n", storage.write_stalls);
+    let _ = write!(out, "write_rejects:{}\r\n", storage.write_rejects);
+}
+
+/// Returns the resident set size of the process in bytes, where the platform
+/// reports it in `/proc`.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.trim_start_matches("VmRSS:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
@@ -4,16 +4,73 @@ pub use get::Get;
 mod set;
 pub use set::Set;
 
+mod del;
+pub use del::Del;
+
+mod exists;
+pub use exists::Exists;
+
+mod mget;
+pub use mget::MGet;
+
+mod mset;
+pub use mset::MSet;
+
+mod incr;
+pub use incr::Incr;
+
+mod cas;
+pub use cas::Cas;
+
+mod getver;
+pub use getver::GetVer;
+
+mod publish;
+pub use publish::Publish;
+
+mod subscribe;
+pub use subscribe::{Subscribe, Unsubscribe};
+
+mod hello;
+pub use hello::Hello;
+
+mod auth;
+pub use auth::Auth;
+
 mod ping;
 pub use ping::Ping;
 
+mod info;
+pub use info::Info;
+
+mod select;
+pub use select::Select;
+
+mod dbsize;
+pub use dbsize::DbSize;
+
+mod config;
+pub use config::ConfigGet;
+
+mod client;
+pub use client::{ClientKill, ClientList};
+
+mod slowlog;
+pub use slowlog::{SlowLogEntry, SlowLogGet, SlowLogLen, SlowLogReset};
+
 mod unknown;
 pub use unknown::Unknown;
 
 mod peer;
 pub use peer::Peer;
 
-use crate::{cmd, node::Node, Connection, Db, Frame, Parse, Shutdown, error::Error};
+use crate::{cmd, node::Node, Connection, Db, Frame, Parse, Session, Shutdown, error::Error};
+
+use crate::db::measure_storage;
+
+use bytes::Bytes;
+use std::time::Instant;
+use tracing::{field, info_span, Instrument};
 
 /// Enumeration of supported Redis commands.
 ///
@@ -22,49 +79,179 @@ use crate::{cmd, node::Node, Connection, Db, Frame, Parse, Shutdown, error::Erro
 pub enum Command {
     Get(Get),
     Set(Set),
+    Del(Del),
+    Exists(Exists),
+    MGet(MGet),
+    MSet(MSet),
+    Incr(Incr),
+    Cas(Cas),
+    GetVer(GetVer),
+    Publish(Publish),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Hello(Hello),
+    Auth(Auth),
     Ping(Ping),
+    Info(Info),
+    Select(Select),
+    DbSize(DbSize),
+    ConfigGet(ConfigGet),
+    ClientList(ClientList),
+    ClientKill(ClientKill),
+    SlowLogGet(SlowLogGet),
+    SlowLogLen(SlowLogLen),
+    SlowLogReset(SlowLogReset),
     Unknown(Unknown),
     Peer(Peer),
 }
 
 impl Command {
     /// On success, the command value is returned, otherwise, `Err` is returned.
+    ///
+    /// A missing or extra argument fails with `Error::WrongArity`. The frame
+    /// has been read whole, so the caller can reply with the error and keep
+    /// serving the connection.
     pub fn from_frame(frame: Frame) -> crate::Result<Command> {
         // result in an error being returned.
         let mut parse = Parse::new(frame)?;
 
         let command_name = parse.next_string()?.to_lowercase();
 
-        let command = match &command_name[..] {
-            "get" => Command::Get(Get::parse_frames(&mut parse)?),
-            "set" => Command::Set(Set::parse_frames(&mut parse)?),
-            "ping" => Command::Ping(Ping::parse_frames(&mut parse)?),
-            "peer" => Command::Peer(Peer::parse_frames(&mut parse)?),
-            _ => {
-                return Ok(Command::Unknown(Unknown::new(command_name)));
-            }
+        let command = match Command::parse_args(&mut parse, &command_name) {
+            Ok(command) => command,
+            Err(Error::EndOfStream) => return Err(Error::WrongArity(command_name)),
+            Err(err) => return Err(err),
         };
 
-        parse.finish()?;
+        // Unknown commands and subcommands ignore their arguments.
+        if !matches!(command, Command::Unknown(_)) && parse.finish().is_err() {
+            return Err(Error::WrongArity(command_name));
+        }
+
+        Ok(command)
+    }
+
+    /// Parses the arguments of the command called `command_name`.
+    fn parse_args(parse: &mut Parse, command_name: &str) -> crate::Result<Command> {
+        let command = match command_name {
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "del" => Command::Del(Del::parse_frames(parse)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(parse)?),
+            "cas" => Command::Cas(Cas::parse_frames(parse)?),
+            "getver" => Command::GetVer(GetVer::parse_frames(parse)?),
+            "incr" | "decr" | "incrby" | "decrby" => Command::Incr(Incr::parse_frames(parse, command_name)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "info" => Command::Info(Info::parse_frames(parse)?),
+            "select" => Command::Select(Select::parse_frames(parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(parse)?),
+            "config" => match &parse.next_string()?.to_lowercase()[..] {
+                "get" => Command::ConfigGet(ConfigGet::parse_frames(parse)?),
+                sub => Command::Unknown(Unknown::new(format!("config {}", sub))),
+            },
+            "client" => match &parse.next_string()?.to_lowercase()[..] {
+                "list" => Command::ClientList(ClientList::parse_frames(parse)?),
+                "kill" => Command::ClientKill(ClientKill::parse_frames(parse)?),
+                sub => Command::Unknown(Unknown::new(format!("client {}", sub))),
+            },
+            "slowlog" => match &parse.next_string()?.to_lowercase()[..] {
+                "get" => Command::SlowLogGet(SlowLogGet::parse_frames(parse)?),
+                "len" => Command::SlowLogLen(SlowLogLen::parse_frames(parse)?),
+                "reset" => Command::SlowLogReset(SlowLogReset::parse_frames(parse)?),
+                sub => Command::Unknown(Unknown::new(format!("slowlog {}", sub))),
+            },
+            "peer" => Command::Peer(Peer::parse_frames(parse)?),
+            _ => Command::Unknown(Unknown::new(command_name)),
+        };
 
         Ok(command)
     }
 
+    /// Apply the command to the node, writing the reply to `dst`.
+    ///
+    /// The run time is recorded in the metrics, on the `apply` span, and in
+    /// the slow log with `args`, the arguments captured by
+    /// `SlowLog::capture` before the frame was parsed.
     pub(crate) async fn apply(
         self,
         node: &Node,
         dst: &mut Connection,
+        session: &mut Session,
         shutdown: &mut Shutdown,
+        args: Option<Vec<Bytes>>,
     ) -> crate::Result<()> {
         use Command::*;
 
-        match self {
-            Get(cmd) => cmd.apply(node, dst).  await,
-            Set(cmd) => cmd.apply(node, dst).await,
-            Ping(cmd) => cmd.apply(dst).await,
-            Peer(cmd) => cmd.apply(node, dst).await,
-            Unknown(cmd) => cmd.apply(dst).await,
+        session.client().record_command(self.get_name());
+
+        if let Err(msg) = session.authorize(&self) {
+            dst.write_frame(&Frame::Error(msg)).await?;
+            return Ok(());
         }
+
+        // Unknown commands share one name, theirs come from clients.
+        let name = match &self {
+            Unknown(_) => "unknown".to_string(),
+            cmd => cmd.get_name().to_string(),
+        };
+        // Subscriptions last until the client unsubscribes and are not timed.
+        let timed = !matches!(self, Subscribe(_));
+
+        let span = info_span!("apply", command = %name, duration_us = field::Empty, storage_us = field::Empty);
+        let start = Instant::now();
+
+        let dispatch = async {
+            match self {
+                Get(cmd) => cmd.apply(node, session, dst).await,
+                Set(cmd) => cmd.apply(node, session, dst).await,
+                Del(cmd) => cmd.apply(node, session, dst).await,
+                Exists(cmd) => cmd.apply(node, session, dst).await,
+                MGet(cmd) => cmd.apply(node, session, dst).await,
+                MSet(cmd) => cmd.apply(node, session, dst).await,
+                Incr(cmd) => cmd.apply(node, session, dst).await,
+                Cas(cmd) => cmd.apply(node, session, dst).await,
+                GetVer(cmd) => cmd.apply(node, session, dst).await,
+                Publish(cmd) => cmd.apply(node, dst).await,
+                Subscribe(cmd) => cmd.apply(node, dst, session, shutdown).await,
+                // Inside a subscription `Unsubscribe` is handled by the
+                // `Subscribe` command, here there is nothing to unsubscribe.
+                Unsubscribe(cmd) => cmd.apply(dst).await,
+                Hello(cmd) => cmd.apply(session, dst).await,
+                Auth(cmd) => cmd.apply(session, dst).await,
+                Ping(cmd) => cmd.apply(dst).await,
+                Info(cmd) => cmd.apply(node, session, dst).await,
+                Select(cmd) => cmd.apply(node, session, dst).await,
+                DbSize(cmd) => cmd.apply(node, session, dst).await,
+                ConfigGet(cmd) => cmd.apply(node, dst).await,
+                ClientList(cmd) => cmd.apply(node, dst).await,
+                ClientKill(cmd) => cmd.apply(node, session, dst).await,
+                SlowLogGet(cmd) => cmd.apply(node, dst).await,
+                SlowLogLen(cmd) => cmd.apply(node, dst).await,
+                SlowLogReset(cmd) => cmd.apply(node, dst).await,
+                Peer(cmd) => cmd.apply(node, dst).await,
+                Unknown(cmd) => cmd.apply(dst).await,
+            }
+        };
+        let (res, storage) = measure_storage(dispatch).instrument(span.clone()).await;
+
+        let elapsed = start.elapsed();
+        span.record("duration_us", elapsed.as_micros() as u64);
+        span.record("storage_us", storage.as_micros() as u64);
+
+        if timed {
+            node.metrics().observe_command(&name, elapsed);
+            if let Some(args) = args {
+                node.slowlog().record(args, session.client().addr(), elapsed, storage);
+            }
+        }
+        res
     }
 
     /// Returns the command name
@@ -72,9 +259,94 @@ impl Command {
         match self {
             Command::Get(_) => "get",
             Command::Set(_) => "set",
+            Command::Del(_) => "del",
+            Command::Exists(_) => "exists",
+            Command::MGet(_) => "mget",
+            Command::MSet(_) => "mset",
+            Command::Incr(cmd) => cmd.name(),
+            Command::Cas(_) => "cas",
+            Command::GetVer(_) => "getver",
+            Command::Publish(_) => "publish",
+            Command::Subscribe(_) => "subscribe",
+            Command::Unsubscribe(_) => "unsubscribe",
+            Command::Hello(_) => "hello",
+            Command::Auth(_) => "auth",
             Command::Ping(_) => "ping",
+            Command::Info(_) => "info",
+            Command::Select(_) => "select",
+            Command::DbSize(_) => "dbsize",
+            Command::ConfigGet(_) => "config",
+            Command::ClientList(_) | Command::ClientKill(_) => "client",
+            Command::SlowLogGet(_) | Command::SlowLogLen(_) | Command::SlowLogReset(_) => "slowlog",
             Command::Peer(_) => "peer",
             Command::Unknown(cmd) => cmd.get_name(),
         }
     }
+
+    /// Returns the keys the command reads or writes.
+    pub(crate) fn keys(&self) -> Vec<&Bytes> {
+        match self {
+            Command::Get(cmd) => vec![cmd.key()],
+            Command::Set(cmd) => vec![cmd.key()],
+            Command::Del(cmd) => cmd.keys().iter().collect(),
+            Command::Exists(cmd) => cmd.keys().iter().collect(),
+            Command::MGet(cmd) => cmd.keys().iter().collect(),
+            Command::MSet(cmd) => cmd.pairs().iter().map(|(key, _)| key).collect(),
+            Command::Incr(cmd) => vec![cmd.key()],
+            Command::Cas(cmd) => vec![cmd.key()],
+            Command::GetVer(cmd) => vec![cmd.key()],
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> crate::Result<Command> {
+        let frame = Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect());
+        Command::from_frame(frame)
+    }
+
+    fn error_reply(args: &[&str]) -> String {
+        parse(args).unwrap_err().to_resp()
+    }
+
+    #[test]
+    fn parse_errors_become_replies() {
+        assert_eq!(error_reply(&["incrby", "k", "notanint"]), "ERR value is not an integer or out of range");
+        assert_eq!(error_reply(&["incrby", "k", "5x"]), "ERR value is not an integer or out of range");
+        assert_eq!(error_reply(&["set", "k", "v", "EX", "soon"]), "ERR value is not an integer or out of range");
+        assert_eq!(error_reply(&["set", "k", "v", "NOPE"]), "ERR syntax error");
+
+        assert_eq!(error_reply(&["get"]), "ERR wrong number of arguments for 'get' command");
+        assert_eq!(error_reply(&["get", "a", "b"]), "ERR wrong number of arguments for 'get' command");
+        for cmd in ["config", "client", "slowlog"] {
+            assert_eq!(error_reply(&[cmd]), format!("ERR wrong number of arguments for '{}' command", cmd));
+        }
+    }
+
+    #[test]
+    fn parse_incr() {
+        let delta = |args: &[&str]| match parse(args).unwrap() {
+            Command::Incr(cmd) => cmd.delta(),
+            cmd => panic!("unexpected command {:?}", cmd),
+        };
+        assert_eq!(delta(&["incr", "k"]), 1);
+        assert_eq!(delta(&["decr", "k"]), -1);
+        assert_eq!(delta(&["incrby", "k", "-7"]), -7);
+        assert_eq!(delta(&["decrby", "k", "7"]), -7);
+        for name in ["incr", "decr", "incrby", "decrby"] {
+            let args = if name.ends_with("by") { vec![name, "k", "1"] } else { vec![name, "k"] };
+            assert_eq!(parse(&args).unwrap().get_name(), name);
+        }
+        assert_eq!(
+            error_reply(&["decrby", "k", &i64::MIN.to_string()]),
+            "ERR increment or decrement would overflow"
+        );
+
+        // Unknown subcommands are replied to when applied.
+        assert!(matches!(parse(&["config", "set", "a", "b"]).unwrap(), Command::Unknown(_)));
+    }
 }
@@ -0,0 +1,66 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Sets several keys at once.
+///
+/// `MSET key value [key value ...]` always replies `OK`. Other clients never
+/// see only some of the keys updated.
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Vec<(Bytes, Bytes)>,
+}
+
+impl MSet {
+    /// Create a new `MSet` command which stores every pair of `pairs`.
+    pub fn new(pairs: Vec<(Bytes, Bytes)>) -> MSet {
+        MSet { pairs }
+    }
+
+    /// Get the key-value pairs
+    pub fn pairs(&self) -> &[(Bytes, Bytes)] {
+        &self.pairs
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<MSet> {
+        let items = parse.rest_bytes()?;
+        if items.len() % 2 != 0 {
+            return Err("ERR wrong number of arguments for 'mset' command".into());
+        }
+
+        let mut items = items.into_iter();
+        let mut pairs = vec![];
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((key, value));
+        }
+
+        Ok(MSet { pairs })
+    }
+
+    /// Apply the `MSet` command to the database selected by the connection.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.mset(session.db(), self.pairs).await {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err.to_resp()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("mset".as_bytes()));
+        for (key, value) in self.pairs {
+            frame.push_bulk(key);
+            frame.push_bulk(value);
+        }
+        frame
+    }
+}
@@ -18,7 +18,8 @@ impl PeerBasic {
     #[instrument(skip(self, node, dst))]
     pub async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
         let response = if let Some(value) = node.peer_basic().await {
-            let mut frame = Frame::array();
+            // Peer ids are unique, RESP2 clients receive the set as an array.
+            let mut frame = Frame::Set(vec![]);
             let _ = value.iter().for_each(|s| frame.push_string(s.clone()));
             frame
         } else {
@@ -0,0 +1,78 @@
+use crate::{node::Node, Connection, Frame, Parse};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Posts a message to the given channel.
+///
+/// Send a message into a channel without any knowledge of individual
+/// consumers. Consumers may subscribe to channels in order to receive the
+/// messages.
+///
+/// Channel names have no relation to the key-value namespace, so publishing
+/// on a channel named "foo" has no relation to setting the "foo" key. When the
+/// pub/sub bridge is enabled the message also reaches the subscribers
+/// connected to the other nodes.
+#[derive(Debug)]
+pub struct Publish {
+    /// Name of the channel on which the message should be published.
+    channel: String,
+
+    /// The message to publish.
+    message: Bytes,
+}
+
+impl Publish {
+    /// Create a new `Publish` command which sends `message` on `channel`.
+    pub(crate) fn new(channel: impl ToString, message: Bytes) -> Publish {
+        Publish {
+            channel: channel.to_string(),
+            message,
+        }
+    }
+
+    /// Parse a `Publish` instance from a received frame.
+    ///
+    /// The `PUBLISH` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing three entries.
+    ///
+    /// ```text
+    /// PUBLISH channel message
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Publish> {
+        let channel = parse.next_string()?;
+        let message = parse.next_bytes()?;
+
+        Ok(Publish { channel, message })
+    }
+
+    /// Apply the `Publish` command.
+    ///
+    /// The response is the number of subscribers on this node that received
+    /// the message.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        let num_subscribers = node.publish(&self.channel, self.message);
+
+        let response = Frame::Integer(num_subscribers as i64);
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("publish".as_bytes()));
+        frame.push_bulk(Bytes::from(self.channel.into_bytes()));
+        frame.push_bulk(self.message);
+
+        frame
+    }
+}
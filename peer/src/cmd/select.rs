@@ -0,0 +1,53 @@
+use crate::{node::Node, Connection, Frame, Parse, Session};
+
+use bytes::Bytes;
+use tracing::{debug, instrument};
+
+/// Switches the connection to another logical database.
+///
+/// The database is given either by its index or by its namespace name. The
+/// selection only applies to the connection that issued the command.
+#[derive(Debug)]
+pub struct Select {
+    /// database index or namespace name
+    db: String,
+}
+
+impl Select {
+    /// Create a new `Select` command which switches to `db`.
+    pub fn new(db: impl ToString) -> Select {
+        Select { db: db.to_string() }
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Select> {
+        let db = parse.next_string()?;
+
+        Ok(Select { db })
+    }
+
+    /// Apply the `Select` command to the connection `session`.
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &mut Session, dst: &mut Connection) -> crate::Result<()> {
+        let response = match node.select(&self.db) {
+            Some(db) => {
+                session.select(db);
+                Frame::Simple("OK".to_string())
+            }
+            None => Frame::Error("ERR DB index is out of range".to_string()),
+        };
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("select".as_bytes()));
+        frame.push_bulk(Bytes::from(self.db.into_bytes()));
+        frame
+    }
+}
@@ -1,12 +1,19 @@
 use crate::cmd::Parse;
 use crate::error::Error;
 use crate::node::Node;
-use crate::{Connection, Db, Frame};
+use crate::{Connection, Db, Frame, Session};
 
 use bytes::Bytes;
+use mineral::kv::hash::{SetCond, SetOptions};
 use std::time::Duration;
 use tracing::{debug, instrument};
 
+/// Set `key` to hold the string `value`.
+///
+/// Supports the `EX`/`PX` expiration options and the conditional `NX` (only
+/// if absent) and `XX` (only if present) options. With `GET` the reply is the
+/// previous value instead of `OK`. A write skipped by `NX`/`XX` replies with
+/// `nil`.
 #[derive(Debug)]
 pub struct Set {
     /// the lookup key
@@ -17,6 +24,12 @@ pub struct Set {
 
     /// When to expire the key
     expire: Option<Duration>,
+
+    /// Condition the write depends on
+    cond: SetCond,
+
+    /// Whether to reply with the previous value
+    get: bool,
 }
 
 impl Set {
@@ -26,9 +39,29 @@ impl Set {
             key,
             value,
             expire,
+            cond: SetCond::Always,
+            get: false,
         }
     }
 
+    /// Only set the key if it does not exist yet (`NX`).
+    pub fn if_absent(mut self) -> Set {
+        self.cond = SetCond::IfAbsent;
+        self
+    }
+
+    /// Only set the key if it already exists (`XX`).
+    pub fn if_present(mut self) -> Set {
+        self.cond = SetCond::IfPresent;
+        self
+    }
+
+    /// Reply with the previous value (`GET`).
+    pub fn with_get(mut self) -> Set {
+        self.get = true;
+        self
+    }
+
     /// Get the key
     pub fn key(&self) -> &Bytes {
         &self.key
@@ -51,37 +84,57 @@ impl Set {
         // Read the value to set. This is a required field.
         let value = parse.next_bytes()?;
 
-        let mut expire = None;
-
-        // Attempt to parse another string.
-        match parse.next_string() {
-            Ok(s) if s.to_uppercase() == "EX" => {
-                // An expiration is specified in seconds. The next value is an
-                // integer.
-                let secs = parse.next_int()?;
-                expire = Some(Duration::from_secs(secs));
-            }
-            Ok(s) if s.to_uppercase() == "PX" => {
-                // An expiration is specified in milliseconds. The next value is
-                // an integer.
-                let ms = parse.next_int()?;
-                expire = Some(Duration::from_millis(ms));
+        let mut set = Set::new(key, value, None);
+
+        // Options may follow in any order.
+        loop {
+            match parse.next_string() {
+                Ok(s) => match &s.to_uppercase()[..] {
+                    // An expiration is specified in seconds. The next value is
+                    // an integer.
+                    "EX" if set.expire.is_none() => {
+                        let secs = parse.next_int()?;
+                        set.expire = Some(Duration::from_secs(secs));
+                    }
+                    // An expiration is specified in milliseconds. The next
+                    // value is an integer.
+                    "PX" if set.expire.is_none() => {
+                        let ms = parse.next_int()?;
+                        set.expire = Some(Duration::from_millis(ms));
+                    }
+                    "NX" if set.cond == SetCond::Always => set.cond = SetCond::IfAbsent,
+                    "XX" if set.cond == SetCond::Always => set.cond = SetCond::IfPresent,
+                    "GET" => set.get = true,
+                    _ => return Err(Error::Other("ERR syntax error".into())),
+                },
+                Err(Error::EndOfStream) => break,
+                Err(err) => return Err(err),
             }
-            Ok(_) => return Err(Error::Other("currently `SET` only supports the expiration option".into())),
-            Err(Error::EndOfStream) => {}
-            Err(err) => return Err(err.into()),
         }
 
-        Ok(Set { key, value, expire })
+        Ok(set)
     }
 
-    #[instrument(skip(self, node, dst))]
-    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
-        // Set the value in the shared database state.
-        node.set(self.key, self.value, self.expire);
-
-        // Create a success response and write it to `dst`.
-        let response = Frame::Simple("OK".to_string());
+    #[instrument(skip(self, node, session, dst))]
+    pub(crate) async fn apply(self, node: &Node, session: &Session, dst: &mut Connection) -> crate::Result<()> {
+        let opts = SetOptions {
+            expire: self.expire,
+            cond: self.cond,
+            get: self.get,
+        };
+
+        // Set the value in the database selected by the connection.
+        // Storage failures are reported to the client instead of closing the
+        // connection.
+        let response = match node.set(session.db(), self.key, self.value, opts).await {
+            Ok(outcome) if self.get => match outcome.old {
+                Some(old) => Frame::Bulk(Bytes::from(old)),
+                None => Frame::Null,
+            },
+            Ok(outcome) if outcome.written => Frame::Simple("OK".to_string()),
+            Ok(_) => Frame::Null,
+            Err(err) => Frame::Error(err.to_resp()),
+        };
         debug!(?response);
         dst.write_frame(&response).await.map_err(|err| Error::Response(format!("{:?}", err)))?;
 
@@ -99,7 +152,15 @@ impl Set {
         frame.push_bulk(self.value);
         if let Some(ms) = self.expire {
             frame.push_bulk(Bytes::from("px".as_bytes()));
-            frame.push_int(ms.as_millis() as u64);
+            frame.push_int(ms.as_millis() as i64);
+        }
+        match self.cond {
+            SetCond::IfAbsent => frame.push_bulk(Bytes::from("nx".as_bytes())),
+            SetCond::IfPresent => frame.push_bulk(Bytes::from("xx".as_bytes())),
+            _ => {}
+        }
+        if self.get {
+            frame.push_bulk(Bytes::from("get".as_bytes()));
         }
         frame
     }
@@ -0,0 +1,233 @@
+use crate::{error::Error, node::Node, Connection, Frame, Parse};
+
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+/// Number of entries `SLOWLOG GET` returns when no count is given.
+const DEFAULT_COUNT: usize = 10;
+
+/// Returns the most recent entries of the slow log, newest first.
+///
+/// `SLOWLOG GET [count]` returns up to `count` entries, 10 by default, or all
+/// of them if `count` is -1. Each entry is an array of:
+///
+/// 1. a unique, increasing id
+/// 2. the unix time the command was run at, in seconds
+/// 3. how long it ran, in microseconds
+/// 4. its arguments, at most 32 of them and each cut to 128 bytes
+/// 5. the client address
+/// 6. the client name, always empty
+/// 7. the part of the run time spent in the storage engine, in microseconds
+/// 8. the rest of the run time, spent parsing the arguments and encoding the
+///    reply, in microseconds. Replies are buffered and sent once the batch of
+///    pipelined commands is done, so sending them is not part of the run time
+///
+/// The first six fields are those Redis returns.
+#[derive(Debug, Default)]
+pub struct SlowLogGet {
+    /// `None` returns every entry.
+    count: Option<usize>,
+}
+
+/// Returns the number of entries in the slow log.
+#[derive(Debug, Default)]
+pub struct SlowLogLen;
+
+/// Removes every entry from the slow log.
+#[derive(Debug, Default)]
+pub struct SlowLogReset;
+
+/// A command recorded in the slow log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowLogEntry {
+    pub id: u64,
+
+    /// Unix time the command was run at, in seconds.
+    pub timestamp: u64,
+
+    /// How long the command ran.
+    pub duration: Duration,
+
+    /// The part of `duration` spent in the storage engine.
+    pub storage: Duration,
+
+    /// The command name and arguments, truncated.
+    pub args: Vec<Bytes>,
+
+    /// Address of the client that ran the command.
+    pub addr: String,
+}
+
+impl SlowLogGet {
+    /// Create a new `SlowLogGet` command returning up to `count` entries,
+    /// every entry if `None`.
+    pub fn new(count: Option<usize>) -> SlowLogGet {
+        SlowLogGet { count }
+    }
+
+    /// Parse a `SlowLogGet` instance from a received frame.
+    ///
+    /// The `SLOWLOG GET` strings have already been consumed.
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<SlowLogGet> {
+        let count = match parse.next_string() {
+            Ok(count) => count,
+            Err(Error::EndOfStream) => return Ok(SlowLogGet::new(Some(DEFAULT_COUNT))),
+            Err(err) => return Err(err),
+        };
+
+        match count.parse::<i64>() {
+            Ok(-1) => Ok(SlowLogGet::new(None)),
+            Ok(count) if count >= 0 => Ok(SlowLogGet::new(Some(count as usize))),
+            _ => Err("count should be greater than or equal to -1".into()),
+        }
+    }
+
+    /// Apply the `SlowLogGet` command and write the entries to `dst`.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        let entries = node.slowlog().get(self.count.unwrap_or(usize::MAX));
+        let response = Frame::Array(entries.into_iter().map(SlowLogEntry::into_frame).collect());
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("slowlog".as_bytes()));
+        frame.push_bulk(Bytes::from("get".as_bytes()));
+        let count = self.count.map_or(-1, |count| count as i64);
+        frame.push_bulk(Bytes::from(count.to_string()));
+        frame
+    }
+}
+
+impl SlowLogLen {
+    /// Create a new `SlowLogLen` command.
+    pub fn new() -> SlowLogLen {
+        SlowLogLen
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<SlowLogLen> {
+        Ok(SlowLogLen)
+    }
+
+    /// Apply the `SlowLogLen` command.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        let response = Frame::Integer(node.slowlog().len() as i64);
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("slowlog".as_bytes()));
+        frame.push_bulk(Bytes::from("len".as_bytes()));
+        frame
+    }
+}
+
+impl SlowLogReset {
+    /// Create a new `SlowLogReset` command.
+    pub fn new() -> SlowLogReset {
+        SlowLogReset
+    }
+
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> crate::Result<SlowLogReset> {
+        Ok(SlowLogReset)
+    }
+
+    /// Apply the `SlowLogReset` command.
+    #[instrument(skip(self, node, dst))]
+    pub(crate) async fn apply(self, node: &Node, dst: &mut Connection) -> crate::Result<()> {
+        node.slowlog().reset();
+
+        let response = Frame::Simple("OK".to_string());
+
+        debug!(?response);
+
+        dst.write_frame(&response).await?;
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("slowlog".as_bytes()));
+        frame.push_bulk(Bytes::from("reset".as_bytes()));
+        frame
+    }
+}
+
+impl SlowLogEntry {
+    /// Returns the time not spent in the storage engine.
+    pub fn other(&self) -> Duration {
+        self.duration.saturating_sub(self.storage)
+    }
+
+    /// Converts the entry into the array `SLOWLOG GET` replies with.
+    pub(crate) fn into_frame(self) -> Frame {
+        let other = self.other();
+
+        Frame::Array(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.timestamp as i64),
+            Frame::Integer(self.duration.as_micros() as i64),
+            Frame::Array(self.args.into_iter().map(Frame::Bulk).collect()),
+            Frame::Bulk(Bytes::from(self.addr.into_bytes())),
+            Frame::Bulk(Bytes::new()),
+            Frame::Integer(self.storage.as_micros() as i64),
+            Frame::Integer(other.as_micros() as i64),
+        ])
+    }
+}
+
+impl TryFrom<Frame> for SlowLogEntry {
+    type Error = crate::Error;
+
+    /// Parses an entry of a `SLOWLOG GET` reply.
+    fn try_from(frame: Frame) -> crate::Result<SlowLogEntry> {
+        let fields = match frame {
+            Frame::Array(fields) if fields.len() >= 5 => fields,
+            frame => return Err(frame.to_error()),
+        };
+
+        let uint = |index: usize| match fields.get(index) {
+            Some(Frame::Integer(value)) if *value >= 0 => Ok(*value as u64),
+            Some(frame) => Err(frame.to_error()),
+            None => Ok(0),
+        };
+        let args = match &fields[3] {
+            Frame::Array(args) => args
+                .iter()
+                .map(|arg| match arg {
+                    Frame::Bulk(arg) => Ok(arg.clone()),
+                    frame => Err(frame.to_error()),
+                })
+                .collect::<crate::Result<_>>()?,
+            frame => return Err(frame.to_error()),
+        };
+
+        Ok(SlowLogEntry {
+            id: uint(0)?,
+            timestamp: uint(1)?,
+            duration: Duration::from_micros(uint(2)?),
+            storage: Duration::from_micros(uint(6)?),
+            args,
+            addr: fields[4].to_string(),
+        })
+    }
+}
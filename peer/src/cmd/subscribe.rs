@@ -0,0 +1,384 @@
+use crate::cmd::{Parse, Unknown};
+use crate::{error::Error, node::Node, Command, Connection, Frame, Session, Shutdown};
+
+use bytes::Bytes;
+use std::pin::Pin;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, StreamMap};
+
+/// Subscribes the client to one or more channels.
+///
+/// Once a RESP2 client enters the subscribed state, it is not supposed to
+/// issue any other commands, except for additional SUBSCRIBE and UNSUBSCRIBE
+/// commands. A RESP3 client receives messages as push frames, so it may keep
+/// issuing regular commands on the same connection.
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<String>,
+}
+
+/// Unsubscribes the client from one or more channels.
+///
+/// When no channels are specified, the client is unsubscribed from all the
+/// previously subscribed channels.
+#[derive(Clone, Debug)]
+pub struct Unsubscribe {
+    channels: Vec<String>,
+}
+
+/// Stream of messages. The stream receives messages from the
+/// `broadcast::Receiver`. We use `stream!` to create a `Stream` that consumes
+/// messages. Because `stream!` values cannot be named, we box the stream using
+/// a trait object.
+type Messages = Pin<Box<dyn Stream<Item = Bytes> + Send>>;
+
+impl Subscribe {
+    /// Creates a new `Subscribe` command to listen on the specified channels.
+    pub(crate) fn new(channels: Vec<String>) -> Subscribe {
+        Subscribe { channels }
+    }
+
+    /// Parse a `Subscribe` instance from a received frame.
+    ///
+    /// The `SUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing two or more entries.
+    ///
+    /// ```text
+    /// SUBSCRIBE channel [channel ...]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> crate::Result<Subscribe> {
+        // The first channel is required, the others are optional.
+        let mut channels = vec![parse.next_string()?];
+
+        loop {
+            match parse.next_string() {
+                Ok(s) => channels.push(s),
+                Err(Error::EndOfStream) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Subscribe { channels })
+    }
+
+    /// Apply the `Subscribe` command.
+    ///
+    /// This function is the entry point and includes the initial list of
+    /// channels to subscribe to. Additional `subscribe` and `unsubscribe`
+    /// commands may be received from the client and the list of subscriptions
+    /// are updated accordingly.
+    pub(crate) async fn apply(
+        mut self,
+        node: &Node,
+        dst: &mut Connection,
+        session: &mut Session,
+        shutdown: &mut Shutdown,
+    ) -> crate::Result<()> {
+        // Each individual channel subscription is handled using a
+        // `sync::broadcast` channel. Messages are then fanned out to all
+        // clients currently subscribed to the channels.
+        //
+        // A `StreamMap` is used to track active subscriptions. Messages from
+        // all subscriptions are merged into one stream.
+        let mut subscriptions = StreamMap::new();
+
+        loop {
+            // `self.channels` is used to track additional channels to subscribe
+            // to. When new `SUBSCRIBE` commands are received during the
+            // execution of `apply`, the new channels are pushed onto this vec.
+            for channel_name in self.channels.drain(..) {
+                subscribe_to_channel(channel_name, &mut subscriptions, node, dst).await?;
+            }
+
+            // Wait for one of the following to happen:
+            //
+            // - Receive a message from one of the subscribed channels.
+            // - Receive a subscribe or unsubscribe command from the client.
+            // - A server shutdown signal.
+            select! {
+                // Receive messages from subscribed channels
+                Some((channel_name, msg)) = subscriptions.next() => {
+                    dst.write_frame(&make_message_frame(channel_name, msg)).await?;
+                }
+                res = dst.read_frame() => {
+                    let frame = match res? {
+                        Some(frame) => frame,
+                        // This happens if the remote client has disconnected.
+                        None => return Ok(())
+                    };
+
+                    handle_command(
+                        frame,
+                        &mut self.channels,
+                        &mut subscriptions,
+                        node,
+                        dst,
+                        session,
+                        shutdown,
+                    ).await?;
+                }
+                _ = shutdown.recv() => {
+                    return Ok(());
+                }
+            };
+        }
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("subscribe".as_bytes()));
+        for channel in self.channels {
+            frame.push_bulk(Bytes::from(channel.into_bytes()));
+        }
+        frame
+    }
+}
+
+async fn subscribe_to_channel(
+    channel_name: String,
+    subscriptions: &mut StreamMap<String, Messages>,
+    node: &Node,
+    dst: &mut Connection,
+) -> crate::Result<()> {
+    let mut rx = node.subscribe(channel_name.clone());
+
+    // Subscribe to the channel.
+    let rx = Box::pin(async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => yield msg,
+                // If we lagged in consuming messages, just resume.
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(_) => break,
+            }
+        }
+    });
+
+    // Track subscription in this client's subscription set.
+    subscriptions.insert(channel_name.clone(), rx);
+
+    // Respond with the successful subscription
+    let response = make_subscribe_frame(channel_name, subscriptions.len());
+    dst.write_frame(&response).await?;
+
+    Ok(())
+}
+
+/// Handle a command received while inside `Subscribe::apply`. On RESP2
+/// connections only subscribe and unsubscribe commands are permitted in this
+/// context; RESP3 connections may issue any command.
+///
+/// Any new subscriptions are appended to `subscribe_to` instead of modifying
+/// `subscriptions`.
+#[allow(clippy::too_many_arguments)]
+async fn handle_command(
+    frame: Frame,
+    subscribe_to: &mut Vec<String>,
+    subscriptions: &mut StreamMap<String, Messages>,
+    node: &Node,
+    dst: &mut Connection,
+    session: &mut Session,
+    shutdown: &mut Shutdown,
+) -> crate::Result<()> {
+    // A command has been received from the client.
+    let args = node.slowlog().capture(&frame);
+    match Command::from_frame(frame)? {
+        Command::Subscribe(subscribe) => {
+            // The `apply` method will subscribe to the channels we add to this
+            // vector.
+            subscribe_to.extend(subscribe.channels);
+        }
+        Command::Unsubscribe(mut unsubscribe) => {
+            // If no channels are specified, this requests unsubscribing from
+            // **all** channels. To implement this, the `unsubscribe.channels`
+            // vec is populated with the list of channels currently subscribed
+            // to.
+            if unsubscribe.channels.is_empty() {
+                unsubscribe.channels = subscriptions
+                    .keys()
+                    .map(|channel_name| channel_name.to_string())
+                    .collect();
+            }
+
+            for channel_name in unsubscribe.channels {
+                subscriptions.remove(&channel_name);
+
+                let response = make_unsubscribe_frame(channel_name, subscriptions.len());
+                dst.write_frame(&response).await?;
+            }
+        }
+        // Replies of RESP3 connections can not be confused with the pushed
+        // messages, so other commands run as usual.
+        command if dst.protocol() >= 3 => {
+            Box::pin(command.apply(node, dst, session, shutdown, args)).await?;
+        }
+        command => {
+            let cmd = Unknown::new(command.get_name());
+            cmd.apply(dst).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates the response to a subcribe request.
+///
+/// All of these functions take the `channel_name` as a `String` instead of
+/// a `&str` since `Bytes::from` can reuse the allocation in the `String`, and
+/// taking a `&str` would require copying the data. This allows the caller to
+/// decide whether to clone the channel name or not.
+fn make_subscribe_frame(channel_name: String, num_subs: usize) -> Frame {
+    let mut response = Frame::Push(vec![]);
+    response.push_bulk(Bytes::from_static(b"subscribe"));
+    response.push_bulk(Bytes::from(channel_name));
+    response.push_int(num_subs as i64);
+    response
+}
+
+/// Creates the response to an unsubcribe request.
+fn make_unsubscribe_frame(channel_name: String, num_subs: usize) -> Frame {
+    let mut response = Frame::Push(vec![]);
+    response.push_bulk(Bytes::from_static(b"unsubscribe"));
+    response.push_bulk(Bytes::from(channel_name));
+    response.push_int(num_subs as i64);
+    response
+}
+
+/// Creates a message informing the client about a new message on a channel
+/// that the client subscribes to.
+///
+/// Pub/sub replies are push frames, which RESP2 connections receive as plain
+/// arrays.
+fn make_message_frame(channel_name: String, msg: Bytes) -> Frame {
+    let mut response = Frame::Push(vec![]);
+    response.push_bulk(Bytes::from_static(b"message"));
+    response.push_bulk(Bytes::from(channel_name));
+    response.push_bulk(msg);
+    response
+}
+
+impl Unsubscribe {
+    /// Create a new `Unsubscribe` command with the given `channels`.
+    pub(crate) fn new(channels: &[String]) -> Unsubscribe {
+        Unsubscribe {
+            channels: channels.to_vec(),
+        }
+    }
+
+    /// Parse an `Unsubscribe` instance from a received frame.
+    ///
+    /// The `UNSUBSCRIBE` string has already been consumed.
+    ///
+    /// # Format
+    ///
+    /// Expects an array frame containing at least one entry.
+    ///
+    /// ```text
+    /// UNSUBSCRIBE [channel [channel ...]]
+    /// ```
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Unsubscribe, Error> {
+        // There may be no channels listed, so start with an empty vec.
+        let mut channels = vec![];
+
+        // Each entry in the frame must be a string or the frame is malformed.
+        // Once all values in the frame have been consumed, the command is
+        // fully parsed.
+        loop {
+            match parse.next_string() {
+                // A string has been consumed from the `parse`, push it into the
+                // list of channels to unsubscribe from.
+                Ok(s) => channels.push(s),
+                // The `EndOfStream` error indicates there is no further data to
+                // parse.
+                Err(Error::EndOfStream) => break,
+                // All other errors are bubbled up, resulting in the connection
+                // being terminated.
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Unsubscribe { channels })
+    }
+
+    /// Apply the `Unsubscribe` command to a connection that is not subscribed
+    /// to any channel.
+    ///
+    /// As with Redis, each channel is acknowledged with a count of zero
+    /// subscriptions. Without channels a single reply with a null channel is
+    /// written.
+    pub(crate) async fn apply(self, dst: &mut Connection) -> crate::Result<()> {
+        if self.channels.is_empty() {
+            let response = Frame::Push(vec![
+                Frame::Bulk(Bytes::from_static(b"unsubscribe")),
+                Frame::Null,
+                Frame::Integer(0),
+            ]);
+            dst.write_frame(&response).await?;
+        }
+
+        for channel_name in self.channels {
+            let response = make_unsubscribe_frame(channel_name, 0);
+            dst.write_frame(&response).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts the command into an equivalent `Frame`.
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from("unsubscribe".as_bytes()));
+
+        for channel in self.channels {
+            frame.push_bulk(Bytes::from(channel.into_bytes()));
+        }
+
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    async fn pair() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        (Connection::new(server), Connection::new(client))
+    }
+
+    async fn unsubscribe(channels: &[&str]) -> Vec<String> {
+        let channels: Vec<String> = channels.iter().map(|channel| channel.to_string()).collect();
+        let (mut server, mut client) = pair().await;
+        Unsubscribe::new(&channels).apply(&mut server).await.unwrap();
+        server.flush().await.unwrap();
+        drop(server);
+
+        let mut replies = vec![];
+        while let Some(frame) = client.read_frame().await.unwrap() {
+            replies.push(format!("{:?}", frame));
+        }
+        replies
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_without_subscriptions() {
+        let reply = |channel: Frame| {
+            let frame = Frame::Array(vec![Frame::Bulk(Bytes::from("unsubscribe")), channel, Frame::Integer(0)]);
+            format!("{:?}", frame)
+        };
+
+        assert_eq!(unsubscribe(&[]).await, vec![reply(Frame::Null)]);
+        assert_eq!(
+            unsubscribe(&["a", "b"]).await,
+            vec![reply(Frame::Bulk(Bytes::from("a"))), reply(Frame::Bulk(Bytes::from("b")))]
+        );
+    }
+}
@@ -1,10 +1,18 @@
+use std::collections::HashSet;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use mineral::flate::Codec;
 use mineral::KvConfig;
 use p2p::P2pConfig;
 use serde::Deserialize;
 
 use crate::error::Error;
+use crate::frame::FrameLimits;
+
+/// Name of namespace 0, which always exists.
+pub(crate) const DEFAULT_NAMESPACE: &str = "default";
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Config {
@@ -14,10 +22,268 @@ pub struct Config {
     pub genesis_file: String,
     /// The address to listen on for HTTP Server.
     pub http_addr: String,
+    /// The address of the HTTP listener serving Prometheus metrics on
+    /// `/metrics`. Metrics are not served if not set.
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
     /// The miner account to receive mining rewards.
     pub author: String,
     /// P2p configuration.
     pub p2p: P2pConfig,
+    /// Storage engine settings shared by all namespaces.
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Additional logical databases, selectable with `SELECT`.
+    #[serde(default)]
+    pub namespaces: Vec<NamespaceConfig>,
+    /// Pub/sub settings.
+    #[serde(default)]
+    pub pubsub: PubSubConfig,
+    /// Authentication and access control.
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// TLS for client connections. Plain TCP is used if not set.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Slow command log, read with `SLOWLOG GET`.
+    #[serde(default)]
+    pub slowlog: SlowLogConfig,
+    /// Limits on client connections and the requests they send.
+    #[serde(default)]
+    pub limits: LimitsConfig,
+}
+
+/// Storage engine settings, mapped onto `mineral::KvConfig`.
+///
+/// File paths are not configured here; each namespace stores its files under
+/// its own directory inside `data_dir`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Size of a data block in bytes, including the block header.
+    pub block_size: usize,
+    /// Maximum size of a change buffer page in bytes.
+    pub page_max_cap: usize,
+    /// Size of the slot cache in bytes.
+    pub cache_cap: usize,
+    /// Number of hash slots. Must be a power of two and can not be changed
+    /// once data has been written.
+    pub slot_qty: u32,
+    /// Free block ratio that triggers background compaction, 0 disables it.
+    pub compact_threshold: f64,
+    /// Pending change buffer bytes above which writes wait for a flush, 0
+    /// disables the limit.
+    pub cbf_high_water: usize,
+    /// Milliseconds a write waits for the flush before it is rejected.
+    pub cbf_stall_ms: u64,
+    /// Values larger than this are compressed, 0 disables compression.
+    pub compress_threshold: usize,
+    /// Compression codec, one of `none`, `gzip` or `lz4`.
+    pub compress_codec: Codec,
+    /// Values larger than this are stored in the value log, 0 disables it.
+    pub vlog_threshold: usize,
+    /// Maximum size of a value log segment in bytes, 0 uses the default.
+    pub vlog_segment_size: u64,
+    /// Dead data ratio a value log segment needs before it is collected.
+    pub vlog_gc_ratio: f64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            block_size: 1024,
+            page_max_cap: 1024 * 1024 * 50,
+            cache_cap: 1024 * 1024 * 50,
+            slot_qty: 16384,
+            compact_threshold: 0.0,
+            cbf_high_water: 0,
+            cbf_stall_ms: 0,
+            compress_threshold: 0,
+            compress_codec: Codec::None,
+            vlog_threshold: 0,
+            vlog_segment_size: 0,
+            vlog_gc_ratio: 0.0,
+        }
+    }
+}
+
+impl StorageConfig {
+    /// Builds the store configuration for a namespace with files under `dir`.
+    ///
+    /// `cache_cap` overrides the configured cache size when set.
+    pub fn kv_config(&self, dir: &Path, cache_cap: Option<usize>) -> KvConfig {
+        KvConfig {
+            storage: mineral::StorageConfig {
+                path: dir.join("data").to_string_lossy().into_owned(),
+                block_size: self.block_size,
+                page_max_cap: self.page_max_cap,
+                compact_threshold: self.compact_threshold,
+                cbf_high_water: self.cbf_high_water,
+                cbf_stall_ms: self.cbf_stall_ms,
+            },
+            wal_path: dir.join("log").to_string_lossy().into_owned(),
+            cache_cap: cache_cap.unwrap_or(self.cache_cap),
+            slot_qty: self.slot_qty,
+            compress_threshold: self.compress_threshold,
+            compress_codec: self.compress_codec,
+            vlog_threshold: self.vlog_threshold,
+            vlog_segment_size: self.vlog_segment_size,
+            vlog_gc_ratio: self.vlog_gc_ratio,
+        }
+    }
+}
+
+/// Settings of one logical database.
+///
+/// Each namespace is backed by its own `HashKv`, so keys, cache and files are
+/// isolated from the other namespaces. The `default` namespace always exists
+/// as database 0; an entry with that name only overrides its settings.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NamespaceConfig {
+    /// The name used to select the namespace.
+    pub name: String,
+    /// Size of the slot cache in bytes. Uses `storage.cache_cap` if not set.
+    #[serde(default)]
+    pub cache_cap: Option<usize>,
+    /// Expiration in seconds applied to keys set without `EX` or `PX`.
+    #[serde(default)]
+    pub default_ttl: Option<u64>,
+}
+
+/// Pub/sub settings.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PubSubConfig {
+    /// Gossipsub topic used to bridge `PUBLISH` to the other nodes. When set,
+    /// every message published on this node is also delivered to the
+    /// subscribers connected to the other nodes joined to the topic.
+    #[serde(default)]
+    pub bridge_topic: Option<String>,
+}
+
+/// Slow log settings.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SlowLogConfig {
+    /// Commands running longer than this many microseconds are logged. 0 logs
+    /// every command, a negative value disables the slow log.
+    pub log_slower_than: i64,
+    /// Number of entries kept, the oldest are dropped first.
+    pub max_len: usize,
+}
+
+impl Default for SlowLogConfig {
+    fn default() -> Self {
+        SlowLogConfig {
+            log_slower_than: 10_000,
+            max_len: 128,
+        }
+    }
+}
+
+/// Limits on client connections.
+///
+/// A client exceeding them gets an error reply and is disconnected.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    /// Maximum number of concurrent client connections.
+    pub max_clients: usize,
+    /// Seconds a client may stay idle before it is disconnected, 0 disables
+    /// the timeout. Subscribers are never disconnected.
+    pub timeout: u64,
+    /// Largest bulk string a request may contain, in bytes.
+    pub max_bulk_len: usize,
+    /// Deepest nesting of arrays a request may contain. A plain command has
+    /// depth 1.
+    pub max_array_depth: usize,
+    /// Most elements an array in a request may contain, including the
+    /// command name.
+    pub max_array_len: usize,
+    /// Largest request, in bytes.
+    pub max_request_len: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let frame = FrameLimits::default();
+        LimitsConfig {
+            max_clients: 250,
+            timeout: 0,
+            max_bulk_len: frame.max_bulk_len,
+            max_array_depth: frame.max_array_depth,
+            max_array_len: frame.max_array_len,
+            max_request_len: frame.max_request_len,
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// Returns the limits enforced on the frames clients send.
+    pub fn frame_limits(&self) -> FrameLimits {
+        FrameLimits {
+            max_bulk_len: self.max_bulk_len,
+            max_array_depth: self.max_array_depth,
+            max_array_len: self.max_array_len,
+            max_request_len: self.max_request_len,
+        }
+    }
+
+    /// Returns how long a client may stay idle, `None` if it may forever.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+}
+
+/// TLS settings of the listener on `http_addr`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate chain.
+    pub cert_file: String,
+    /// PEM file with the private key of the server certificate.
+    pub key_file: String,
+    /// PEM file with the CA certificates trusted to sign client certificates.
+    /// When set, clients must present a certificate (mutual TLS).
+    #[serde(default)]
+    pub client_ca_file: Option<String>,
+}
+
+/// Authentication and access control settings.
+///
+/// Authentication is required once `requirepass` or any user is configured.
+/// Without either, every connection may run every command.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AuthConfig {
+    /// Password of the `default` user, which may run every command on every
+    /// key. Clients authenticate as it with `AUTH <password>`.
+    #[serde(default)]
+    pub requirepass: Option<String>,
+    /// Additional users, authenticated with `AUTH <username> <password>`.
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+}
+
+/// A user and the commands and keys it may access.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserConfig {
+    /// The name passed to `AUTH`.
+    pub name: String,
+    /// The password passed to `AUTH`.
+    pub password: String,
+    /// Commands the user may run, `*` for all. A name prefixed with `-` is
+    /// denied even if `*` allows it.
+    #[serde(default = "match_all")]
+    pub commands: Vec<String>,
+    /// Glob patterns of the keys the user may read and write. `*` matches any
+    /// sequence of characters and `?` a single one.
+    #[serde(default = "match_all")]
+    pub keys: Vec<String>,
+}
+
+fn match_all() -> Vec<String> {
+    vec!["*".to_string()]
 }
 
 impl Config {
@@ -27,6 +293,149 @@ impl Config {
             fs::read_to_string(path).map_err(|_| Error::ConfigNotExist(path.to_string()))?;
 
         let config: Config = toml::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
+
+    /// Returns the directory holding the files of namespace `name`.
+    ///
+    /// The default namespace uses `data_dir` itself, so data directories
+    /// created before namespaces existed open unchanged.
+    pub fn namespace_dir(&self, name: &str) -> PathBuf {
+        if name == DEFAULT_NAMESPACE {
+            PathBuf::from(&self.data_dir)
+        } else {
+            Path::new(&self.data_dir).join("ns").join(name)
+        }
+    }
+
+    /// Returns the p2p settings with the pub/sub bridge topic added to the
+    /// gossipsub topics, so the node joins it on startup.
+    pub fn p2p_config(&self) -> P2pConfig {
+        let mut p2p = self.p2p.clone();
+        if let Some(topic) = &self.pubsub.bridge_topic {
+            if !p2p.pubsub_topics.contains(topic) {
+                p2p.pubsub_topics.push(topic.clone());
+            }
+        }
+        p2p
+    }
+
+    /// Returns the effective settings as `CONFIG GET` parameters, named by
+    /// section like `storage.block_size`. Unset options have an empty value.
+    ///
+    /// Passwords and the p2p secret are left out.
+    pub fn parameters(&self) -> Vec<(String, String)> {
+        fn opt<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+
+        let storage = &self.storage;
+        let req_resp = self.p2p.req_resp.clone().unwrap_or_default();
+        let mut params = vec![
+            ("data_dir", self.data_dir.clone()),
+            ("genesis_file", self.genesis_file.clone()),
+            ("http_addr", self.http_addr.clone()),
+            ("metrics_addr", opt(&self.metrics_addr)),
+            ("author", self.author.clone()),
+            ("p2p.addr", self.p2p.addr.clone()),
+            ("p2p.boot_node", opt(&self.p2p.boot_node)),
+            ("p2p.discovery_interval", opt(&self.p2p.discovery_interval)),
+            ("p2p.pubsub_topics", self.p2p.pubsub_topics.join(",")),
+            ("p2p.req_resp.connection_keep_alive", opt(&req_resp.connection_keep_alive)),
+            ("p2p.req_resp.request_timeout", opt(&req_resp.request_timeout)),
+            ("p2p.req_resp.max_request_size", opt(&req_resp.max_request_size)),
+            ("p2p.req_resp.max_response_size", opt(&req_resp.max_response_size)),
+            ("storage.block_size", storage.block_size.to_string()),
+            ("storage.page_max_cap", storage.page_max_cap.to_string()),
+            ("storage.cache_cap", storage.cache_cap.to_string()),
+            ("storage.slot_qty", storage.slot_qty.to_string()),
+            ("storage.compact_threshold", storage.compact_threshold.to_string()),
+            ("storage.cbf_high_water", storage.cbf_high_water.to_string()),
+            ("storage.cbf_stall_ms", storage.cbf_stall_ms.to_string()),
+            ("storage.compress_threshold", storage.compress_threshold.to_string()),
+            ("storage.compress_codec", format!("{:?}", storage.compress_codec).to_lowercase()),
+            ("storage.vlog_threshold", storage.vlog_threshold.to_string()),
+            ("storage.vlog_segment_size", storage.vlog_segment_size.to_string()),
+            ("storage.vlog_gc_ratio", storage.vlog_gc_ratio.to_string()),
+            ("pubsub.bridge_topic", opt(&self.pubsub.bridge_topic)),
+            ("slowlog.log_slower_than", self.slowlog.log_slower_than.to_string()),
+            ("slowlog.max_len", self.slowlog.max_len.to_string()),
+            ("limits.max_clients", self.limits.max_clients.to_string()),
+            ("limits.timeout", self.limits.timeout.to_string()),
+            ("limits.max_bulk_len", self.limits.max_bulk_len.to_string()),
+            ("limits.max_array_depth", self.limits.max_array_depth.to_string()),
+            ("limits.max_array_len", self.limits.max_array_len.to_string()),
+            ("limits.max_request_len", self.limits.max_request_len.to_string()),
+            ("tls.cert_file", opt(&self.tls.as_ref().map(|tls| tls.cert_file.clone()))),
+            ("tls.key_file", opt(&self.tls.as_ref().map(|tls| tls.key_file.clone()))),
+            ("tls.client_ca_file", opt(&self.tls.as_ref().and_then(|tls| tls.client_ca_file.clone()))),
+            (
+                "auth.users",
+                self.auth.users.iter().map(|user| user.name.as_str()).collect::<Vec<_>>().join(","),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect::<Vec<_>>();
+
+        for ns in &self.namespaces {
+            params.push((format!("namespaces.{}.cache_cap", ns.name), opt(&ns.cache_cap)));
+            params.push((format!("namespaces.{}.default_ttl", ns.name), opt(&ns.default_ttl)));
+        }
+        params
+    }
+
+    /// Checks the storage settings, that namespace names are unique and can
+    /// not be mistaken for a database index, that user names are unique and
+    /// that the connection limits let clients in.
+    fn validate(&self) -> Result<(), Error> {
+        let dir = Path::new(&self.data_dir);
+        self.storage.kv_config(dir, None).validate()?;
+
+        let mut names = HashSet::new();
+        for ns in &self.namespaces {
+            if ns.name.is_empty() || ns.name.parse::<usize>().is_ok() {
+                return Err(Error::InvalidNamespace(format!("invalid name {:?}", ns.name)));
+            }
+            if !names.insert(ns.name.as_str()) {
+                return Err(Error::InvalidNamespace(format!("duplicate name {:?}", ns.name)));
+            }
+            self.storage.kv_config(dir, ns.cache_cap).validate()?;
+        }
+
+        let mut users = HashSet::new();
+        if let Some(password) = &self.auth.requirepass {
+            if password.is_empty() {
+                return Err(Error::InvalidUser("requirepass is empty".to_string()));
+            }
+            users.insert(crate::acl::DEFAULT_USER);
+        }
+        for user in &self.auth.users {
+            if user.name.is_empty() || user.password.is_empty() {
+                return Err(Error::InvalidUser(format!("user {:?} needs a name and a password", user.name)));
+            }
+            if !users.insert(user.name.as_str()) {
+                return Err(Error::InvalidUser(format!("duplicate name {:?}", user.name)));
+            }
+        }
+
+        let limits = &self.limits;
+        if limits.max_clients == 0 {
+            return Err(Error::InvalidLimit("max_clients must be at least 1".to_string()));
+        }
+        if limits.max_bulk_len == 0 {
+            return Err(Error::InvalidLimit("max_bulk_len must be at least 1".to_string()));
+        }
+        if limits.max_array_depth == 0 {
+            return Err(Error::InvalidLimit("max_array_depth must be at least 1".to_string()));
+        }
+        if limits.max_array_len == 0 {
+            return Err(Error::InvalidLimit("max_array_len must be at least 1".to_string()));
+        }
+        if limits.max_request_len < limits.max_bulk_len {
+            return Err(Error::InvalidLimit("max_request_len must be at least max_bulk_len".to_string()));
+        }
+        Ok(())
+    }
 }
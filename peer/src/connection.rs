@@ -1,5 +1,6 @@
 use crate::error::Error;
-use crate::frame::{self, Frame};
+use crate::frame::{self, Frame, FrameLimits};
+use crate::tls::Stream;
 
 use bytes::{Buf, BytesMut};
 use std::io::{self, Cursor};
@@ -10,39 +11,78 @@ use tokio::net::TcpStream;
 ///
 /// When implementing networking protocols, a message on that protocol is
 /// often composed of several smaller messages known as frames. The purpose of
-/// `Connection` is to read and write frames on the underlying `TcpStream`.
+/// `Connection` is to read and write frames on the underlying `TcpStream`,
+/// which may be wrapped in TLS.
 ///
 /// To read frames, the `Connection` uses an internal buffer, which is filled
 /// up until there are enough bytes to create a full frame. Once this happens,
 /// the `Connection` creates the frame and returns it to the caller.
 ///
 /// When sending frames, the frame is first encoded into the write buffer.
-/// The contents of the write buffer are then written to the socket.
+/// The contents of the write buffer are written to the socket once the
+/// connection waits for more data to read, so the replies to a batch of
+/// pipelined requests go out together.
 #[derive(Debug)]
 pub struct Connection {
-    // The `TcpStream`. It is decorated with a `BufWriter`, which provides write
-    // level buffering. The `BufWriter` implementation provided by Tokio is
-    // sufficient for our needs.
-    stream: BufWriter<TcpStream>,
+    // The `TcpStream` or its TLS session. It is decorated with a `BufWriter`,
+    // which provides write level buffering. The `BufWriter` implementation
+    // provided by Tokio is sufficient for our needs.
+    stream: BufWriter<Stream>,
 
     // The buffer for reading frames.
     buffer: BytesMut,
+
+    // The RESP version used to encode frames, 2 until the client negotiates
+    // protocol 3 with `HELLO`.
+    protocol: u8,
+
+    // Limits on the frames read from the peer.
+    limits: FrameLimits,
 }
 
 impl Connection {
     /// Create a new `Connection`, backed by `socket`. Read and write buffers
     /// are initialized.
     pub fn new(socket: TcpStream) -> Connection {
+        // Frames are already batched by the write buffer. Waiting for more
+        // data before sending a partial segment would only delay the replies
+        // of a pipeline, so Nagle's algorithm is disabled.
+        let _ = socket.set_nodelay(true);
+
+        Connection::with_stream(Stream::Tcp(socket))
+    }
+
+    /// Create a new `Connection` on an established, possibly encrypted,
+    /// `stream`.
+    pub(crate) fn with_stream(stream: Stream) -> Connection {
         Connection {
-            stream: BufWriter::new(socket),
+            stream: BufWriter::new(stream),
             // Default to a 4KB read buffer. For the use case of mini redis,
             // this is fine. However, real applications will want to tune this
             // value to their specific use case. There is a high likelihood that
             // a larger read buffer will work better.
             buffer: BytesMut::with_capacity(4 * 1024),
+            protocol: 2,
+            limits: FrameLimits::default(),
         }
     }
 
+    /// Returns the RESP version used to encode frames.
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    /// Switches the RESP version used to encode frames written from now on.
+    pub(crate) fn set_protocol(&mut self, protocol: u8) {
+        self.protocol = protocol;
+    }
+
+    /// Sets the limits on the frames read from now on. A frame exceeding them
+    /// makes `read_frame` fail with `Error::Protocol`.
+    pub(crate) fn set_limits(&mut self, limits: FrameLimits) {
+        self.limits = limits;
+    }
+
     /// Read a single `Frame` value from the underlying stream.
     ///
     /// The function waits until it has retrieved enough data to parse a frame.
@@ -62,6 +102,17 @@ impl Connection {
                 return Ok(Some(frame));
             }
 
+            // What is left is the start of a frame. Stop buffering it once
+            // it outgrows the limit, it could otherwise grow without bound.
+            if self.buffer.len() > self.limits.max_request_len {
+                return Err(Error::Protocol("request too large".to_string()));
+            }
+
+            // Every frame already received has been handled. Send the
+            // buffered replies before waiting for the peer; a client only
+            // sends more once it has seen them.
+            self.stream.flush().await?;
+
             // There is not enough buffered data to read a frame. Attempt to
             // read more data from the socket.
             //
@@ -97,7 +148,7 @@ impl Connection {
         // parse of the frame, and allows us to skip allocating data structures
         // to hold the frame data unless we know the full frame has been
         // received.
-        match Frame::check(&mut buf) {
+        match Frame::check(&mut buf, &self.limits) {
             Ok(_) => {
                 // The `check` function will have advanced the cursor until the
                 // end of the frame. Since the cursor had position set to zero
@@ -152,35 +203,28 @@ impl Connection {
     /// syscalls. However, it is fine to call these functions on a *buffered*
     /// write stream. The data will be written to the buffer. Once the buffer is
     /// full, it is flushed to the underlying socket.
+    ///
+    /// The frame is not flushed. It is sent by the next call to `read_frame`
+    /// that has to wait for the socket, or by an explicit `flush`.
     pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
-        // Arrays are encoded by encoding each entry. All other frame types are
-        // considered literals. For now, peer is not able to encode
-        // recursive frame structures. See below for more details.
-        match frame {
-            Frame::Array(val) => {
-                // Encode the frame type prefix. For an array, it is `*`.
-                self.stream.write_u8(b'*').await?;
-
-                // Encode the length of the array.
-                self.write_decimal(val.len() as u64).await?;
-
-                // Iterate and encode each entry in the array.
-                for entry in &**val {
-                    self.write_value(entry).await?;
-                }
-            }
-            // The frame type is a literal. Encode the value directly.
-            _ => self.write_value(frame).await?,
-        }
+        self.write_value(frame).await
+    }
 
-        // Ensure the encoded frame is written to the socket. The calls above
-        // are to the buffered stream and writes. Calling `flush` writes the
-        // remaining contents of the buffer to the socket.
+    /// Write all buffered frames to the socket.
+    pub async fn flush(&mut self) -> io::Result<()> {
         self.stream.flush().await
     }
 
-    /// Write a frame literal to the stream
+    /// Write a frame to the stream.
+    ///
+    /// Aggregate frames are encoded by writing their length followed by each
+    /// entry. On RESP2 connections the RESP3 types are converted the same way
+    /// Redis does: maps become flat arrays of keys and values, sets and pushes
+    /// become arrays, booleans become integers, attributes are dropped and the
+    /// remaining types are sent as bulk strings.
     async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
+        let resp3 = self.protocol >= 3;
+
         match frame {
             Frame::Simple(val) => {
                 self.stream.write_u8(b'+').await?;
@@ -196,29 +240,105 @@ impl Connection {
                 self.stream.write_u8(b':').await?;
                 self.write_decimal(*val).await?;
             }
+            Frame::Null if resp3 => {
+                self.stream.write_all(b"_\r\n").await?;
+            }
             Frame::Null => {
                 self.stream.write_all(b"$-1\r\n").await?;
             }
-            Frame::Bulk(val) => {
-                let len = val.len();
+            Frame::Bulk(val) => self.write_bulk(b'$', val).await?,
+            Frame::Array(val) => self.write_items(b'*', val).await?,
+            Frame::Set(val) => self.write_items(if resp3 { b'~' } else { b'*' }, val).await?,
+            Frame::Push(val) => self.write_items(if resp3 { b'>' } else { b'*' }, val).await?,
+            Frame::Map(entries) if resp3 => self.write_entries(b'%', entries).await?,
+            Frame::Map(entries) => {
+                self.stream.write_u8(b'*').await?;
+                self.write_decimal(entries.len() as i64 * 2).await?;
 
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
+                for (key, value) in entries {
+                    Box::pin(self.write_value(key)).await?;
+                    Box::pin(self.write_value(value)).await?;
+                }
+            }
+            Frame::Double(val) if resp3 => {
+                self.stream.write_u8(b',').await?;
+                self.stream.write_all(format_double(*val).as_bytes()).await?;
+                self.stream.write_all(b"\r\n").await?;
+            }
+            Frame::Double(val) => {
+                self.write_bulk(b'$', format_double(*val).as_bytes()).await?;
+            }
+            Frame::Boolean(val) if resp3 => {
+                self.stream.write_all(if *val { b"#t\r\n" } else { b"#f\r\n" }).await?;
+            }
+            Frame::Boolean(val) => {
+                self.stream.write_u8(b':').await?;
+                self.write_decimal(*val as i64).await?;
+            }
+            Frame::BigNumber(val) if resp3 => {
+                self.stream.write_u8(b'(').await?;
+                self.stream.write_all(val.as_bytes()).await?;
                 self.stream.write_all(b"\r\n").await?;
             }
-            // Encoding an `Array` from within a value cannot be done using a
-            // recursive strategy. In general, async fns do not support
-            // recursion. peer has not needed to encode nested arrays yet,
-            // so for now it is skipped.
-            Frame::Array(_val) => unreachable!(),
+            Frame::BigNumber(val) => self.write_bulk(b'$', val.as_bytes()).await?,
+            Frame::Verbatim(format, text) if resp3 => {
+                let mut data = Vec::with_capacity(format.len() + 1 + text.len());
+                data.extend_from_slice(format.as_bytes());
+                data.push(b':');
+                data.extend_from_slice(text);
+
+                self.write_bulk(b'=', &data).await?;
+            }
+            Frame::Verbatim(_, text) => self.write_bulk(b'$', text).await?,
+            Frame::Attribute(attrs, value) => {
+                if resp3 {
+                    self.write_entries(b'|', attrs).await?;
+                }
+                Box::pin(self.write_value(value)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a length-prefixed string with the given type prefix.
+    async fn write_bulk(&mut self, prefix: u8, val: &[u8]) -> io::Result<()> {
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(val.len() as i64).await?;
+        self.stream.write_all(val).await?;
+        self.stream.write_all(b"\r\n").await?;
+
+        Ok(())
+    }
+
+    /// Write an aggregate frame with the given type prefix. Entries are
+    /// written recursively, so they may be aggregates themselves.
+    async fn write_items(&mut self, prefix: u8, items: &[Frame]) -> io::Result<()> {
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(items.len() as i64).await?;
+
+        for item in items {
+            Box::pin(self.write_value(item)).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the key-value pairs of a map or attribute frame.
+    async fn write_entries(&mut self, prefix: u8, entries: &[(Frame, Frame)]) -> io::Result<()> {
+        self.stream.write_u8(prefix).await?;
+        self.write_decimal(entries.len() as i64).await?;
+
+        for (key, value) in entries {
+            Box::pin(self.write_value(key)).await?;
+            Box::pin(self.write_value(value)).await?;
         }
 
         Ok(())
     }
 
     /// Write a decimal frame to the stream
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
         use std::io::Write;
 
         // Convert the value to a string
@@ -233,3 +353,181 @@ impl Connection {
         Ok(())
     }
 }
+
+/// Formats a double the way RESP3 expects, with `inf`, `-inf` and `nan` for
+/// the special values.
+fn format_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tokio::net::TcpListener;
+
+    /// Returns the server and client ends of a loopback connection.
+    async fn pair() -> (Connection, Connection) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        (Connection::new(server), Connection::new(client))
+    }
+
+    /// Writes `frame` with RESP `protocol` and returns the raw bytes sent.
+    async fn encode(frame: &Frame, protocol: u8) -> Vec<u8> {
+        let (mut server, client) = pair().await;
+        server.set_protocol(protocol);
+        server.write_frame(frame).await.unwrap();
+        server.flush().await.unwrap();
+        drop(server);
+
+        let mut raw = vec![];
+        client.stream.into_inner().read_to_end(&mut raw).await.unwrap();
+        raw
+    }
+
+    /// Writes `frame` with RESP `protocol` and reads it back on the other
+    /// end of the connection.
+    async fn round_trip(frame: &Frame, protocol: u8) -> Frame {
+        let (mut server, mut client) = pair().await;
+        server.set_protocol(protocol);
+        server.write_frame(frame).await.unwrap();
+        server.flush().await.unwrap();
+
+        client.read_frame().await.unwrap().unwrap()
+    }
+
+    fn bulk(value: &str) -> Frame {
+        Frame::Bulk(Bytes::from(value.to_string()))
+    }
+
+    // `Frame` holds doubles, so frames are compared by their debug output.
+    fn assert_frame(actual: Frame, expected: &Frame) {
+        assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    }
+
+    #[tokio::test]
+    async fn resp3_round_trip() {
+        let frames = vec![
+            Frame::Simple("OK".to_string()),
+            Frame::Error("ERR boom".to_string()),
+            Frame::Integer(-42),
+            bulk("hello"),
+            Frame::Null,
+            Frame::Array(vec![bulk("a"), Frame::Integer(1), Frame::Null]),
+            Frame::Map(vec![(bulk("key"), Frame::Array(vec![bulk("nested")]))]),
+            Frame::Set(vec![bulk("a"), bulk("b")]),
+            Frame::Double(1.5),
+            Frame::Double(f64::INFINITY),
+            Frame::Double(f64::NEG_INFINITY),
+            Frame::Boolean(true),
+            Frame::Boolean(false),
+            Frame::BigNumber("-3492890328409238509324850943850943825024385".to_string()),
+            Frame::Verbatim("txt".to_string(), Bytes::from_static(b"Some string")),
+            Frame::Push(vec![bulk("message"), bulk("chan"), bulk("payload")]),
+            Frame::Attribute(vec![(bulk("ttl"), Frame::Integer(3600))], Box::new(bulk("value"))),
+        ];
+
+        for frame in &frames {
+            assert_frame(round_trip(frame, 3).await, frame);
+        }
+
+        // NaN never equals itself, so it is checked separately.
+        match round_trip(&Frame::Double(f64::NAN), 3).await {
+            Frame::Double(val) => assert!(val.is_nan()),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[tokio::test]
+    async fn resp3_encoding() {
+        assert_eq!(encode(&Frame::Null, 3).await, b"_\r\n");
+        assert_eq!(encode(&Frame::Boolean(true), 3).await, b"#t\r\n");
+        assert_eq!(encode(&Frame::Double(f64::NAN), 3).await, b",nan\r\n");
+        assert_eq!(encode(&Frame::Set(vec![Frame::Integer(1)]), 3).await, b"~1\r\n:1\r\n");
+        assert_eq!(
+            encode(&Frame::Map(vec![(bulk("a"), Frame::Integer(1))]), 3).await,
+            b"%1\r\n$1\r\na\r\n:1\r\n"
+        );
+        assert_eq!(
+            encode(&Frame::Verbatim("txt".to_string(), Bytes::from_static(b"hi")), 3).await,
+            b"=6\r\ntxt:hi\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn request_too_large() {
+        let limits = FrameLimits {
+            max_bulk_len: 16,
+            max_request_len: 64,
+            ..FrameLimits::default()
+        };
+
+        // Each bulk string is within its limit, the request as a whole is not.
+        let (mut server, mut client) = pair().await;
+        server.set_limits(limits);
+        client.stream.write_all(b"*10\r\n").await.unwrap();
+        for _ in 0..9 {
+            client.stream.write_all(b"$16\r\n0123456789abcdef\r\n").await.unwrap();
+        }
+        client.flush().await.unwrap();
+        assert!(matches!(server.read_frame().await, Err(Error::Protocol(msg)) if msg == "request too large"));
+
+        // A line that never ends.
+        let (mut server, mut client) = pair().await;
+        server.set_limits(limits);
+        client.stream.write_all(&[b'+'; 100]).await.unwrap();
+        client.flush().await.unwrap();
+        assert!(matches!(server.read_frame().await, Err(Error::Protocol(_))));
+
+        // Complete frames are read even when more than the limit arrives at
+        // once.
+        let (mut server, mut client) = pair().await;
+        server.set_limits(limits);
+        client.stream.write_all(&b"+OK\r\n".repeat(20)).await.unwrap();
+        client.flush().await.unwrap();
+        for _ in 0..20 {
+            assert_frame(server.read_frame().await.unwrap().unwrap(), &Frame::Simple("OK".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn resp2_down_conversion() {
+        let cases = vec![
+            (Frame::Null, Frame::Null),
+            (
+                Frame::Map(vec![(bulk("a"), Frame::Integer(1)), (bulk("b"), Frame::Integer(2))]),
+                Frame::Array(vec![bulk("a"), Frame::Integer(1), bulk("b"), Frame::Integer(2)]),
+            ),
+            (Frame::Set(vec![bulk("a")]), Frame::Array(vec![bulk("a")])),
+            (Frame::Push(vec![bulk("message")]), Frame::Array(vec![bulk("message")])),
+            (Frame::Double(1.5), bulk("1.5")),
+            (Frame::Double(f64::INFINITY), bulk("inf")),
+            (Frame::Boolean(true), Frame::Integer(1)),
+            (Frame::Boolean(false), Frame::Integer(0)),
+            (Frame::BigNumber("12345678901234567890".to_string()), bulk("12345678901234567890")),
+            (Frame::Verbatim("txt".to_string(), Bytes::from_static(b"hi")), bulk("hi")),
+            (
+                Frame::Attribute(vec![(bulk("ttl"), Frame::Integer(1))], Box::new(bulk("value"))),
+                bulk("value"),
+            ),
+            (
+                Frame::Array(vec![Frame::Map(vec![(bulk("k"), Frame::Boolean(true))])]),
+                Frame::Array(vec![Frame::Array(vec![bulk("k"), Frame::Integer(1)])]),
+            ),
+        ];
+
+        for (frame, expected) in &cases {
+            assert_frame(round_trip(frame, 2).await, expected);
+        }
+
+        // RESP2 has no null type of its own; a null bulk string is sent.
+        assert_eq!(encode(&Frame::Null, 2).await, b"$-1\r\n");
+    }
+}
@@ -1,12 +1,24 @@
-use mineral::kv::hash::HashKv;
-use mineral::{KvConfig, StorageConfig};
-use tokio::sync::Notify;
+use mineral::kv::hash::{HashKv, KvStats, SetOptions, SetOutcome};
+use mineral::metrics::Metrics as StorageMetrics;
+use tokio::sync::{broadcast, Notify};
 use tokio::time::Duration;
 
 use bytes::Bytes;
-use std::sync::{Arc, Mutex};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
 
-use crate::config::Config;
+use crate::config::{Config, NamespaceConfig, DEFAULT_NAMESPACE};
+use crate::error::Error;
+
+tokio::task_local! {
+    /// Time the running command spent in the storage engine, including
+    /// waiting for the namespace lock. Only set within `measure_storage`.
+    static STORAGE_TIME: Cell<Duration>;
+}
 
 #[derive(Debug, Clone)]
 pub struct DbDropGuard {
@@ -23,20 +35,39 @@ struct Shared {
 
     state: Mutex<State>,
 
+    /// Logical databases, indexed by the number used with `SELECT`.
+    namespaces: Vec<Namespace>,
+
     background_task: Notify,
 }
 
 #[derive(Debug)]
 struct State {
-    kv: mineral::kv::hash::HashKv,
+    /// The pub/sub key-space. Channels are shared by all namespaces, as in
+    /// Redis.
+    ///
+    /// A separate `HashMap` is used to manage pub/sub. The `HashMap` value is a
+    /// broadcast sender connected to the channel's subscribers.
+    pub_sub: HashMap<String, broadcast::Sender<Bytes>>,
 
     shutdown: bool,
 }
 
+/// A logical database backed by its own `HashKv`.
+#[derive(Debug)]
+struct Namespace {
+    name: String,
+
+    /// Expiration applied to keys set without one.
+    default_ttl: Option<Duration>,
+
+    kv: Mutex<HashKv>,
+}
+
 impl DbDropGuard {
 
-    pub fn new(config: Config) -> DbDropGuard {
-        DbDropGuard { db: Db::new(config) }
+    pub fn new(config: Config) -> crate::Result<DbDropGuard> {
+        Ok(DbDropGuard { db: Db::new(config)? })
     }
 
     pub fn db(&self) -> Db {
@@ -51,39 +82,241 @@ impl Drop for DbDropGuard {
 }
 
 impl Db {
-    pub fn new(config: Config) -> Db {
-        let conf = KvConfig {
-            storage: StorageConfig {
-                path: config.data_dir.clone() + "data",
-                block_size: 1024,
-                page_max_cap: 1024 * 1024 * 50,
-            },
-            wal_path: config.data_dir.clone() + "log",
-            cache_cap: 1024 * 1024 * 50,
-            cbf_cap: 1024 * 1024 * 50,
-            slot_qty: 10000,
-        };
+    /// Opens every namespace of `config`.
+    ///
+    /// Fails if a data directory is locked by another process or was created
+    /// with a different `block_size` or `slot_qty`.
+    pub fn new(config: Config) -> crate::Result<Db> {
+        // The default namespace keeps the original file layout so existing
+        // data directories open unchanged.
+        let default = config
+            .namespaces
+            .iter()
+            .find(|ns| ns.name == DEFAULT_NAMESPACE)
+            .cloned()
+            .unwrap_or_else(|| NamespaceConfig {
+                name: DEFAULT_NAMESPACE.to_string(),
+                ..Default::default()
+            });
+        let mut namespaces = vec![Namespace::open(&config, &default)?];
+        for ns in config.namespaces.iter().filter(|ns| ns.name != DEFAULT_NAMESPACE) {
+            namespaces.push(Namespace::open(&config, ns)?);
+        }
 
         let shared = Arc::new(Shared {
             state: Mutex::new(State {
-                kv: HashKv::new(conf),
+                pub_sub: HashMap::new(),
                 shutdown: false,
             }),
+            namespaces,
             background_task: Notify::new(),
         });
 
-        Db { shared }
+        Ok(Db { shared })
+    }
+
+    /// Resolves a `SELECT` argument, either a database index or a namespace
+    /// name, to the database index.
+    pub fn select(&self, db: &str) -> Option<usize> {
+        match db.parse::<usize>() {
+            Ok(index) if index < self.shared.namespaces.len() => Some(index),
+            Ok(_) => None,
+            Err(_) => self.shared.namespaces.iter().position(|ns| ns.name == db),
+        }
+    }
+
+    /// Returns the names of all namespaces, in database index order.
+    pub fn namespaces(&self) -> Vec<String> {
+        self.shared.namespaces.iter().map(|ns| ns.name.clone()).collect()
+    }
+
+    pub fn get(&self, db: usize, key: &Bytes) -> crate::Result<Option<Bytes>> {
+        let mut kv = self.namespace(db)?.lock();
+        Ok(kv.get(&key.to_vec())?.map(Bytes::from))
+    }
+
+    /// Returns the values of `keys` in database `db`, `None` for missing keys.
+    ///
+    /// All keys are read under one namespace lock.
+    pub fn mget(&self, db: usize, keys: &[Bytes]) -> crate::Result<Vec<Option<Bytes>>> {
+        let mut kv = self.namespace(db)?.lock();
+        keys.iter()
+            .map(|key| Ok(kv.get(&key.to_vec())?.map(Bytes::from)))
+            .collect()
+    }
+
+    /// Stores every key-value pair in database `db` with the namespace
+    /// default TTL.
+    ///
+    /// Other clients never observe a partial update, but pairs written before
+    /// a storage failure are kept.
+    pub fn mset(&self, db: usize, pairs: Vec<(Bytes, Bytes)>) -> crate::Result<()> {
+        let ns = self.namespace(db)?;
+        let mut kv = ns.lock();
+
+        for (key, value) in pairs {
+            kv.setex(&key.to_vec(), &value.to_vec(), ns.default_ttl)?;
+        }
+        Ok(())
+    }
+
+    /// Removes `keys` from database `db` and returns how many existed.
+    pub fn del(&self, db: usize, keys: &[Bytes]) -> crate::Result<u64> {
+        let mut kv = self.namespace(db)?.lock();
+
+        let mut removed = 0;
+        for key in keys {
+            let key = key.to_vec();
+            if kv.exists(&key)? {
+                kv.del(&key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
     }
 
-    pub fn get(&self, key: &Bytes) -> Option<Bytes> {
+    /// Returns how many of `keys` exist in database `db`. A key given twice
+    /// is counted twice.
+    pub fn exists(&self, db: usize, keys: &[Bytes]) -> crate::Result<u64> {
+        let mut kv = self.namespace(db)?.lock();
+
+        let mut found = 0;
+        for key in keys {
+            if kv.exists(&key.to_vec())? {
+                found += 1;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Stores `value` under `key` in database `db` if the condition in
+    /// `opts` holds.
+    ///
+    /// The condition is checked and the value written under the namespace
+    /// lock. Keys set without an expiration use the namespace default TTL.
+    /// Fails with a storage error once the store has switched to read-only
+    /// mode after a write failure.
+    pub fn set(&self, db: usize, key: Bytes, value: Bytes, mut opts: SetOptions) -> crate::Result<SetOutcome> {
+        let ns = self.namespace(db)?;
+        let mut kv = ns.lock();
+
+        opts.expire = opts.expire.or(ns.default_ttl);
+        Ok(kv.set_with(&key.to_vec(), &value.to_vec(), &opts)?)
+    }
+
+    /// Returns the value stored under `key` with its version, as used by
+    /// compare-and-swap.
+    pub fn get_versioned(&self, db: usize, key: &Bytes) -> crate::Result<Option<(Bytes, u64)>> {
+        let mut kv = self.namespace(db)?.lock();
+        Ok(kv.get_versioned(&key.to_vec())?.map(|(value, version)| (Bytes::from(value), version)))
+    }
+
+    /// Adds `delta` to the integer stored under `key` in database `db` and
+    /// returns the new value.
+    ///
+    /// A missing key counts as 0 and gets the namespace default TTL. The
+    /// read-modify-write runs under the namespace lock, so concurrent
+    /// increments are never lost.
+    pub fn incr_by(&self, db: usize, key: &Bytes, delta: i64) -> crate::Result<i64> {
+        let ns = self.namespace(db)?;
+        let mut kv = ns.lock();
+
+        let key = key.to_vec();
+        if ns.default_ttl.is_some() && kv.get(&key)?.is_none() {
+            kv.setex(&key, &delta.to_string().into_bytes(), ns.default_ttl)?;
+            return Ok(delta);
+        }
+        Ok(kv.incr_by(&key, delta)?)
+    }
+
+    /// Returns a `Receiver` for the requested channel.
+    ///
+    /// The returned `Receiver` is used to receive values broadcast by `PUBLISH`
+    /// commands.
+    pub fn subscribe(&self, channel: String) -> broadcast::Receiver<Bytes> {
         let mut state = self.shared.state.lock().unwrap();
-        state.kv.get(&key.to_vec()).map(|data| Bytes::from(data))
+
+        // If there is no entry for the requested channel, then create a new
+        // broadcast channel and associate it with the key. If one already
+        // exists, return an associated receiver.
+        match state.pub_sub.get(&channel) {
+            Some(tx) => tx.subscribe(),
+            None => {
+                // The channel is bounded; a subscriber that falls more than
+                // 1024 messages behind misses the oldest ones.
+                let (tx, rx) = broadcast::channel(1024);
+                state.pub_sub.insert(channel, tx);
+                rx
+            }
+        }
     }
 
-    pub fn set(&self, key: Bytes, value: Bytes, expire: Option<Duration>) {
+    /// Publish a message to the channel. Returns the number of subscribers
+    /// listening on the channel.
+    pub fn publish(&self, channel: &str, value: Bytes) -> usize {
         let mut state = self.shared.state.lock().unwrap();
 
-        state.kv.setnx(&key.to_vec(), &value.to_vec(), expire);
+        match state.pub_sub.get(channel).map(|tx| tx.send(value)) {
+            Some(Ok(receivers)) => receivers,
+            Some(Err(_)) => {
+                // Every subscriber has gone away; drop the channel so the map
+                // does not grow with abandoned channels.
+                state.pub_sub.remove(channel);
+                0
+            }
+            None => 0,
+        }
+    }
+
+    /// Returns space usage statistics of database `db`.
+    ///
+    /// Counting the keys walks every slot, so it is relatively expensive. The
+    /// namespace lock is released before the walk, which locks the store one
+    /// slot at a time, so commands on the namespace are not held up by it.
+    pub fn stats(&self, db: usize) -> crate::Result<KvStats> {
+        let (counter, mut stats) = {
+            let kv = self.namespace(db)?.lock();
+            (kv.key_counter(), kv.usage()?)
+        };
+
+        stats.keys = counter.count()?;
+        Ok(stats)
+    }
+
+    /// Returns the storage metrics of every database, by namespace name.
+    pub(crate) fn storage_metrics(&self) -> Vec<(String, Arc<StorageMetrics>)> {
+        self.shared
+            .namespaces
+            .iter()
+            .map(|ns| (ns.name.clone(), ns.kv.lock().unwrap().metrics()))
+            .collect()
+    }
+
+    /// Returns true if a write failure switched database `db` to read-only
+    /// mode.
+    pub fn is_read_only(&self, db: usize) -> crate::Result<bool> {
+        Ok(self.namespace(db)?.kv.lock().unwrap().is_read_only())
+    }
+
+    /// Flushes all buffered writes to disk and stops the storage background
+    /// threads.
+    ///
+    /// Called once every connection has been closed. Calling it again is a
+    /// no-op.
+    pub fn close(&self) -> crate::Result<()> {
+        self.shutdown_purge_task();
+
+        for ns in &self.shared.namespaces {
+            ns.kv.lock().unwrap().close()?;
+        }
+        Ok(())
+    }
+
+    fn namespace(&self, db: usize) -> crate::Result<&Namespace> {
+        self.shared
+            .namespaces
+            .get(db)
+            .ok_or_else(|| Error::Other("DB index is out of range".into()))
     }
 
     /// Signals the purge background task to shut down. This is called by the
@@ -97,8 +330,206 @@ impl Db {
     }
 }
 
+/// Access to the store of a namespace, counting the time it is held as
+/// storage time of the running command.
+struct KvGuard<'a> {
+    kv: MutexGuard<'a, HashKv>,
+
+    /// When the lock was requested.
+    start: Instant,
+}
+
+impl Namespace {
+    /// Locks the store of the namespace.
+    fn lock(&self) -> KvGuard<'_> {
+        let start = Instant::now();
+        KvGuard {
+            kv: self.kv.lock().unwrap(),
+            start,
+        }
+    }
+
+    /// Opens the store of namespace `ns` using the storage settings of
+    /// `config`.
+    fn open(config: &Config, ns: &NamespaceConfig) -> crate::Result<Namespace> {
+        let dir = config.namespace_dir(&ns.name);
+        let kv = config.storage.kv_config(&dir, ns.cache_cap);
+
+        Ok(Namespace {
+            name: ns.name.clone(),
+            default_ttl: ns.default_ttl.map(Duration::from_secs),
+            kv: Mutex::new(HashKv::open(kv)?),
+        })
+    }
+}
+
+impl Deref for KvGuard<'_> {
+    type Target = HashKv;
+
+    fn deref(&self) -> &HashKv {
+        &self.kv
+    }
+}
+
+impl DerefMut for KvGuard<'_> {
+    fn deref_mut(&mut self) -> &mut HashKv {
+        &mut self.kv
+    }
+}
+
+impl Drop for KvGuard<'_> {
+    fn drop(&mut self) {
+        // Storage accessed outside of a command, e.g. by a background task,
+        // is not counted.
+        let elapsed = self.start.elapsed();
+        let _ = STORAGE_TIME.try_with(|total| total.set(total.get() + elapsed));
+    }
+}
+
+/// Runs `fut` and returns its output with the time it spent in the storage
+/// engine.
+pub(crate) async fn measure_storage<F: Future>(fut: F) -> (F::Output, Duration) {
+    STORAGE_TIME
+        .scope(Cell::new(Duration::ZERO), async {
+            let output = fut.await;
+            (output, STORAGE_TIME.with(Cell::get))
+        })
+        .await
+}
+
+/// Runs the storage call `f` on the blocking thread pool.
+///
+/// A write may wait up to `cbf_stall_ms` for the change buffer to be flushed
+/// while it holds the namespace lock, which must not stall a runtime worker
+/// and every task queued on it. Reads take the same lock and run here too.
+/// The time is counted as storage time of the running command.
+pub(crate) async fn spawn_storage<F, T>(f: F) -> crate::Result<T>
+where
+    F: FnOnce() -> crate::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let start = Instant::now();
+    let res = tokio::task::spawn_blocking(f).await;
+    let _ = STORAGE_TIME.try_with(|total| total.set(total.get() + start.elapsed()));
+    res.map_err(|err| Error::Other(format!("storage task failed: {}", err)))?
+}
+
 impl Shared {
     fn is_shutdown(&self) -> bool {
         self.state.lock().unwrap().shutdown
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageConfig;
+
+    /// Returns a config with a fresh data directory under `name` and the
+    /// extra namespaces `namespaces`.
+    fn test_config(name: &str, namespaces: &[&str]) -> Config {
+        let data_dir = std::env::temp_dir().join("terra/tests/peer-db").join(name);
+        let _ = std::fs::remove_dir_all(&data_dir);
+
+        Config {
+            data_dir: data_dir.to_string_lossy().into_owned(),
+            storage: StorageConfig {
+                page_max_cap: 1024 * 1024,
+                cache_cap: 1024,
+                slot_qty: 1024,
+                ..Default::default()
+            },
+            namespaces: namespaces
+                .iter()
+                .map(|name| NamespaceConfig {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn bytes(value: &str) -> Bytes {
+        Bytes::from(value.to_string())
+    }
+
+    #[test]
+    fn namespace_isolation() {
+        let config = test_config("namespaces", &["cache"]);
+        let db = Db::new(config.clone()).unwrap();
+        assert_eq!(db.namespaces(), vec!["default", "cache"]);
+        assert_eq!(db.select("1"), Some(1));
+        assert_eq!(db.select("cache"), Some(1));
+        assert_eq!(db.select("2"), None);
+        assert!(db.get(2, &bytes("foo")).is_err());
+
+        // The same key holds a separate value in each namespace.
+        db.set(0, bytes("foo"), bytes("zero"), SetOptions::default()).unwrap();
+        db.set(1, bytes("foo"), bytes("one"), SetOptions::default()).unwrap();
+        db.set(1, bytes("bar"), bytes("one"), SetOptions::default()).unwrap();
+        assert_eq!(db.get(0, &bytes("foo")).unwrap(), Some(bytes("zero")));
+        assert_eq!(db.get(1, &bytes("foo")).unwrap(), Some(bytes("one")));
+        assert_eq!(db.exists(0, &[bytes("bar")]).unwrap(), 0);
+
+        assert_eq!(db.del(1, &[bytes("foo")]).unwrap(), 1);
+        assert_eq!(db.get(0, &bytes("foo")).unwrap(), Some(bytes("zero")));
+        assert_eq!(db.incr_by(0, &bytes("counter"), 5).unwrap(), 5);
+        assert_eq!(db.incr_by(1, &bytes("counter"), 1).unwrap(), 1);
+        assert_eq!(db.stats(0).unwrap().keys, 2);
+        assert_eq!(db.stats(1).unwrap().keys, 2);
+        db.close().unwrap();
+        drop(db);
+
+        // Each namespace keeps its files in its own directory.
+        assert!(config.namespace_dir("cache").join("data").exists());
+        let db = Db::new(config).unwrap();
+        assert_eq!(db.get(0, &bytes("foo")).unwrap(), Some(bytes("zero")));
+        assert_eq!(db.get(1, &bytes("foo")).unwrap(), None);
+        assert_eq!(db.get(1, &bytes("bar")).unwrap(), Some(bytes("one")));
+    }
+
+    #[test]
+    fn multi_key_counts() {
+        let db = Db::new(test_config("multi-key", &[])).unwrap();
+        db.mset(0, vec![(bytes("a"), bytes("1")), (bytes("b"), bytes("2")), (bytes("a"), bytes("3"))]).unwrap();
+
+        // The last value of a key given twice wins.
+        assert_eq!(
+            db.mget(0, &[bytes("a"), bytes("b"), bytes("c")]).unwrap(),
+            vec![Some(bytes("3")), Some(bytes("2")), None]
+        );
+
+        // A key given twice is counted twice by EXISTS but removed once.
+        assert_eq!(db.exists(0, &[bytes("a"), bytes("a"), bytes("c")]).unwrap(), 2);
+        assert_eq!(db.del(0, &[bytes("a"), bytes("a"), bytes("c")]).unwrap(), 1);
+        assert_eq!(db.exists(0, &[bytes("a"), bytes("b")]).unwrap(), 1);
+    }
+
+    #[test]
+    fn mset_is_atomic() {
+        let db = Db::new(test_config("mset-atomic", &[])).unwrap();
+        let keys: Vec<Bytes> = (0..8).map(|i| bytes(&format!("key-{}", i))).collect();
+        db.mset(0, keys.iter().map(|key| (key.clone(), bytes("0"))).collect()).unwrap();
+
+        let writer = {
+            let (db, keys) = (db.clone(), keys.clone());
+            std::thread::spawn(move || {
+                for round in 1..=200 {
+                    let value = bytes(&round.to_string());
+                    db.mset(0, keys.iter().map(|key| (key.clone(), value.clone())).collect()).unwrap();
+                }
+            })
+        };
+
+        // Readers see every key of one MSET or none of them.
+        loop {
+            let values = db.mget(0, &keys).unwrap();
+            assert!(values.iter().all(|value| value == &values[0]), "partial MSET: {:?}", values);
+            if values[0] == Some(bytes("200")) || writer.is_finished() {
+                break;
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(db.get(0, &keys[7]).unwrap(), Some(bytes("200")));
+    }
+}
@@ -11,6 +11,18 @@ pub enum Error {
     #[error(transparent)]
     InvalidConfig(#[from] toml::de::Error),
 
+    #[error("Invalid namespace: {0}")]
+    InvalidNamespace(String),
+
+    #[error("TLS error: {0}")]
+    Tls(String),
+
+    #[error("Invalid user: {0}")]
+    InvalidUser(String),
+
+    #[error("Invalid limit: {0}")]
+    InvalidLimit(String),
+
     #[error("Unknown {0} sub command: {1}")]
     UnknownCommand(String, String),
 
@@ -26,9 +38,19 @@ pub enum Error {
     #[error("End of the frame steam")]
     EndOfStream,
 
+    /// A command was sent with too few or too many arguments.
+    #[error("Wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+
+    #[error("Value is not an integer or out of range")]
+    NotInteger,
+
     #[error("Response error: {0}")]
     Response(String),
 
+    #[error("Storage error: {0}")]
+    Storage(#[from] mineral::error::Error),
+
     #[error("P2P error: {0}")]
     P2pError(P2pError),
 
@@ -37,6 +59,35 @@ pub enum Error {
 
     #[error("Parser incomplete")]
     Incomplete,
+
+    /// A frame exceeded the limits of the connection. The connection can not
+    /// be resynchronized and is closed after replying.
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Timed out waiting for {0}")]
+    Timeout(String),
+}
+
+impl Error {
+    /// Returns the message of the RESP error frame reported to clients.
+    ///
+    /// A store that switched to read-only mode after a write failure uses the
+    /// `READONLY` prefix, so clients can tell it apart from a failed command.
+    /// Integer and arity errors use the exact Redis wording that client
+    /// libraries match.
+    pub(crate) fn to_resp(&self) -> String {
+        match self {
+            Error::NotInteger => "ERR value is not an integer or out of range".to_string(),
+            Error::WrongArity(name) => format!("ERR wrong number of arguments for '{}' command", name),
+            // Messages already in reply form, such as `ERR syntax error`.
+            Error::Other(msg) if msg.starts_with("ERR ") => msg.clone(),
+            Error::Storage(mineral::error::Error::ReadOnly) => format!("READONLY {}", self),
+            Error::Storage(mineral::error::Error::NotInteger) => "ERR value is not an integer or out of range".to_string(),
+            Error::Storage(mineral::error::Error::IntegerOverflow) => "ERR increment or decrement would overflow".to_string(),
+            _ => format!("ERR {}", self),
+        }
+    }
 }
 
 impl From<P2pError> for Error {
@@ -2,23 +2,79 @@
 //! parsing frames from a byte array.
 
 use bytes::{Buf, Bytes};
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::io::Cursor;
+use std::str;
 use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 
 use crate::error::Error;
 
 /// A frame in the Redis protocol.
+///
+/// The RESP3 types are only sent on connections that negotiated protocol 3
+/// with `HELLO`; on RESP2 connections `Connection` converts them to the
+/// closest RESP2 type.
 #[derive(Clone, Debug)]
 pub enum Frame {
     Simple(String),
     Error(String),
-    Integer(u64),
+    Integer(i64),
     Bulk(Bytes),
     Null,
     Array(Vec<Frame>),
+    /// RESP3 map of key-value pairs, in order.
+    Map(Vec<(Frame, Frame)>),
+    /// RESP3 unordered collection of unique values.
+    Set(Vec<Frame>),
+    /// RESP3 floating point number.
+    Double(f64),
+    /// RESP3 boolean.
+    Boolean(bool),
+    /// RESP3 integer outside of the 64 bit range, kept as its decimal digits.
+    BigNumber(String),
+    /// RESP3 string with a three character format such as `txt` or `mkd`.
+    Verbatim(String, Bytes),
+    /// RESP3 out-of-band data such as pub/sub messages.
+    Push(Vec<Frame>),
+    /// RESP3 attributes describing the reply that follows them.
+    Attribute(Vec<(Frame, Frame)>, Box<Frame>),
+}
+
+/// Limits on the frames a `Connection` accepts.
+///
+/// They bound the memory a peer can make the connection buffer: a bulk
+/// string announcing more than `max_bulk_len` bytes, or an aggregate
+/// announcing more than `max_array_len` elements, is rejected by
+/// `Frame::check` before its data is read. `Connection` rejects a frame once
+/// more than `max_request_len` bytes of it are buffered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Largest bulk or verbatim string, in bytes.
+    pub max_bulk_len: usize,
+
+    /// Deepest nesting of aggregate frames. A command, an array of bulk
+    /// strings, has depth 1.
+    pub max_array_depth: usize,
+
+    /// Most elements of an aggregate frame. Maps and attributes count their
+    /// key-value pairs.
+    pub max_array_len: usize,
+
+    /// Largest frame, in bytes, including the frames nested in it.
+    pub max_request_len: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> FrameLimits {
+        FrameLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_depth: 32,
+            max_array_len: 1024 * 1024,
+            max_request_len: 1024 * 1024 * 1024,
+        }
+    }
 }
 
 impl Frame {
@@ -27,14 +83,15 @@ impl Frame {
         Frame::Array(vec![])
     }
 
-    /// Push a "bulk" frame into the array. `self` must be an Array frame.
+    /// Push a "bulk" frame into the array. `self` must be an Array or Push
+    /// frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
     pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Bulk(bytes));
             }
             _ => panic!("not an array frame"),
@@ -43,27 +100,42 @@ impl Frame {
 
     pub(crate) fn push_string(&mut self, s: String) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Set(vec) => {
                 vec.push(Frame::Simple(s));
             }
             _ => panic!("not an array frame"),
         }
     }
 
-    /// Push an "integer" frame into the array. `self` must be an Array frame.
+    /// Push an "integer" frame into the array. `self` must be an Array or Push
+    /// frame.
     ///
     /// # Panics
     ///
     /// panics if `self` is not an array
-    pub(crate) fn push_int(&mut self, value: u64) {
+    pub(crate) fn push_int(&mut self, value: i64) {
         match self {
-            Frame::Array(vec) => {
+            Frame::Array(vec) | Frame::Push(vec) => {
                 vec.push(Frame::Integer(value));
             }
             _ => panic!("not an array frame"),
         }
     }
 
+    /// Insert a key-value pair into the map. `self` must be a Map frame.
+    ///
+    /// # Panics
+    ///
+    /// panics if `self` is not a map
+    pub(crate) fn push_entry(&mut self, key: &str, value: Frame) {
+        match self {
+            Frame::Map(entries) => {
+                entries.push((Frame::Simple(key.to_string()), value));
+            }
+            _ => panic!("not a map frame"),
+        }
+    }
+
     pub(crate) fn merge_frame(&mut self, frame: Frame) {
         match frame {
             Frame::Array(other_vec) => {
@@ -81,43 +153,11 @@ impl Frame {
     }
 
     /// Checks if an entire message can be decoded from `src`
-    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
-        match get_u8(src)? {
-            b'+' => {
-                get_line(src)?;
-                Ok(())
-            }
-            b'-' => {
-                get_line(src)?;
-                Ok(())
-            }
-            b':' => {
-                let _ = get_decimal(src)?;
-                Ok(())
-            }
-            b'$' => {
-                if b'-' == peek_u8(src)? {
-                    // Skip '-1\r\n'
-                    skip(src, 4)
-                } else {
-                    // Read the bulk string
-                    let len: usize = get_decimal(src)?.try_into()?;
-
-                    // skip that number of bytes + 2 (\r\n).
-                    skip(src, len + 2)
-                }
-            }
-            b'*' => {
-                let len = get_decimal(src)?;
-
-                for _ in 0..len {
-                    Frame::check(src)?;
-                }
-
-                Ok(())
-            }
-            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
-        }
+    ///
+    /// Frames exceeding `limits` are rejected as soon as their header is
+    /// read, before the data they announce is buffered.
+    pub fn check(src: &mut Cursor<&[u8]>, limits: &FrameLimits) -> Result<(), Error> {
+        check_nested(src, limits, 1)
     }
 
     /// The message has already been validated with `check`.
@@ -142,8 +182,8 @@ impl Frame {
                 Ok(Frame::Error(string))
             }
             b':' => {
-                let len = get_decimal(src)?;
-                Ok(Frame::Integer(len))
+                let value = get_signed(src)?;
+                Ok(Frame::Integer(value))
             }
             b'$' => {
                 if b'-' == peek_u8(src)? {
@@ -172,16 +212,80 @@ impl Frame {
                 }
             }
             b'*' => {
-                let len = get_decimal(src)?.try_into()?;
-                let mut out = Vec::with_capacity(len);
+                if b'-' == peek_u8(src)? {
+                    // A RESP2 null array
+                    let line = get_line(src)?;
+
+                    if line != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
 
-                for _ in 0..len {
-                    out.push(Frame::parse(src)?);
+                    return Ok(Frame::Null);
                 }
 
-                Ok(Frame::Array(out))
+                Ok(Frame::Array(parse_items(src)?))
             }
-            _ => unimplemented!(),
+            b'~' => Ok(Frame::Set(parse_items(src)?)),
+            b'>' => Ok(Frame::Push(parse_items(src)?)),
+            b'%' => Ok(Frame::Map(parse_entries(src)?)),
+            b'|' => {
+                let attrs = parse_entries(src)?;
+                let value = Frame::parse(src)?;
+
+                Ok(Frame::Attribute(attrs, Box::new(value)))
+            }
+            b'_' => {
+                if !get_line(src)?.is_empty() {
+                    return Err("protocol error; invalid frame format".into());
+                }
+
+                Ok(Frame::Null)
+            }
+            b',' => {
+                let line = str::from_utf8(get_line(src)?)
+                    .map_err(|_| Error::from("protocol error; invalid frame format"))?;
+                let value = line
+                    .parse::<f64>()
+                    .map_err(|_| Error::from("protocol error; invalid double"))?;
+
+                Ok(Frame::Double(value))
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid boolean".into()),
+            },
+            b'(' => {
+                let line = get_line(src)?;
+                let digits = line.strip_prefix(b"-").unwrap_or(line);
+
+                if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+                    return Err("protocol error; invalid big number".into());
+                }
+
+                Ok(Frame::BigNumber(String::from_utf8(line.to_vec())?))
+            }
+            b'=' => {
+                let len: usize = get_decimal(src)?.try_into()?;
+                let n = len + 2;
+
+                if src.remaining() < n {
+                    return Err(Error::Incomplete);
+                }
+
+                // The text is prefixed with its format and a colon, e.g. `txt:`.
+                let data = &src.chunk()[..len];
+                if len < 4 || data[3] != b':' {
+                    return Err("protocol error; invalid verbatim string".into());
+                }
+                let format = String::from_utf8(data[..3].to_vec())?;
+                let text = Bytes::copy_from_slice(&data[4..]);
+
+                skip(src, n)?;
+
+                Ok(Frame::Verbatim(format, text))
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
         }
     }
 
@@ -195,7 +299,7 @@ impl PartialEq<&str> for Frame {
     fn eq(&self, other: &&str) -> bool {
         match self {
             Frame::Simple(s) => s.eq(other),
-            Frame::Bulk(s) => s.eq(other),
+            Frame::Bulk(s) | Frame::Verbatim(_, s) => s.eq(other),
             _ => false,
         }
     }
@@ -203,8 +307,6 @@ impl PartialEq<&str> for Frame {
 
 impl fmt::Display for Frame {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        use std::str;
-
         match self {
             Frame::Simple(response) => response.fmt(fmt),
             Frame::Error(msg) => write!(fmt, "error: {}", msg),
@@ -214,7 +316,7 @@ impl fmt::Display for Frame {
                 Err(_) => write!(fmt, "{:?}", msg),
             },
             Frame::Null => "(nil)".fmt(fmt),
-            Frame::Array(parts) => {
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
                         // use space as the array element display separator
@@ -226,8 +328,141 @@ impl fmt::Display for Frame {
 
                 Ok(())
             }
+            Frame::Map(entries) => {
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+
+                    write!(fmt, "{}: {}", key, value)?;
+                }
+
+                Ok(())
+            }
+            Frame::Double(num) => num.fmt(fmt),
+            Frame::Boolean(value) => value.fmt(fmt),
+            Frame::BigNumber(num) => num.fmt(fmt),
+            Frame::Verbatim(_, text) => match str::from_utf8(text) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", text),
+            },
+            // Attributes are metadata, display the reply itself.
+            Frame::Attribute(_, value) => value.fmt(fmt),
+        }
+    }
+}
+
+/// Checks a frame nested `depth` aggregates deep, the outermost frame being
+/// at depth 1.
+fn check_nested(src: &mut Cursor<&[u8]>, limits: &FrameLimits, depth: usize) -> Result<(), Error> {
+    match get_u8(src)? {
+        b'+' | b'-' | b'_' | b',' | b'#' | b'(' => {
+            get_line(src)?;
+            Ok(())
+        }
+        b':' => {
+            let _ = get_signed(src)?;
+            Ok(())
+        }
+        b'$' => {
+            if b'-' == peek_u8(src)? {
+                // Skip '-1\r\n'
+                skip(src, 4)
+            } else {
+                // Read the bulk string
+                let len = get_length(src, limits.max_bulk_len)?;
+
+                // skip that number of bytes + 2 (\r\n).
+                skip(src, len + 2)
+            }
         }
+        b'=' => {
+            let len = get_length(src, limits.max_bulk_len)?;
+
+            skip(src, len + 2)
+        }
+        b'*' if b'-' == peek_u8(src)? => {
+            // Skip '-1\r\n'
+            skip(src, 4)
+        }
+        b'*' | b'~' | b'>' => {
+            let len = get_aggregate_length(src, limits, depth)?;
+
+            for _ in 0..len {
+                check_nested(src, limits, depth + 1)?;
+            }
+
+            Ok(())
+        }
+        b'%' => {
+            let len = get_aggregate_length(src, limits, depth)?;
+
+            // Each entry is a key frame followed by a value frame.
+            for _ in 0..len.saturating_mul(2) {
+                check_nested(src, limits, depth + 1)?;
+            }
+
+            Ok(())
+        }
+        b'|' => {
+            let len = get_aggregate_length(src, limits, depth)?;
+
+            for _ in 0..len.saturating_mul(2) {
+                check_nested(src, limits, depth + 1)?;
+            }
+
+            // Attributes are followed by the reply they describe.
+            check_nested(src, limits, depth)
+        }
+        actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+    }
+}
+
+/// Reads the length of an aggregate frame at `depth`, rejecting it if the
+/// frame is nested deeper or has more elements than allowed.
+fn get_aggregate_length(src: &mut Cursor<&[u8]>, limits: &FrameLimits, depth: usize) -> Result<u64, Error> {
+    if depth > limits.max_array_depth {
+        return Err(Error::Protocol("aggregate nested too deeply".to_string()));
+    }
+
+    match get_decimal(src)? {
+        len if len <= limits.max_array_len as u64 => Ok(len),
+        _ => Err(Error::Protocol("invalid aggregate length".to_string())),
+    }
+}
+
+/// Reads the length of a string frame, rejecting it if it is above `max`.
+fn get_length(src: &mut Cursor<&[u8]>, max: usize) -> Result<usize, Error> {
+    match usize::try_from(get_decimal(src)?) {
+        Ok(len) if len <= max => Ok(len),
+        _ => Err(Error::Protocol("invalid bulk length".to_string())),
+    }
+}
+
+/// Parse the length-prefixed items of an aggregate frame.
+fn parse_items(src: &mut Cursor<&[u8]>) -> Result<Vec<Frame>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        out.push(Frame::parse(src)?);
+    }
+
+    Ok(out)
+}
+
+/// Parse the length-prefixed key-value pairs of a map or attribute frame.
+fn parse_entries(src: &mut Cursor<&[u8]>) -> Result<Vec<(Frame, Frame)>, Error> {
+    let len = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        let key = Frame::parse(src)?;
+        let value = Frame::parse(src)?;
+        out.push((key, value));
     }
+
+    Ok(out)
 }
 
 fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
@@ -264,6 +499,15 @@ fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     atoi::<u64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
 }
 
+/// Read a new-line terminated signed decimal, as used by integer frames
+fn get_signed(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    use atoi::atoi;
+
+    let line = get_line(src)?;
+
+    atoi::<i64>(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
 /// Find a line
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
     // Scan the bytes directly
@@ -307,3 +551,60 @@ impl From<TryFromIntError> for Error {
         "protocol error; invalid frame format".into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> FrameLimits {
+        FrameLimits {
+            max_bulk_len: 8,
+            max_array_depth: 2,
+            max_array_len: 3,
+            max_request_len: 1024,
+        }
+    }
+
+    fn check(src: &[u8]) -> Result<(), Error> {
+        Frame::check(&mut Cursor::new(src), &limits())
+    }
+
+    fn assert_rejected(src: &[u8], msg: &str) {
+        match check(src) {
+            Err(Error::Protocol(actual)) => assert_eq!(actual, msg),
+            res => panic!("{:?} was not rejected: {:?}", String::from_utf8_lossy(src), res),
+        }
+    }
+
+    #[test]
+    fn within_limits() {
+        check(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$8\r\n12345678\r\n").unwrap();
+        check(b"*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n+OK\r\n").unwrap();
+        check(b"%3\r\n:1\r\n:1\r\n:2\r\n:2\r\n:3\r\n:3\r\n").unwrap();
+        assert!(matches!(check(b"*3\r\n$3\r\nGET\r\n"), Err(Error::Incomplete)));
+    }
+
+    #[test]
+    fn bulk_too_long() {
+        // Rejected from the header, before the data arrives.
+        assert_rejected(b"$9\r\n", "invalid bulk length");
+        assert_rejected(b"*2\r\n$3\r\nGET\r\n$100\r\n", "invalid bulk length");
+        assert_rejected(b"=9\r\ntxt:abcd\r\n", "invalid bulk length");
+    }
+
+    #[test]
+    fn nested_too_deeply() {
+        assert_rejected(b"*1\r\n*1\r\n*1\r\n", "aggregate nested too deeply");
+        assert_rejected(b"*1\r\n%1\r\n~1\r\n", "aggregate nested too deeply");
+        // Attributes describe the reply at their own depth.
+        check(b"*1\r\n|1\r\n+a\r\n+b\r\n*1\r\n:1\r\n").unwrap();
+    }
+
+    #[test]
+    fn too_many_elements() {
+        assert_rejected(b"*4\r\n", "invalid aggregate length");
+        assert_rejected(b"*1\r\n~4\r\n", "invalid aggregate length");
+        assert_rejected(b"%4\r\n", "invalid aggregate length");
+        assert_rejected(b"*99999999999\r\n", "invalid aggregate length");
+    }
+}
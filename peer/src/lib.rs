@@ -26,7 +26,7 @@
 //!   representation.
 
 pub mod clients;
-pub use clients::{BlockingClient, Client};
+pub use clients::{BlockingClient, BufferedClient, Client, Pool};
 
 pub mod cmd;
 pub use cmd::Command;
@@ -34,6 +34,9 @@ pub use cmd::Command;
 mod connection;
 pub use connection::Connection;
 
+mod tls;
+pub use tls::TlsOptions;
+
 pub mod frame;
 pub use frame::Frame;
 
@@ -48,6 +51,14 @@ pub mod server;
 
 pub mod config;
 
+mod acl;
+use acl::Acl;
+
+mod metrics;
+
+mod session;
+use session::Session;
+
 mod shutdown;
 use shutdown::Shutdown;
 
@@ -0,0 +1,308 @@
+//! Prometheus metrics, served over HTTP on `metrics_addr`.
+//!
+//! Command latencies are recorded as they run. Everything else is read when
+//! the metrics are scraped: the connection count from the client registry,
+//! the storage metrics each `HashKv` keeps, and the p2p node status.
+
+use crate::node::{ClientRegistry, Node};
+use crate::Shutdown;
+
+use mineral::metrics::{HistogramSnapshot, Metrics as StorageMetrics};
+use p2p::NodeStatus;
+use prometheus_client::collector::Collector;
+use prometheus_client::encoding::{DescriptorEncoder, EncodeLabelSet, EncodeMetric};
+use prometheus_client::metrics::counter::ConstCounter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::ConstGauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::metrics::MetricType;
+use prometheus_client::registry::{Registry, Unit};
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self, Duration};
+use tracing::{debug, warn};
+
+/// Largest HTTP request head accepted on the metrics listener.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// How long a scraper may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Content type of the OpenMetrics text format produced by the encoder.
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// The metrics registry of the node.
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    registry: Registry,
+
+    /// Latency of each command, labeled with `Command::get_name`.
+    commands: Family<CommandLabels, Histogram>,
+
+    /// The p2p status of the scrape in progress. It is fetched from the p2p
+    /// service asynchronously, before encoding.
+    p2p: Arc<Mutex<NodeStatus>>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CommandLabels {
+    command: String,
+}
+
+/// Reads the metrics kept outside of the registry when scraped.
+#[derive(Debug)]
+struct NodeCollector {
+    clients: Arc<ClientRegistry>,
+
+    /// The storage metrics of each namespace, by name.
+    storage: Vec<(String, Arc<StorageMetrics>)>,
+
+    p2p: Arc<Mutex<NodeStatus>>,
+}
+
+impl Metrics {
+    /// Creates the registry of a node serving `clients`, whose namespaces
+    /// keep the given `storage` metrics.
+    pub(crate) fn new(clients: Arc<ClientRegistry>, storage: Vec<(String, Arc<StorageMetrics>)>) -> Metrics {
+        let mut registry = Registry::default();
+
+        // 50us to about 1.6s.
+        let commands = Family::<CommandLabels, Histogram>::new_with_constructor(|| {
+            Histogram::new(exponential_buckets(0.00005, 2.0, 16))
+        });
+        registry.register_with_unit(
+            "peer_command_duration",
+            "Time spent running commands",
+            Unit::Seconds,
+            commands.clone(),
+        );
+
+        let p2p = Arc::new(Mutex::new(NodeStatus::default()));
+        registry.register_collector(Box::new(NodeCollector {
+            clients,
+            storage,
+            p2p: p2p.clone(),
+        }));
+
+        Metrics {
+            registry,
+            commands,
+            p2p,
+        }
+    }
+
+    /// Records that command `name` took `elapsed` to run.
+    pub(crate) fn observe_command(&self, name: &str, elapsed: Duration) {
+        let labels = CommandLabels {
+            command: name.to_string(),
+        };
+        self.commands.get_or_create(&labels).observe(elapsed.as_secs_f64());
+    }
+
+    /// Encodes all metrics in the text format, with the p2p figures taken
+    /// from `p2p`.
+    pub(crate) fn encode(&self, p2p: NodeStatus) -> Result<String, fmt::Error> {
+        *self.p2p.lock().unwrap() = p2p;
+
+        let mut out = String::new();
+        prometheus_client::encoding::text::encode(&mut out, &self.registry)?;
+        Ok(out)
+    }
+}
+
+impl Collector for NodeCollector {
+    fn encode(&self, mut encoder: DescriptorEncoder) -> Result<(), fmt::Error> {
+        gauge(
+            &mut encoder,
+            "peer_connected_clients",
+            "Number of client connections",
+            self.clients.connected() as i64,
+        )?;
+        gauge(
+            &mut encoder,
+            "peer_max_clients",
+            "Maximum number of client connections",
+            self.clients.max_clients() as i64,
+        )?;
+
+        // Storage metrics, labeled by namespace.
+        let mut family = encoder.encode_descriptor(
+            "mineral_cache_hits",
+            "Reads answered from the key cache",
+            None,
+            MetricType::Counter,
+        )?;
+        for (name, metrics) in &self.storage {
+            let counter = ConstCounter::new(metrics.cache_hits.load(Ordering::Relaxed));
+            counter.encode(family.encode_family(&[("namespace", name.as_str())])?)?;
+        }
+        let mut family = encoder.encode_descriptor(
+            "mineral_cache_misses",
+            "Reads that had to load the slot from storage",
+            None,
+            MetricType::Counter,
+        )?;
+        for (name, metrics) in &self.storage {
+            let counter = ConstCounter::new(metrics.cache_misses.load(Ordering::Relaxed));
+            counter.encode(family.encode_family(&[("namespace", name.as_str())])?)?;
+        }
+        let mut family = encoder.encode_descriptor(
+            "mineral_cache_hit_ratio",
+            "Share of reads answered from the key cache",
+            None,
+            MetricType::Gauge,
+        )?;
+        for (name, metrics) in &self.storage {
+            let ratio = ConstGauge::new(metrics.cache_hit_ratio());
+            ratio.encode(family.encode_family(&[("namespace", name.as_str())])?)?;
+        }
+        let mut family = encoder.encode_descriptor(
+            "mineral_cbf_pending",
+            "Bytes in the change buffer waiting to be flushed",
+            Some(&Unit::Bytes),
+            MetricType::Gauge,
+        )?;
+        for (name, metrics) in &self.storage {
+            let pending = ConstGauge::new(metrics.cbf_bytes.load(Ordering::Relaxed) as i64);
+            pending.encode(family.encode_family(&[("namespace", name.as_str())])?)?;
+        }
+        let mut family = encoder.encode_descriptor(
+            "mineral_wal_append_duration",
+            "Time spent appending to the write-ahead log",
+            Some(&Unit::Seconds),
+            MetricType::Histogram,
+        )?;
+        for (name, metrics) in &self.storage {
+            histogram(family.encode_family(&[("namespace", name.as_str())])?, metrics.wal_append.snapshot())?;
+        }
+        let mut family = encoder.encode_descriptor(
+            "mineral_flush_duration",
+            "Time spent flushing the change buffer to the data blocks",
+            Some(&Unit::Seconds),
+            MetricType::Histogram,
+        )?;
+        for (name, metrics) in &self.storage {
+            histogram(family.encode_family(&[("namespace", name.as_str())])?, metrics.flush.snapshot())?;
+        }
+
+        let p2p = self.p2p.lock().unwrap();
+        gauge(
+            &mut encoder,
+            "p2p_known_peers",
+            "Number of peers in the routing table",
+            p2p.known_peers_count as i64,
+        )?;
+        gauge(
+            &mut encoder,
+            "p2p_connected_peers",
+            "Number of peers with an open connection",
+            p2p.connected_peers as i64,
+        )?;
+        let mut family = encoder.encode_descriptor(
+            "p2p_request_failures",
+            "Requests between peers that failed",
+            None,
+            MetricType::Counter,
+        )?;
+        for (direction, failures) in [("outbound", p2p.outbound_failures), ("inbound", p2p.inbound_failures)] {
+            ConstCounter::new(failures).encode(family.encode_family(&[("direction", direction)])?)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn gauge(encoder: &mut DescriptorEncoder, name: &str, help: &str, value: i64) -> Result<(), fmt::Error> {
+    let gauge = ConstGauge::new(value);
+    gauge.encode(encoder.encode_descriptor(name, help, None, gauge.metric_type())?)
+}
+
+fn histogram(
+    mut encoder: prometheus_client::encoding::MetricEncoder,
+    snapshot: HistogramSnapshot,
+) -> Result<(), fmt::Error> {
+    encoder.encode_histogram::<()>(snapshot.sum, snapshot.count, &snapshot.buckets, None)
+}
+
+/// Serves `GET /metrics` on `listener` until the server shuts down.
+pub(crate) async fn serve(listener: TcpListener, node: Arc<Node>, mut shutdown: Shutdown) {
+    loop {
+        let socket = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((socket, _)) => socket,
+                Err(err) => {
+                    warn!(cause = %err, "failed to accept metrics connection");
+                    time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = shutdown.recv() => return,
+        };
+
+        let node = node.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(socket, &node).await {
+                debug!(cause = %err, "metrics request failed");
+            }
+        });
+    }
+}
+
+/// Reads one HTTP request from `socket` and writes the response. The
+/// connection is closed afterwards.
+async fn respond(mut socket: TcpStream, node: &Node) -> crate::Result<()> {
+    let head = match time::timeout(REQUEST_TIMEOUT, read_head(&mut socket)).await {
+        Ok(head) => head?,
+        Err(_) => return Err(crate::Error::Timeout("metrics request".to_string())),
+    };
+
+    let mut parts = head.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => {
+            let status = node.p2p_status().await;
+            match node.metrics().encode(status) {
+                Ok(body) => ("200 OK", CONTENT_TYPE, body),
+                Err(_) => ("500 Internal Server Error", "text/plain", "failed to encode metrics\n".to_string()),
+            }
+        }
+        (_, "/metrics") => ("405 Method Not Allowed", "text/plain", "only GET is supported\n".to_string()),
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await?;
+    Ok(())
+}
+
+/// Reads the request line and headers, up to the empty line ending them.
+async fn read_head(socket: &mut TcpStream) -> crate::Result<String> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        if buf.len() > MAX_REQUEST_HEAD {
+            return Err("metrics request head too large".into());
+        }
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Err("connection closed before the request was complete".into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
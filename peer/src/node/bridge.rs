@@ -0,0 +1,86 @@
+//! Envelope for pub/sub messages bridged over gossipsub.
+//!
+//! All channels share one gossipsub topic, so each message carries its
+//! channel name: `nonce (u64) | channel length (u32) | channel | message`,
+//! integers in big endian.
+//!
+//! Gossipsub identifies a message by the hash of its data and drops the ones
+//! it has seen. The random nonce keeps a message published twice from being
+//! dropped as a duplicate, without changing how other topics are handled.
+
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::Error;
+
+/// Length of the nonce and the channel length.
+const HEADER_LEN: usize = 8 + 4;
+
+/// Encodes a message published on `channel`.
+pub(crate) fn encode(channel: &str, message: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + channel.len() + message.len());
+    buf.put_u64(rand::random());
+    buf.put_u32(channel.len() as u32);
+    buf.put_slice(channel.as_bytes());
+    buf.put_slice(message);
+    buf
+}
+
+/// Decodes an envelope into the channel name and the message.
+pub(crate) fn decode(data: Vec<u8>) -> crate::Result<(String, Bytes)> {
+    let mut buf = Bytes::from(data);
+    if buf.remaining() < HEADER_LEN {
+        return Err(Error::Other("bridged message is too short".into()));
+    }
+
+    buf.advance(8);
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err(Error::Other("bridged message is too short".into()));
+    }
+
+    let channel = String::from_utf8(buf.split_to(len).to_vec())
+        .map_err(|_| Error::Other("bridged channel is not valid UTF-8".into()))?;
+    Ok((channel, buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = encode("news", b"hello");
+        assert_eq!(decode(data).unwrap(), ("news".to_string(), Bytes::from("hello")));
+
+        let data = encode("", b"");
+        assert_eq!(decode(data).unwrap(), (String::new(), Bytes::new()));
+
+        // The same message published twice is not a gossipsub duplicate.
+        assert_ne!(encode("news", b"hello"), encode("news", b"hello"));
+    }
+
+    #[test]
+    fn truncated() {
+        let data = encode("news", b"hello");
+
+        // Cut inside the nonce, the channel length and the channel.
+        for len in [0, 5, 8, 11, 12, 15] {
+            assert!(decode(data[..len].to_vec()).is_err(), "decoded {} bytes", len);
+        }
+
+        // The message takes the rest of the data, so a shorter one is still valid.
+        assert_eq!(decode(data[..16].to_vec()).unwrap(), ("news".to_string(), Bytes::new()));
+    }
+
+    #[test]
+    fn invalid_channel() {
+        let mut data = encode("news", b"hello");
+        data[12] = 0xff;
+        assert!(decode(data).is_err());
+
+        // A channel length past the end of the data.
+        let mut data = encode("news", b"");
+        data[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode(data).is_err());
+    }
+}
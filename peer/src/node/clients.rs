@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{Notify, Semaphore};
+
+/// The client connections of the server, listed by `CLIENT LIST` and closed
+/// by `CLIENT KILL`.
+///
+/// Also holds the semaphore limiting the number of connections, so the
+/// connection count reported by `INFO` matches the limit the listener
+/// enforces.
+#[derive(Debug)]
+pub(crate) struct ClientRegistry {
+    /// Maximum number of concurrent connections.
+    max_clients: usize,
+
+    /// One permit per connection the server may still accept.
+    permits: Arc<Semaphore>,
+
+    /// Id assigned to the next connection.
+    next_id: AtomicU64,
+
+    /// The registered connections by id, so they are listed oldest first.
+    clients: Mutex<BTreeMap<u64, Arc<ClientEntry>>>,
+}
+
+/// What is known about one client connection.
+#[derive(Debug)]
+pub(crate) struct ClientEntry {
+    id: u64,
+
+    addr: SocketAddr,
+
+    created: Instant,
+
+    /// Signalled by `CLIENT KILL`.
+    kill: Arc<Notify>,
+
+    /// State that changes while the connection runs commands.
+    state: Mutex<ClientState>,
+}
+
+#[derive(Debug)]
+struct ClientState {
+    db: usize,
+    user: String,
+    last_command: String,
+    last_active: Instant,
+}
+
+/// Registration of a connection, removed from the registry when dropped.
+#[derive(Debug)]
+pub(crate) struct ClientHandle {
+    entry: Arc<ClientEntry>,
+
+    registry: Arc<ClientRegistry>,
+}
+
+/// Selects the connections closed by `CLIENT KILL`. Every criterion that is
+/// set must match.
+#[derive(Debug, Default)]
+pub(crate) struct KillFilter {
+    pub(crate) id: Option<u64>,
+    pub(crate) addr: Option<String>,
+    pub(crate) user: Option<String>,
+    /// Connection that must not be closed, normally the caller.
+    pub(crate) skip: Option<u64>,
+}
+
+impl ClientRegistry {
+    pub(crate) fn new(max_clients: usize) -> ClientRegistry {
+        ClientRegistry {
+            max_clients,
+            permits: Arc::new(Semaphore::new(max_clients)),
+            next_id: AtomicU64::new(1),
+            clients: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the semaphore the listener acquires a permit of for every
+    /// connection.
+    pub(crate) fn permits(&self) -> Arc<Semaphore> {
+        self.permits.clone()
+    }
+
+    /// Returns the maximum number of concurrent connections.
+    pub(crate) fn max_clients(&self) -> usize {
+        self.max_clients
+    }
+
+    /// Returns the number of open connections, including those still
+    /// performing the TLS handshake.
+    pub(crate) fn connected(&self) -> usize {
+        self.max_clients - self.permits.available_permits()
+    }
+
+    /// Registers a new connection from `addr`.
+    pub(crate) fn register(self: &Arc<Self>, addr: SocketAddr) -> ClientHandle {
+        let now = Instant::now();
+        let entry = Arc::new(ClientEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            addr,
+            created: now,
+            kill: Arc::new(Notify::new()),
+            state: Mutex::new(ClientState {
+                db: 0,
+                user: crate::acl::DEFAULT_USER.to_string(),
+                last_command: String::new(),
+                last_active: now,
+            }),
+        });
+
+        self.clients.lock().unwrap().insert(entry.id, entry.clone());
+
+        ClientHandle {
+            entry,
+            registry: self.clone(),
+        }
+    }
+
+    /// Formats the connections in the `CLIENT LIST` layout, one line each.
+    pub(crate) fn list(&self) -> String {
+        let now = Instant::now();
+        let mut out = String::new();
+
+        for entry in self.clients.lock().unwrap().values() {
+            let state = entry.state.lock().unwrap();
+            let _ = writeln!(
+                out,
+                "id={} addr={} age={} idle={} db={} user={} cmd={}",
+                entry.id,
+                entry.addr,
+                now.duration_since(entry.created).as_secs(),
+                now.duration_since(state.last_active).as_secs(),
+                state.db,
+                state.user,
+                state.last_command,
+            );
+        }
+        out
+    }
+
+    /// Closes the connections matching `filter` and returns how many there
+    /// were.
+    ///
+    /// A connection is closed once it finishes the command it is running.
+    pub(crate) fn kill(&self, filter: &KillFilter) -> usize {
+        let clients = self.clients.lock().unwrap();
+
+        let mut killed = 0;
+        for entry in clients.values().filter(|entry| filter.matches(entry)) {
+            entry.kill.notify_one();
+            killed += 1;
+        }
+        killed
+    }
+}
+
+impl ClientHandle {
+    /// Returns the id of the connection.
+    pub(crate) fn id(&self) -> u64 {
+        self.entry.id
+    }
+
+    /// Returns the address of the client.
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.entry.addr
+    }
+
+    /// Returns the signal `CLIENT KILL` sends to the connection.
+    pub(crate) fn kill_signal(&self) -> Arc<Notify> {
+        self.entry.kill.clone()
+    }
+
+    /// Records that the connection started running `command`.
+    pub(crate) fn record_command(&self, command: &str) {
+        let mut state = self.entry.state.lock().unwrap();
+        state.last_command.clear();
+        state.last_command.push_str(command);
+        state.last_active = Instant::now();
+    }
+
+    /// Records the database selected by the connection.
+    pub(crate) fn set_db(&self, db: usize) {
+        self.entry.state.lock().unwrap().db = db;
+    }
+
+    /// Records the user the connection authenticated as.
+    pub(crate) fn set_user(&self, user: &str) {
+        self.entry.state.lock().unwrap().user = user.to_string();
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        self.registry.clients.lock().unwrap().remove(&self.entry.id);
+    }
+}
+
+impl KillFilter {
+    fn matches(&self, entry: &ClientEntry) -> bool {
+        if self.skip == Some(entry.id) || self.id.is_some_and(|id| id != entry.id) {
+            return false;
+        }
+        if let Some(addr) = &self.addr {
+            if *addr != entry.addr.to_string() {
+                return false;
+            }
+        }
+        if let Some(user) = &self.user {
+            if *user != entry.state.lock().unwrap().user {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -1,9 +1,20 @@
-use std::{ops::Deref, sync::Arc, time::Duration};
+mod bridge;
+
+mod clients;
+pub(crate) use clients::{ClientHandle, ClientRegistry, KillFilter};
+
+mod slowlog;
+pub(crate) use slowlog::SlowLog;
+
+use std::{ops::Deref, sync::Arc, time::Instant};
 
 use bytes::Bytes;
-use p2p::PeerIdWithMultiaddr;
+use log::error;
+use mineral::kv::hash::{KvStats, SetOptions, SetOutcome};
+use p2p::{NodeStatus, PeerIdWithMultiaddr};
+use tokio::sync::broadcast;
 
-use crate::{db::{Db, DbDropGuard}, P2pClient};
+use crate::{config::Config, db::{spawn_storage, Db, DbDropGuard}, metrics::Metrics, P2pClient};
 
 #[derive(Debug, Clone)]
 pub struct Node {
@@ -14,11 +25,21 @@ impl Node {
     pub fn new(
         db_holder: DbDropGuard,
         p2p: P2pClient,
+        config: Config,
     ) -> Self {
+        let clients = Arc::new(ClientRegistry::new(config.limits.max_clients));
+        let metrics = Arc::new(Metrics::new(clients.clone(), db_holder.db().storage_metrics()));
+
         Self {
             inner: Arc::new(NodeInner {
                 db_holder,
                 p2p,
+                bridge_topic: config.pubsub.bridge_topic.clone(),
+                clients,
+                metrics,
+                slowlog: Arc::new(SlowLog::new(&config.slowlog)),
+                config,
+                started: Instant::now(),
             }),
         }
     }
@@ -38,6 +59,24 @@ pub struct NodeInner {
     db_holder: DbDropGuard,
 
     p2p: P2pClient,
+
+    /// Gossipsub topic that carries published messages to the other nodes.
+    bridge_topic: Option<String>,
+
+    /// The connected clients.
+    clients: Arc<ClientRegistry>,
+
+    /// Metrics served on `metrics_addr`.
+    metrics: Arc<Metrics>,
+
+    /// Commands that ran longer than the configured threshold.
+    slowlog: Arc<SlowLog>,
+
+    /// The configuration the server was started with.
+    config: Config,
+
+    /// When the node was started.
+    started: Instant,
 }
 
 impl Node {
@@ -45,16 +84,128 @@ impl Node {
         self.db_holder.db()
     }
 
-    pub(crate) fn get(&self, key: &Bytes) -> Option<Bytes> {
-        self.db().get(key)
+    /// Reads run on the blocking thread pool too, see `spawn_storage`.
+    pub(crate) async fn get(&self, db: usize, key: &Bytes) -> crate::Result<Option<Bytes>> {
+        let (store, key) = (self.db(), key.clone());
+        spawn_storage(move || store.get(db, &key)).await
     }
 
-    // pub(crate) fn get_node_status(&self, key: &str) -> Option<Bytes> {
-    //     self.p2p.get_node_status()
-    // }
+    pub(crate) fn select(&self, db: &str) -> Option<usize> {
+        self.db().select(db)
+    }
+
+    pub(crate) fn namespaces(&self) -> Vec<String> {
+        self.db().namespaces()
+    }
+
+    pub(crate) async fn mget(&self, db: usize, keys: &[Bytes]) -> crate::Result<Vec<Option<Bytes>>> {
+        let (store, keys) = (self.db(), keys.to_vec());
+        spawn_storage(move || store.mget(db, &keys)).await
+    }
+
+    pub(crate) async fn mset(&self, db: usize, pairs: Vec<(Bytes, Bytes)>) -> crate::Result<()> {
+        let store = self.db();
+        spawn_storage(move || store.mset(db, pairs)).await
+    }
+
+    pub(crate) async fn del(&self, db: usize, keys: &[Bytes]) -> crate::Result<u64> {
+        let (store, keys) = (self.db(), keys.to_vec());
+        spawn_storage(move || store.del(db, &keys)).await
+    }
+
+    pub(crate) async fn exists(&self, db: usize, keys: &[Bytes]) -> crate::Result<u64> {
+        let (store, keys) = (self.db(), keys.to_vec());
+        spawn_storage(move || store.exists(db, &keys)).await
+    }
+
+    pub(crate) async fn get_versioned(&self, db: usize, key: &Bytes) -> crate::Result<Option<(Bytes, u64)>> {
+        let (store, key) = (self.db(), key.clone());
+        spawn_storage(move || store.get_versioned(db, &key)).await
+    }
+
+    pub(crate) async fn incr_by(&self, db: usize, key: &Bytes, delta: i64) -> crate::Result<i64> {
+        let (store, key) = (self.db(), key.clone());
+        spawn_storage(move || store.incr_by(db, &key, delta)).await
+    }
+
+    pub(crate) fn subscribe(&self, channel: String) -> broadcast::Receiver<Bytes> {
+        self.db().subscribe(channel)
+    }
+
+    /// Publishes `message` to the local subscribers of `channel` and, if the
+    /// bridge is enabled, to the other nodes.
+    ///
+    /// Returns the number of local subscribers that received the message.
+    pub(crate) fn publish(&self, channel: &str, message: Bytes) -> usize {
+        if let Some(topic) = &self.bridge_topic {
+            self.p2p.broadcast(topic.clone(), bridge::encode(channel, &message));
+        }
+        self.db().publish(channel, message)
+    }
+
+    /// Returns true if `topic` is the gossipsub topic of the pub/sub bridge.
+    pub(crate) fn is_bridge_topic(&self, topic: &str) -> bool {
+        self.bridge_topic.as_deref() == Some(topic)
+    }
+
+    /// Delivers a message bridged from another node to the local
+    /// subscribers. It is not broadcast again.
+    pub(crate) fn handle_bridged_message(&self, data: Vec<u8>) {
+        match bridge::decode(data) {
+            Ok((channel, message)) => {
+                self.db().publish(&channel, message);
+            }
+            Err(err) => error!("❌ >> [P2P-IN-BROADCAST] Invalid pub/sub message: {}", err),
+        }
+    }
+
+    pub(crate) async fn stats(&self, db: usize) -> crate::Result<KvStats> {
+        let store = self.db();
+        spawn_storage(move || store.stats(db)).await
+    }
+
+    /// Returns the state of the p2p node: its peer id, listened addresses and
+    /// known peers.
+    pub(crate) async fn p2p_status(&self) -> NodeStatus {
+        self.p2p.get_node_status().await
+    }
+
+    /// Returns the connected clients.
+    pub(crate) fn clients(&self) -> &Arc<ClientRegistry> {
+        &self.clients
+    }
+
+    /// Returns the metrics registry.
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns the slow log.
+    pub(crate) fn slowlog(&self) -> &SlowLog {
+        &self.slowlog
+    }
+
+    /// Returns the configuration the server was started with.
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns when the node was started.
+    pub(crate) fn started(&self) -> Instant {
+        self.started
+    }
+
+    /// Returns true if a write failure switched database `db` to read-only
+    /// mode.
+    pub(crate) async fn is_read_only(&self, db: usize) -> crate::Result<bool> {
+        let store = self.db();
+        spawn_storage(move || store.is_read_only(db)).await
+    }
 
-    pub(crate) fn set(&self, key: Bytes, value: Bytes, expire: Option<Duration>) {
-        self.db().set(key, value, expire)
+    /// Writes run on the blocking thread pool, see `spawn_storage`.
+    pub(crate) async fn set(&self, db: usize, key: Bytes, value: Bytes, opts: SetOptions) -> crate::Result<SetOutcome> {
+        let store = self.db();
+        spawn_storage(move || store.set(db, key, value, opts)).await
     }
 
     // pub fn next_account_nonce(&self, account: &str) -> u64 {
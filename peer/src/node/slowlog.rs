@@ -0,0 +1,142 @@
+use crate::cmd::SlowLogEntry;
+use crate::config::SlowLogConfig;
+use crate::Frame;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Most arguments kept per entry, including the command name.
+const MAX_ARGS: usize = 32;
+
+/// Longest argument kept, in bytes.
+const MAX_ARG_LEN: usize = 128;
+
+/// Commands that ran longer than the configured threshold, read with
+/// `SLOWLOG GET`.
+#[derive(Debug)]
+pub(crate) struct SlowLog {
+    /// Threshold in microseconds, negative when the log is disabled.
+    log_slower_than: i64,
+
+    /// Number of entries kept.
+    max_len: usize,
+
+    /// Id of the next entry.
+    next_id: AtomicU64,
+
+    /// The entries, newest first.
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    pub(crate) fn new(config: &SlowLogConfig) -> SlowLog {
+        SlowLog {
+            log_slower_than: config.log_slower_than,
+            max_len: config.max_len,
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Returns the arguments of the command in `frame` as the log keeps them,
+    /// or `None` if the log is disabled.
+    ///
+    /// Called before the command runs, as running it consumes the frame.
+    /// Arguments share the frame buffers unless they have to be cut.
+    pub(crate) fn capture(&self, frame: &Frame) -> Option<Vec<Bytes>> {
+        if self.log_slower_than < 0 || self.max_len == 0 {
+            return None;
+        }
+
+        let parts = match frame {
+            Frame::Array(parts) => parts,
+            _ => return Some(vec![]),
+        };
+
+        let mut args: Vec<Bytes> = parts.iter().take(MAX_ARGS).map(argument).collect();
+        if parts.len() > MAX_ARGS {
+            args[MAX_ARGS - 1] = Bytes::from(format!("... ({} more arguments)", parts.len() - MAX_ARGS + 1));
+        }
+        redact(&mut args);
+        Some(args)
+    }
+
+    /// Logs the command with the `args` returned by `capture` if it ran for
+    /// longer than the threshold.
+    pub(crate) fn record(&self, args: Vec<Bytes>, addr: SocketAddr, duration: Duration, storage: Duration) {
+        if (duration.as_micros() as i64) < self.log_slower_than {
+            return;
+        }
+
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()),
+            duration,
+            storage,
+            args,
+            addr: addr.to_string(),
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(self.max_len);
+    }
+
+    /// Returns up to `count` entries, newest first.
+    pub(crate) fn get(&self, count: usize) -> Vec<SlowLogEntry> {
+        self.entries.lock().unwrap().iter().take(count).cloned().collect()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Removes every entry.
+    pub(crate) fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Returns the argument held by `frame`, cut to `MAX_ARG_LEN` bytes.
+fn argument(frame: &Frame) -> Bytes {
+    let arg = match frame {
+        Frame::Bulk(arg) => arg.clone(),
+        Frame::Simple(arg) => Bytes::from(arg.clone()),
+        frame => Bytes::from(frame.to_string()),
+    };
+
+    if arg.len() <= MAX_ARG_LEN {
+        return arg;
+    }
+
+    let suffix = format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN);
+    let mut cut = BytesMut::with_capacity(MAX_ARG_LEN + suffix.len());
+    cut.put_slice(&arg[..MAX_ARG_LEN]);
+    cut.put_slice(suffix.as_bytes());
+    cut.freeze()
+}
+
+/// Hides the passwords given to `AUTH` and `HELLO ... AUTH`.
+fn redact(args: &mut [Bytes]) {
+    let command = match args.first() {
+        Some(command) => command.to_ascii_lowercase(),
+        None => return,
+    };
+
+    let from = match &command[..] {
+        b"auth" => 1,
+        b"hello" => match args.iter().position(|arg| arg.eq_ignore_ascii_case(b"auth")) {
+            Some(index) => index + 1,
+            None => return,
+        },
+        _ => return,
+    };
+
+    for arg in args.iter_mut().skip(from) {
+        *arg = Bytes::from_static(b"(redacted)");
+    }
+}
@@ -33,6 +33,11 @@ impl EventHandler for EventHandlerImpl {
     }
 
     fn handle_broadcast(&self, topic: &str, message: Vec<u8>) {
+        if self.is_bridge_topic(topic) {
+            self.handle_bridged_message(message);
+            return;
+        }
+
         info!(
             "📣 <<<< Inbound broadcast: {:?} {:?}",
             topic,
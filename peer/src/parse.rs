@@ -1,6 +1,7 @@
 use crate::{error::Error, Frame};
 
 use bytes::Bytes;
+use std::convert::TryFrom;
 use std::{fmt, str, vec};
 
 #[derive(Debug)]
@@ -71,25 +72,48 @@ impl Parse {
         }
     }
 
+    /// Return all remaining entries as raw bytes.
+    ///
+    /// Used by commands taking a variable number of keys. At least one entry
+    /// must remain.
+    pub fn rest_bytes(&mut self) -> Result<Vec<Bytes>, Error> {
+        let mut items = vec![self.next_bytes()?];
+        loop {
+            match self.next_bytes() {
+                Ok(item) => items.push(item),
+                Err(Error::EndOfStream) => return Ok(items),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
     /// Return the next entry as an integer.
     ///
     /// This includes `Simple`, `Bulk`, and `Integer` frame types. `Simple` and
     /// `Bulk` frame types are parsed.
     ///
-    /// If the next entry cannot be represented as an integer, then an error is
-    /// returned.
+    /// If the next entry cannot be represented as an integer,
+    /// `Error::NotInteger` is returned.
     pub fn next_int(&mut self) -> Result<u64, Error> {
-        use atoi::atoi;
-
-        const MSG: &str = "protocol error; invalid number";
-
         match self.next()? {
             // An integer frame type is already stored as an integer.
-            Frame::Integer(v) => Ok(v),
+            Frame::Integer(v) => u64::try_from(v).map_err(|_| Error::NotInteger),
             // Simple and bulk frames must be parsed as integers. If the parsing
             // fails, an error is returned.
-            Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| MSG.into()),
-            Frame::Bulk(data) => atoi::<u64>(&data).ok_or_else(|| MSG.into()),
+            Frame::Simple(data) => data.parse().map_err(|_| Error::NotInteger),
+            Frame::Bulk(data) => parse_bytes(&data),
+            frame => Err(Error::InvalidFrameType(format!("protocol error; expected int frame but got {:?}", frame))),
+        }
+    }
+
+    /// Return the next entry as a signed integer.
+    ///
+    /// Behaves like `next_int`, but also accepts negative values.
+    pub fn next_signed_int(&mut self) -> Result<i64, Error> {
+        match self.next()? {
+            Frame::Integer(v) => Ok(v),
+            Frame::Simple(data) => data.parse().map_err(|_| Error::NotInteger),
+            Frame::Bulk(data) => parse_bytes(&data),
             frame => Err(Error::InvalidFrameType(format!("protocol error; expected int frame but got {:?}", frame))),
         }
     }
@@ -103,3 +127,12 @@ impl Parse {
         }
     }
 }
+
+/// Parses the whole of `data` as a decimal integer. Unlike `atoi`, trailing
+/// bytes are rejected, so `5x` is not read as 5.
+fn parse_bytes<T: str::FromStr>(data: &[u8]) -> Result<T, Error> {
+    str::from_utf8(data)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::NotInteger)
+}
@@ -1,25 +1,42 @@
 use crate::config::Config;
+use crate::frame::FrameLimits;
 use crate::node::Node;
-use crate::{Command, Connection, DbDropGuard, P2pClient, Shutdown};
+use crate::tls::Acceptor;
+use crate::{Acl, Command, Connection, DbDropGuard, Error, Frame, P2pClient, Session, Shutdown};
 
 use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{broadcast, mpsc, Semaphore};
 use tokio::time::{self, Duration};
 use tracing::field::debug;
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 #[derive(Debug)]
 struct Listener {
 
     node: Arc<Node>,
 
+    /// Users allowed to connect, shared by all connections.
+    acl: Arc<Acl>,
+
+    /// Wraps accepted sockets in TLS when the `[tls]` section is set.
+    tls: Option<Acceptor>,
+
     /// TCP listener supplied by the `run` caller.
     listener: TcpListener,
 
+    /// One permit per connection, taken from the client registry. Clients
+    /// connecting once they are all taken are turned away.
     limit_connections: Arc<Semaphore>,
 
+    /// Limits on the frames each connection reads.
+    frame_limits: FrameLimits,
+
+    /// How long a connection may wait for a command, `None` for ever.
+    idle_timeout: Option<Duration>,
+
     notify_shutdown: broadcast::Sender<()>,
 
     shutdown_complete_tx: mpsc::Sender<()>,
@@ -32,34 +49,64 @@ struct Handler {
 
     connection: Connection,
 
+    /// Per-connection state such as the selected database and the
+    /// authenticated user.
+    session: Session,
+
+    /// How long to wait for the next command before closing the connection.
+    idle_timeout: Option<Duration>,
+
     shutdown: Shutdown,
 
     _shutdown_complete: mpsc::Sender<()>,
 }
 
-/// Maximum number of concurrent connections the redis server will accept.
-const MAX_CONNECTIONS: usize = 250;
+/// How long a client may take to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the node logs storage statistics.
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Run the peer server.
-pub async fn run(listener: TcpListener, config: Config, shutdown: impl Future) {
+///
+/// Returns an error if the data directory can not be opened, for example
+/// because another server already uses it, or if the TLS certificate or key
+/// can not be loaded.
+pub async fn run(listener: TcpListener, config: Config, shutdown: impl Future) -> crate::Result<()> {
+    let tls = config.tls.as_ref().map(Acceptor::new).transpose()?;
 
     let (notify_shutdown, _) = broadcast::channel(1);
     let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel(1);
 
     let (p2p_client, mut p2p_server) = 
-        crate::p2p::new(config.p2p.clone()).unwrap();
+        crate::p2p::new(config.p2p_config()).unwrap();
 
-    let node = Arc::new(
-            Node::new(DbDropGuard::new(config.clone()), p2p_client));
+    let node = Arc::new(Node::new(
+        DbDropGuard::new(config.clone())?,
+        p2p_client,
+        config.clone(),
+    ));
     
     let event_handler = crate::p2p::EventHandlerImpl::new(node.clone());
     p2p_server.set_event_handler(event_handler);
 
+    tokio::spawn(log_stats(node.clone(), Shutdown::new(notify_shutdown.subscribe())));
+
+    if let Some(addr) = &config.metrics_addr {
+        let listener = TcpListener::bind(addr).await?;
+        info!(%addr, "serving metrics");
+        tokio::spawn(crate::metrics::serve(listener, node.clone(), Shutdown::new(notify_shutdown.subscribe())));
+    }
+
     // Initialize the listener state
     let mut server = Listener {
         listener,
+        limit_connections: node.clients().permits(),
+        frame_limits: config.limits.frame_limits(),
+        idle_timeout: config.limits.idle_timeout(),
         node,
-        limit_connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        acl: Arc::new(Acl::new(&config.auth)),
+        tls,
         notify_shutdown,
         shutdown_complete_tx,
     };
@@ -80,6 +127,7 @@ pub async fn run(listener: TcpListener, config: Config, shutdown: impl Future) {
     }
 
     let Listener {
+        node,
         shutdown_complete_tx,
         notify_shutdown,
         ..
@@ -90,6 +138,51 @@ pub async fn run(listener: TcpListener, config: Config, shutdown: impl Future) {
     drop(shutdown_complete_tx);
 
     let _ = shutdown_complete_rx.recv().await;
+
+    // All connections are gone; persist everything still buffered in memory.
+    if let Err(err) = node.db().close() {
+        error!(cause = %err, "failed to close db");
+    }
+
+    Ok(())
+}
+
+/// Periodically logs storage statistics until the server shuts down.
+async fn log_stats(node: Arc<Node>, mut shutdown: Shutdown) {
+    let mut interval = time::interval(STATS_LOG_INTERVAL);
+
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.recv() => return,
+        }
+
+        for (db, name) in node.namespaces().iter().enumerate() {
+            log_namespace_stats(&node, db, name).await;
+        }
+    }
+}
+
+/// Logs the storage statistics of one namespace.
+async fn log_namespace_stats(node: &Node, db: usize, name: &str) {
+    match node.stats(db).await {
+        Ok(stats) => info!(
+            namespace = name,
+            keys = stats.keys,
+            slots_used = stats.storage.slots_used,
+            overflows = stats.storage.overflows,
+            blocks_allocated = stats.storage.blocks_allocated,
+            blocks_free = stats.storage.blocks_free,
+            largest_free_extent = stats.storage.largest_free_extent,
+            wal_bytes = stats.storage.wal_bytes,
+            cbf_pages = stats.storage.cbf_pages,
+            flush_lag_ms = stats.storage.flush_lag_ms,
+            write_rejects = stats.storage.write_rejects,
+            vlog_bytes = stats.vlog_bytes,
+            "storage stats"
+        ),
+        Err(err) => error!(namespace = name, cause = %err, "failed to collect storage stats"),
+    }
 }
 
 impl Listener {
@@ -98,35 +191,67 @@ impl Listener {
         info!("accepting inbound connections");
 
         loop {
-            let permit = self
-                .limit_connections
-                .clone()
-                .acquire_owned()
-                .await
-                .unwrap();
+            let (socket, addr) = self.accept().await?;
+
+            // Turn the client away if the server is full. It is still told
+            // why, so it does not retry blindly.
+            let permit = match self.limit_connections.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    warn!(client = %addr, "max number of clients reached");
+                    tokio::spawn(reject(socket, self.tls.clone(), "ERR max number of clients reached"));
+                    continue;
+                }
+            };
 
-            let socket = self.accept().await?;
+            // Gather the per-connection handler state. The handler itself is
+            // created once the connection is established.
+            //
+            // Get a handle to the shared database.
+            let node = self.node.clone();
 
-            // Create the necessary per-connection handler state.
-            let mut handler = Handler {
-                // Get a handle to the shared database.
-                node: self.node.clone(),
+            // Register the connection for `CLIENT LIST`. New connections
+            // start on the default database, unauthenticated.
+            let client = node.clients().register(addr);
 
-                // Initialize the connection state. This allocates read/write
-                // buffers to perform redis protocol frame parsing.
-                connection: Connection::new(socket),
+            // Receive shutdown notifications, for the server or for this
+            // connection only.
+            let shutdown = Shutdown::new(self.notify_shutdown.subscribe()).with_kill(client.kill_signal());
 
-                // Receive shutdown notifications.
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
+            let session = Session::new(client, self.acl.clone());
 
-                // Notifies the receiver half once all clones are
-                // dropped.
-                _shutdown_complete: self.shutdown_complete_tx.clone(),
-            };
+            // Notifies the receiver half once all clones are dropped.
+            let shutdown_complete = self.shutdown_complete_tx.clone();
+
+            let tls = self.tls.clone();
+            let frame_limits = self.frame_limits;
+            let idle_timeout = self.idle_timeout;
 
             // Spawn a new task to process the connections. Tokio tasks are like
             // asynchronous green threads and are executed concurrently.
             tokio::spawn(async move {
+                // Initialize the connection state. This allocates read/write
+                // buffers to perform redis protocol frame parsing. The TLS
+                // handshake runs here so slow clients do not hold up `accept`.
+                let mut connection = match open_connection(socket, tls).await {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        warn!(client = %addr, cause = %err, "TLS handshake failed");
+                        return;
+                    }
+                };
+
+                connection.set_limits(frame_limits);
+
+                let mut handler = Handler {
+                    node,
+                    connection,
+                    session,
+                    idle_timeout,
+                    shutdown,
+                    _shutdown_complete: shutdown_complete,
+                };
+
                 // Process the connection. If an error is encountered, log it.
                 if let Err(err) = handler.run().await {
                     error!(cause = ?err, "connection error");
@@ -138,7 +263,7 @@ impl Listener {
         }
     }
 
-    async fn accept(&mut self) -> crate::Result<TcpStream> {
+    async fn accept(&mut self) -> crate::Result<(TcpStream, SocketAddr)> {
         let mut backoff = 1;
 
         // Try to accept a few times
@@ -146,7 +271,7 @@ impl Listener {
             // Perform the accept operation. If a socket is successfully
             // accepted, return it. Otherwise, save the error.
             match self.listener.accept().await {
-                Ok((socket, _)) => return Ok(socket),
+                Ok(accepted) => return Ok(accepted),
                 Err(err) => {
                     if backoff > 64 {
                         // Accept has failed too many times. Return the error.
@@ -164,18 +289,63 @@ impl Listener {
     }
 }
 
+/// Wraps an accepted socket in a `Connection`, performing the TLS handshake
+/// first if the listener uses TLS.
+async fn open_connection(socket: TcpStream, tls: Option<Acceptor>) -> crate::Result<Connection> {
+    let acceptor = match tls {
+        Some(acceptor) => acceptor,
+        None => return Ok(Connection::new(socket)),
+    };
+
+    match time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(socket)).await {
+        Ok(stream) => Ok(Connection::with_stream(stream?)),
+        Err(_) => Err(Error::Timeout("the TLS handshake".to_string())),
+    }
+}
+
+/// Replies `message` to a client the server does not serve, then closes the
+/// connection.
+async fn reject(socket: TcpStream, tls: Option<Acceptor>, message: &str) {
+    let mut connection = match open_connection(socket, tls).await {
+        Ok(connection) => connection,
+        Err(_) => return,
+    };
+
+    if connection.write_frame(&Frame::Error(message.to_string())).await.is_ok() {
+        let _ = connection.flush().await;
+    }
+}
+
 impl Handler {
 
+    /// Process the connection until the client disconnects or the server
+    /// shuts down.
+    ///
+    /// A client sending a frame over the limits, or idle for longer than
+    /// `idle_timeout`, gets an error reply before the connection is closed.
     #[instrument(skip(self))]
     async fn run(&mut self) -> crate::Result<()> {
+        match self.serve().await {
+            Err(err @ Error::Protocol(_)) | Err(err @ Error::Timeout(_)) => {
+                debug!(cause = %err, "closing connection");
+                self.connection.write_frame(&Frame::Error(err.to_resp())).await?;
+                self.connection.flush().await?;
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    async fn serve(&mut self) -> crate::Result<()> {
         
         while !self.shutdown.is_shutdown() {
             
             let maybe_frame = tokio::select! {
-                res = self.connection.read_frame() => res?,
+                res = read_frame(&mut self.connection, self.idle_timeout) => res?,
                 _ = self.shutdown.recv() => {
                     // If a shutdown signal is received, return from `run`.
                     // This will result in the task terminating.
+                    self.connection.flush().await?;
                     return Ok(());
                 }
             };
@@ -185,14 +355,216 @@ impl Handler {
                 None => return Ok(()),
             };
 
-            let cmd = Command::from_frame(frame)?;
+            // Keep the arguments for the slow log, parsing consumes the frame.
+            let args = self.node.slowlog().capture(&frame);
+
+            // A command that fails to parse gets an error reply. The frame was
+            // read whole, so the connection stays usable.
+            let cmd = match Command::from_frame(frame) {
+                Ok(cmd) => cmd,
+                Err(err) => {
+                    debug!(cause = %err, "invalid command");
+                    self.connection.write_frame(&Frame::Error(err.to_resp())).await?;
+                    continue;
+                }
+            };
 
             debug!(?cmd);
 
-            cmd.apply(&self.node, &mut self.connection, &mut self.shutdown)
+            cmd.apply(&self.node, &mut self.connection, &mut self.session, &mut self.shutdown, args)
                 .await?;
         }
 
+        // Send the replies still buffered when a subscriber saw the shutdown.
+        self.connection.flush().await?;
+
         Ok(())
     }
 }
+
+/// Reads the next frame, failing with `Error::Timeout` if none arrives
+/// within `idle_timeout`.
+async fn read_frame(connection: &mut Connection, idle_timeout: Option<Duration>) -> crate::Result<Option<Frame>> {
+    let idle_timeout = match idle_timeout {
+        Some(idle_timeout) => idle_timeout,
+        None => return connection.read_frame().await,
+    };
+
+    match time::timeout(idle_timeout, connection.read_frame()).await {
+        Ok(res) => res,
+        Err(_) => Err(Error::Timeout("the next command".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LimitsConfig, StorageConfig};
+    use bytes::Bytes;
+    use p2p::P2pConfig;
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::oneshot;
+    use tokio::task::JoinHandle;
+
+    /// A server running on a loopback port, stopped when dropped.
+    struct TestServer {
+        addr: SocketAddr,
+        shutdown: Option<oneshot::Sender<()>>,
+        handle: Option<JoinHandle<crate::Result<()>>>,
+    }
+
+    impl TestServer {
+        /// Starts a server with a fresh data directory under `name` and the
+        /// given `limits`.
+        async fn start(name: &str, limits: LimitsConfig) -> TestServer {
+            let data_dir = std::env::temp_dir().join("terra/tests/peer-server").join(name);
+            let _ = std::fs::remove_dir_all(&data_dir);
+
+            let config = Config {
+                data_dir: data_dir.to_string_lossy().into_owned(),
+                p2p: P2pConfig {
+                    addr: "/ip4/127.0.0.1/tcp/0".to_string(),
+                    ..Default::default()
+                },
+                storage: StorageConfig {
+                    page_max_cap: 1024 * 1024,
+                    cache_cap: 1024,
+                    slot_qty: 1024,
+                    ..Default::default()
+                },
+                limits,
+                ..Default::default()
+            };
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (shutdown, stopped) = oneshot::channel::<()>();
+            let handle = tokio::spawn(run(listener, config, stopped));
+
+            TestServer {
+                addr,
+                shutdown: Some(shutdown),
+                handle: Some(handle),
+            }
+        }
+
+        /// Connects and sends `raw` as is. Returns the connection to read
+        /// the replies from.
+        async fn send(&self, raw: &[u8]) -> Connection {
+            let mut socket = TcpStream::connect(self.addr).await.unwrap();
+            socket.write_all(raw).await.unwrap();
+            Connection::new(socket)
+        }
+
+        async fn stop(mut self) {
+            drop(self.shutdown.take());
+            self.handle.take().unwrap().await.unwrap().unwrap();
+        }
+    }
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string()))).collect())
+    }
+
+    /// Reads the next reply, which must be an error.
+    async fn read_error(connection: &mut Connection) -> String {
+        match connection.read_frame().await.unwrap() {
+            Some(Frame::Error(msg)) => msg,
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    async fn assert_closed(connection: &mut Connection) {
+        assert!(matches!(connection.read_frame().await, Ok(None) | Err(_)));
+    }
+
+    #[tokio::test]
+    async fn request_limits() {
+        let limits = LimitsConfig {
+            max_bulk_len: 16,
+            max_array_depth: 2,
+            max_array_len: 4,
+            ..Default::default()
+        };
+        let server = TestServer::start("limits", limits).await;
+
+        // The limits are enforced from the frame headers: the client is told
+        // why and disconnected.
+        let cases: [(&[u8], &str); 3] = [
+            (b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$17\r\n", "ERR Protocol error: invalid bulk length"),
+            (b"*2\r\n$4\r\nPING\r\n*1\r\n*1\r\n", "ERR Protocol error: aggregate nested too deeply"),
+            (b"*5\r\n", "ERR Protocol error: invalid aggregate length"),
+        ];
+        for (raw, expected) in cases {
+            let mut connection = server.send(raw).await;
+            assert_eq!(read_error(&mut connection).await, expected);
+            assert_closed(&mut connection).await;
+        }
+
+        // A command that fails to parse is answered and the connection stays
+        // open.
+        let mut connection = server.send(b"").await;
+        connection.write_frame(&command(&["incrby", "k", "x"])).await.unwrap();
+        assert_eq!(read_error(&mut connection).await, "ERR value is not an integer or out of range");
+        connection.write_frame(&command(&["set", "k", "0123456789abcdef"])).await.unwrap();
+        assert!(matches!(connection.read_frame().await.unwrap(), Some(frame) if frame == "OK"));
+
+        drop(connection);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn max_clients() {
+        let limits = LimitsConfig {
+            max_clients: 1,
+            ..Default::default()
+        };
+        let server = TestServer::start("max-clients", limits).await;
+
+        let mut first = server.send(b"").await;
+        first.write_frame(&command(&["ping"])).await.unwrap();
+        assert!(matches!(first.read_frame().await.unwrap(), Some(frame) if frame == "PONG"));
+
+        let mut second = server.send(b"").await;
+        assert_eq!(read_error(&mut second).await, "ERR max number of clients reached");
+        assert_closed(&mut second).await;
+
+        // The slot is free again once the first client is gone.
+        drop(first);
+        let mut third = loop {
+            let mut connection = server.send(b"").await;
+            connection.write_frame(&command(&["ping"])).await.unwrap();
+            if let Ok(Some(frame)) = connection.read_frame().await {
+                if frame == "PONG" {
+                    break connection;
+                }
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        };
+        third.write_frame(&command(&["ping"])).await.unwrap();
+        assert!(matches!(third.read_frame().await.unwrap(), Some(frame) if frame == "PONG"));
+
+        drop(third);
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let limits = LimitsConfig {
+            timeout: 1,
+            ..Default::default()
+        };
+        let server = TestServer::start("idle-timeout", limits).await;
+
+        let mut connection = server.send(b"").await;
+        connection.write_frame(&command(&["ping"])).await.unwrap();
+        assert!(matches!(connection.read_frame().await.unwrap(), Some(frame) if frame == "PONG"));
+
+        let start = time::Instant::now();
+        assert_eq!(read_error(&mut connection).await, "ERR Timed out waiting for the next command");
+        assert_closed(&mut connection).await;
+        assert!(start.elapsed() >= Duration::from_millis(900));
+
+        server.stop().await;
+    }
+}
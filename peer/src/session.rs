@@ -0,0 +1,93 @@
+use crate::acl::{Acl, User};
+use crate::node::ClientHandle;
+use crate::Command;
+
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// State kept for a single client connection between commands.
+#[derive(Debug)]
+pub(crate) struct Session {
+    /// Index of the database selected with `SELECT`.
+    db: usize,
+
+    /// Registration of the connection, listed by `CLIENT LIST`.
+    client: ClientHandle,
+
+    /// The users allowed to connect to the server.
+    acl: Arc<Acl>,
+
+    /// The user the connection authenticated as with `AUTH`.
+    user: Option<Arc<User>>,
+}
+
+impl Session {
+    /// Create the state of the new connection registered as `client`. It
+    /// starts on the default database and unauthenticated.
+    pub(crate) fn new(client: ClientHandle, acl: Arc<Acl>) -> Session {
+        Session {
+            db: 0,
+            client,
+            acl,
+            user: None,
+        }
+    }
+
+    /// Returns the index of the selected database.
+    pub(crate) fn db(&self) -> usize {
+        self.db
+    }
+
+    /// Switches the connection to database `db`.
+    pub(crate) fn select(&mut self, db: usize) {
+        self.db = db;
+        self.client.set_db(db);
+    }
+
+    /// Returns the registration of the connection.
+    pub(crate) fn client(&self) -> &ClientHandle {
+        &self.client
+    }
+
+    /// Checks that the connection may run `cmd`.
+    ///
+    /// `AUTH` and `HELLO` are always allowed. Other commands require an
+    /// authenticated user whose rules permit them, unless authentication is
+    /// disabled. On failure the error reported to the client is returned.
+    pub(crate) fn authorize(&self, cmd: &Command) -> Result<(), String> {
+        if !self.acl.is_enabled() || matches!(cmd, Command::Auth(_) | Command::Hello(_)) {
+            return Ok(());
+        }
+
+        match &self.user {
+            Some(user) => user.check(cmd),
+            None => Err("NOAUTH Authentication required.".to_string()),
+        }
+    }
+
+    /// Authenticates the connection as `username`, or as the default user if
+    /// no name is given.
+    ///
+    /// A failed attempt leaves the current user unchanged and is logged.
+    pub(crate) fn authenticate(&mut self, username: Option<&str>, password: &str) -> Result<(), String> {
+        if !self.acl.is_enabled() {
+            return Err("ERR AUTH called without any password configured for the default user. \
+                Are you sure your configuration is correct?"
+                .to_string());
+        }
+
+        let username = username.unwrap_or(crate::acl::DEFAULT_USER);
+        match self.acl.authenticate(username, password) {
+            Some(user) => {
+                info!(user = user.name(), client = %self.client.addr(), "client authenticated");
+                self.client.set_user(user.name());
+                self.user = Some(user);
+                Ok(())
+            }
+            None => {
+                warn!(user = username, client = %self.client.addr(), "authentication failed");
+                Err("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+            }
+        }
+    }
+}
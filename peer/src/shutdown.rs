@@ -1,4 +1,5 @@
-use tokio::sync::broadcast;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Notify};
 
 /// Listens for the server shutdown signal.
 ///
@@ -9,6 +10,9 @@ use tokio::sync::broadcast;
 /// The `Shutdown` struct listens for the signal and tracks that the signal has
 /// been received. Callers may query for whether the shutdown signal has been
 /// received or not.
+///
+/// A connection handler may also listen for the signal `CLIENT KILL` sends to
+/// close only that connection.
 #[derive(Debug)]
 pub(crate) struct Shutdown {
     /// `true` if the shutdown signal has been received
@@ -16,6 +20,9 @@ pub(crate) struct Shutdown {
 
     /// The receive half of the channel used to listen for shutdown.
     notify: broadcast::Receiver<()>,
+
+    /// Signalled when only this connection is closed.
+    kill: Option<Arc<Notify>>,
 }
 
 impl Shutdown {
@@ -24,9 +31,16 @@ impl Shutdown {
         Shutdown {
             is_shutdown: false,
             notify,
+            kill: None,
         }
     }
 
+    /// Also shut down when `kill` is signalled.
+    pub(crate) fn with_kill(mut self, kill: Arc<Notify>) -> Shutdown {
+        self.kill = Some(kill);
+        self
+    }
+
     /// Returns `true` if the shutdown signal has been received.
     pub(crate) fn is_shutdown(&self) -> bool {
         self.is_shutdown
@@ -41,7 +55,15 @@ impl Shutdown {
         }
 
         // Cannot receive a "lag error" as only one value is ever sent.
-        let _ = self.notify.recv().await;
+        match &self.kill {
+            Some(kill) => tokio::select! {
+                _ = self.notify.recv() => {}
+                _ = kill.notified() => {}
+            },
+            None => {
+                let _ = self.notify.recv().await;
+            }
+        }
 
         // Remember that the signal has been received.
         self.is_shutdown = true;
@@ -0,0 +1,364 @@
+use crate::config::TlsConfig;
+use crate::error::Error;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, IoSlice};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::crypto::{ring, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector, TlsStream};
+
+/// The socket a `Connection` reads and writes, either plain TCP or TLS.
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+/// TLS settings of a client connection.
+///
+/// The server certificate is verified against the CA certificates in
+/// `ca_file`. A client certificate is presented when the server requires
+/// mutual TLS.
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    /// PEM file with the CA certificates trusted to sign the server
+    /// certificate.
+    pub ca_file: PathBuf,
+    /// PEM file with the client certificate chain, used together with
+    /// `key_file`.
+    pub cert_file: Option<PathBuf>,
+    /// PEM file with the private key of the client certificate.
+    pub key_file: Option<PathBuf>,
+    /// The name the server certificate must be valid for. Defaults to the
+    /// host the client connects to.
+    pub server_name: Option<String>,
+}
+
+impl TlsOptions {
+    /// Create options trusting the CA certificates in `ca_file`.
+    pub fn new(ca_file: impl Into<PathBuf>) -> TlsOptions {
+        TlsOptions {
+            ca_file: ca_file.into(),
+            ..TlsOptions::default()
+        }
+    }
+
+    /// Connects to `addr`, given as `host:port`, and performs the TLS
+    /// handshake.
+    pub(crate) async fn connect(&self, addr: &str) -> crate::Result<Stream> {
+        let connector = self.connector()?;
+
+        let host = match &self.server_name {
+            Some(name) => name.as_str(),
+            None => host(addr),
+        };
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|_| Error::Tls(format!("invalid server name {:?}", host)))?;
+
+        let socket = TcpStream::connect(addr).await?;
+        let _ = socket.set_nodelay(true);
+
+        let stream = connector.connect(server_name, socket).await?;
+        Ok(Stream::Tls(Box::new(stream.into())))
+    }
+
+    fn connector(&self) -> crate::Result<TlsConnector> {
+        let builder = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| Error::Tls(err.to_string()))?
+            .with_root_certificates(load_roots(&self.ca_file)?);
+
+        let config = match (&self.cert_file, &self.key_file) {
+            (Some(cert_file), Some(key_file)) => builder
+                .with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)
+                .map_err(|err| Error::Tls(err.to_string()))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err(Error::Tls("client certificate and key must be set together".to_string())),
+        };
+
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+}
+
+/// Performs the server side of the TLS handshake on accepted sockets.
+#[derive(Clone)]
+pub(crate) struct Acceptor {
+    inner: TlsAcceptor,
+}
+
+impl Acceptor {
+    /// Builds the acceptor of the RESP listener from the `[tls]` settings.
+    ///
+    /// With `client_ca_file` set, clients must present a certificate signed
+    /// by one of its CA certificates.
+    pub(crate) fn new(config: &TlsConfig) -> crate::Result<Acceptor> {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| Error::Tls(err.to_string()))?;
+
+        let builder = match &config.client_ca_file {
+            Some(ca_file) => {
+                let roots = Arc::new(load_roots(Path::new(ca_file))?);
+                let verifier = WebPkiClientVerifier::builder_with_provider(roots, provider())
+                    .build()
+                    .map_err(|err| Error::Tls(err.to_string()))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let server_config = builder
+            .with_single_cert(load_certs(Path::new(&config.cert_file))?, load_key(Path::new(&config.key_file))?)
+            .map_err(|err| Error::Tls(err.to_string()))?;
+
+        Ok(Acceptor {
+            inner: TlsAcceptor::from(Arc::new(server_config)),
+        })
+    }
+
+    /// Performs the TLS handshake with the client connected on `socket`.
+    pub(crate) async fn accept(&self, socket: TcpStream) -> crate::Result<Stream> {
+        let _ = socket.set_nodelay(true);
+
+        let stream = self.inner.accept(socket).await?;
+        Ok(Stream::Tls(Box::new(stream.into())))
+    }
+}
+
+impl fmt::Debug for Acceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Acceptor").finish_non_exhaustive()
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+/// Returns the host part of `host:port`.
+fn host(addr: &str) -> &str {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+fn open(path: &Path) -> crate::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| Error::Tls(format!("failed to open {}: {}", path.display(), err)))
+}
+
+fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(|err| Error::Tls(format!("invalid certificate in {}: {}", path.display(), err)))?;
+
+    if certs.is_empty() {
+        return Err(Error::Tls(format!("no certificate in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|err| Error::Tls(format!("invalid private key in {}: {}", path.display(), err)))?
+        .ok_or_else(|| Error::Tls(format!("no private key in {}", path.display())))
+}
+
+fn load_roots(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .map_err(|err| Error::Tls(format!("invalid CA certificate in {}: {}", path.display(), err)))?;
+    }
+    Ok(roots)
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Stream::Tls(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(stream) => stream.is_write_vectored(),
+            Stream::Tls(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Stream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Connection, Frame};
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+    use std::fs;
+    use tokio::net::TcpListener;
+
+    /// Writes the certificates used by the tests to a fresh directory under
+    /// `name`: a CA signing `server` and `client`, and an unrelated CA
+    /// signing `rogue`. Returns the directory.
+    fn write_certs(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("terra/tests/peer-tls").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let ca = new_ca("peer test CA");
+        fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+        write_signed(&dir, "server", &ca, &["localhost"]);
+        write_signed(&dir, "client", &ca, &[]);
+        write_signed(&dir, "rogue", &new_ca("rogue CA"), &[]);
+
+        dir
+    }
+
+    fn new_ca(name: &str) -> Certificate {
+        let mut params = CertificateParams::new(vec![]);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.distinguished_name.push(DnType::CommonName, name);
+        Certificate::from_params(params).unwrap()
+    }
+
+    fn write_signed(dir: &Path, name: &str, ca: &Certificate, hosts: &[&str]) {
+        let mut params = CertificateParams::new(hosts.iter().map(|host| host.to_string()).collect::<Vec<_>>());
+        params.distinguished_name.push(DnType::CommonName, name);
+        let cert = Certificate::from_params(params).unwrap();
+
+        fs::write(dir.join(format!("{}.pem", name)), cert.serialize_pem_with_signer(ca).unwrap()).unwrap();
+        fs::write(dir.join(format!("{}.key", name)), cert.serialize_private_key_pem()).unwrap();
+    }
+
+    fn server_config(dir: &Path, mutual: bool) -> TlsConfig {
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+        TlsConfig {
+            cert_file: path("server.pem"),
+            key_file: path("server.key"),
+            client_ca_file: if mutual { Some(path("ca.pem")) } else { None },
+        }
+    }
+
+    /// Client options trusting the test CA, presenting the certificate
+    /// `cert` if set.
+    fn client_options(dir: &Path, cert: Option<&str>) -> TlsOptions {
+        TlsOptions {
+            ca_file: dir.join("ca.pem"),
+            cert_file: cert.map(|name| dir.join(format!("{}.pem", name))),
+            key_file: cert.map(|name| dir.join(format!("{}.key", name))),
+            server_name: None,
+        }
+    }
+
+    /// Connects with `options` to a server using `config` and sends a frame
+    /// over the established session. Returns the result of the server
+    /// handshake and of the client exchange.
+    async fn exchange(config: &TlsConfig, options: &TlsOptions) -> (crate::Result<()>, crate::Result<()>) {
+        let acceptor = Acceptor::new(config).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut connection = Connection::with_stream(acceptor.accept(socket).await?);
+            let frame = connection.read_frame().await?.ok_or("connection closed")?;
+            connection.write_frame(&frame).await?;
+            connection.flush().await?;
+            Ok(())
+        });
+
+        let client = async {
+            let stream = options.connect(&format!("localhost:{}", port)).await?;
+            let mut connection = Connection::with_stream(stream);
+            connection.write_frame(&Frame::Simple("ping".to_string())).await?;
+            match connection.read_frame().await? {
+                Some(frame) if frame == "ping" => Ok(()),
+                frame => Err(format!("unexpected reply {:?}", frame).into()),
+            }
+        };
+
+        let client = client.await;
+        (server.await.unwrap(), client)
+    }
+
+    #[tokio::test]
+    async fn handshake() {
+        let dir = write_certs("handshake");
+
+        let (server, client) = exchange(&server_config(&dir, false), &client_options(&dir, None)).await;
+        server.unwrap();
+        client.unwrap();
+
+        // A server certificate not valid for the requested name is refused.
+        let options = TlsOptions {
+            server_name: Some("example.com".to_string()),
+            ..client_options(&dir, None)
+        };
+        let (server, client) = exchange(&server_config(&dir, false), &options).await;
+        assert!(server.is_err());
+        assert!(matches!(client, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn mutual_tls() {
+        let dir = write_certs("mutual");
+        let config = server_config(&dir, true);
+
+        let (server, client) = exchange(&config, &client_options(&dir, Some("client"))).await;
+        server.unwrap();
+        client.unwrap();
+
+        // A certificate signed by another CA, or none at all, is rejected
+        // by the server.
+        for cert in [Some("rogue"), None] {
+            let (server, client) = exchange(&config, &client_options(&dir, cert)).await;
+            assert!(server.is_err(), "client certificate {:?} was accepted", cert);
+            assert!(client.is_err());
+        }
+    }
+}
@@ -30,6 +30,38 @@ pub fn unix_timestamp() -> u64 {
 //     Hash::from(output)
 // }
 
+/// Matches `key` against a glob `pattern`, where `*` matches any sequence of
+/// bytes and `?` a single byte.
+pub fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // Position after the last `*` and the key position it was tried at.
+    let mut retry = None;
+
+    while k < key.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                retry = Some((p, k));
+            }
+            Some(&c) if c == b'?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match retry {
+                // Let the last `*` swallow one more byte.
+                Some((star, at)) => {
+                    p = star;
+                    k = at + 1;
+                    retry = Some((star, at + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
 pub fn gen_random_number() -> u64 {
     thread_rng().gen::<u64>()
 }